@@ -0,0 +1,307 @@
+//! Comparison of two benchmark CSVs, to catch regressions between runs.
+//!
+//! Rows are matched by graph and layout name. Run time and every quality metric found
+//! in both files are compared as a percentage change from the baseline. A change in
+//! the worse direction beyond its threshold counts as a regression. Columns with no
+//! better direction, such as `aspect_ratio`, are reported but never fail. A baseline
+//! row missing from the current run, e.g. because the layout started failing, is a
+//! regression too unless missing rows are allowed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+/// Columns where an increase is an improvement
+const HIGHER_IS_BETTER: [&str; 2] = ["angular_resolution", "neighborhood_preservation"];
+/// Columns where neither direction is better
+const NEUTRAL: [&str; 3] = ["average_edge_length", "aspect_ratio", "node_distribution_score"];
+/// Columns that identify or describe a row rather than measure it
+const NOT_COMPARED: [&str; 8] = [
+    "timestamp", "graph_name", "layout_name", "node_count", "edge_count", "repeats",
+    "execution_time_stddev_ms", "execution_time_min_ms",
+];
+const TIME_COLUMN: &str = "execution_time_ms";
+
+/// Benchmark rows by (graph, layout), each as column -> value
+type Rows = BTreeMap<(String, String), BTreeMap<String, f64>>;
+
+/// Allowed worsening per column, in percent
+#[derive(Debug, Clone)]
+pub struct CompareThresholds {
+    pub time_percent: f64,               // For `execution_time_ms`
+    pub metric_percent: f64,             // For every quality metric
+    pub columns: HashMap<String, f64>,   // Overrides for single columns
+    pub allow_missing: bool,             // Baseline rows missing from the current run are not regressions
+}
+
+impl Default for CompareThresholds {
+    fn default() -> Self {
+        Self {
+            time_percent: 25.0,
+            metric_percent: 10.0,
+            columns: HashMap::new(),
+            allow_missing: false,
+        }
+    }
+}
+
+impl CompareThresholds {
+    /// Read `--time-threshold PCT`, `--metric-threshold PCT`, `--threshold COLUMN=PCT`
+    /// and `--allow-missing` command-line options
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut thresholds = Self::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            if flag == "--allow-missing" {
+                thresholds.allow_missing = true;
+                continue;
+            }
+            let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+            let percent = |text: &str| text.trim_end_matches('%').parse::<f64>().map_err(|_| format!("Invalid percentage for {}: {}", flag, text));
+            match flag.as_str() {
+                "--time-threshold" => thresholds.time_percent = percent(value)?,
+                "--metric-threshold" => thresholds.metric_percent = percent(value)?,
+                "--threshold" => {
+                    let (column, limit) = value.split_once('=').ok_or_else(|| format!("Expected COLUMN=PCT, found {}", value))?;
+                    thresholds.columns.insert(column.to_string(), percent(limit)?);
+                }
+                _ => return Err(format!("Unknown option: {}", flag)),
+            }
+        }
+        Ok(thresholds)
+    }
+
+    fn for_column(&self, column: &str) -> f64 {
+        match self.columns.get(column) {
+            Some(&threshold) => threshold,
+            None if column == TIME_COLUMN => self.time_percent,
+            None => self.metric_percent,
+        }
+    }
+}
+
+/// Change of one column for one graph and layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDelta {
+    pub graph_name: String,
+    pub layout_name: String,
+    pub column: String,
+    pub baseline: f64,
+    pub current: f64,
+    pub percent: f64,                    // Infinite when the baseline is zero
+    pub regression: bool,
+}
+
+/// Result of comparing two benchmark CSVs
+#[derive(Debug, Clone, Default)]
+pub struct BenchmarkComparison {
+    pub deltas: Vec<MetricDelta>,        // Every compared column of every matched row
+    pub only_in_baseline: Vec<(String, String)>,
+    pub only_in_current: Vec<(String, String)>,
+    pub allow_missing: bool,             // Whether `only_in_baseline` rows are not regressions
+}
+
+impl BenchmarkComparison {
+    pub fn regressions(&self) -> impl Iterator<Item = &MetricDelta> {
+        self.deltas.iter().filter(|delta| delta.regression)
+    }
+
+    /// Regressed columns, plus baseline rows missing from the current run unless allowed
+    pub fn regression_count(&self) -> usize {
+        let missing = if self.allow_missing { 0 } else { self.only_in_baseline.len() };
+        self.regressions().count() + missing
+    }
+
+    pub fn has_regressions(&self) -> bool {
+        self.regression_count() > 0
+    }
+
+    /// Human-readable summary listing every changed column
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        for delta in self.deltas.iter().filter(|delta| delta.percent != 0.0) {
+            out.push_str(&format!(
+                "{:<10} {} / {} {}: {} -> {} ({:+.1}%)\n",
+                if delta.regression { "REGRESSION" } else { "" },
+                delta.graph_name, delta.layout_name, delta.column, delta.baseline, delta.current, delta.percent
+            ));
+        }
+        for (graph, layout) in &self.only_in_baseline {
+            let label = if self.allow_missing { "missing" } else { "REGRESSION" };
+            out.push_str(&format!("{:<10} {} / {}: only in the baseline\n", label, graph, layout));
+        }
+        for (graph, layout) in &self.only_in_current {
+            out.push_str(&format!("new        {} / {}: only in the current run\n", graph, layout));
+        }
+        let matched: std::collections::BTreeSet<_> = self.deltas.iter().map(|delta| (&delta.graph_name, &delta.layout_name)).collect();
+        out.push_str(&format!(
+            "{} rows compared, {} regressions\n",
+            matched.len(), self.regression_count()
+        ));
+        out
+    }
+}
+
+/// Rows of a benchmark CSV; later rows for the same graph and layout win
+fn read_rows(content: &str, name: &str) -> Result<Rows, String> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader.headers().map_err(|e| format!("Failed to read {} header: {}", name, e))?.clone();
+    for required in ["graph_name", "layout_name", TIME_COLUMN] {
+        if !headers.iter().any(|header| header == required) {
+            return Err(format!("{} has no {} column", name, required));
+        }
+    }
+
+    let mut rows = BTreeMap::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Failed to read {} row: {}", name, e))?;
+        let mut key = (String::new(), String::new());
+        let mut values = BTreeMap::new();
+        for (header, field) in headers.iter().zip(record.iter()) {
+            match header {
+                "graph_name" => key.0 = field.to_string(),
+                "layout_name" => key.1 = field.to_string(),
+                _ if NOT_COMPARED.contains(&header) => {}
+                _ => {
+                    if let Ok(value) = field.trim().parse::<f64>() {
+                        values.insert(header.to_string(), value);
+                    }
+                }
+            }
+        }
+        rows.insert(key, values);
+    }
+    Ok(rows)
+}
+
+/// Compare a current benchmark CSV against a baseline, both as CSV text
+pub fn compare_benchmark_csv(baseline: &str, current: &str, thresholds: &CompareThresholds) -> Result<BenchmarkComparison, String> {
+    let baseline = read_rows(baseline, "baseline")?;
+    let current = read_rows(current, "current run")?;
+
+    let mut comparison = BenchmarkComparison { allow_missing: thresholds.allow_missing, ..BenchmarkComparison::default() };
+    for (key, base_values) in &baseline {
+        let Some(current_values) = current.get(key) else {
+            comparison.only_in_baseline.push(key.clone());
+            continue;
+        };
+        for (column, &base) in base_values {
+            let Some(&now) = current_values.get(column) else { continue };
+            let percent = if base == now {
+                0.0
+            } else if base == 0.0 {
+                f64::INFINITY * (now - base).signum()
+            } else {
+                (now - base) / base.abs() * 100.0
+            };
+            let worsening = if HIGHER_IS_BETTER.contains(&column.as_str()) { -percent } else { percent };
+            comparison.deltas.push(MetricDelta {
+                graph_name: key.0.clone(),
+                layout_name: key.1.clone(),
+                column: column.clone(),
+                baseline: base,
+                current: now,
+                percent,
+                regression: !NEUTRAL.contains(&column.as_str()) && worsening > thresholds.for_column(column),
+            });
+        }
+    }
+    comparison.only_in_current = current.keys().filter(|key| !baseline.contains_key(*key)).cloned().collect();
+    Ok(comparison)
+}
+
+/// Compare two benchmark CSV files written by `run_benchmarks`
+pub fn compare_benchmark_files(baseline_path: &str, current_path: &str, thresholds: &CompareThresholds) -> Result<BenchmarkComparison, String> {
+    let read = |path: &str| fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+    compare_benchmark_csv(&read(baseline_path)?, &read(current_path)?, thresholds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::BenchmarkResult;
+
+    const BASELINE: &str = "\
+timestamp,graph_name,layout_name,node_count,edge_count,repeats,execution_time_ms,edge_crossings,stress,neighborhood_preservation,aspect_ratio
+t,a.json,fcose,10,9,3,100.00,4,0.2000,0.5000,1.0000
+t,a.json,\"dagre[x=1;y=2]\",10,9,3,10.00,0,0.1000,0.4000,1.0000
+t,b.json,fcose,5,4,3,10.00,0,0.1000,0.4000,1.0000
+";
+
+    #[test]
+    fn test_matches_rows_and_flags_regressions() {
+        let current = "\
+timestamp,graph_name,layout_name,node_count,edge_count,repeats,execution_time_ms,edge_crossings,stress,neighborhood_preservation,aspect_ratio
+u,a.json,fcose,10,9,3,120.00,3,0.2000,0.4000,3.0000
+u,a.json,\"dagre[x=1;y=2]\",10,9,3,10.50,1,0.1000,0.4000,1.0000
+u,c.json,fcose,5,4,3,10.00,0,0.1000,0.4000,1.0000
+";
+        let comparison = compare_benchmark_csv(BASELINE, current, &CompareThresholds::default()).unwrap();
+        let regressions: Vec<(&str, &str)> = comparison.regressions().map(|delta| (delta.layout_name.as_str(), delta.column.as_str())).collect();
+
+        // Time +20% is within 25%, fewer crossings is better, and aspect ratio has no
+        // better direction; lower neighbourhood preservation and new crossings are worse
+        assert_eq!(regressions, vec![("dagre[x=1;y=2]", "edge_crossings"), ("fcose", "neighborhood_preservation")]);
+        let time = comparison.deltas.iter().find(|delta| delta.layout_name == "fcose" && delta.column == TIME_COLUMN).unwrap();
+        assert!((time.percent - 20.0).abs() < 1e-9);
+        assert_eq!(comparison.only_in_baseline, vec![("b.json".to_string(), "fcose".to_string())]);
+        assert_eq!(comparison.only_in_current, vec![("c.json".to_string(), "fcose".to_string())]);
+        // b.json / fcose is missing from the current run, which counts unless allowed
+        assert_eq!(comparison.regression_count(), 3);
+        assert!(comparison.report().contains("REGRESSION b.json / fcose: only in the baseline"));
+        assert!(comparison.report().contains("2 rows compared, 3 regressions"));
+        let allowed = CompareThresholds { allow_missing: true, ..CompareThresholds::default() };
+        let comparison = compare_benchmark_csv(BASELINE, current, &allowed).unwrap();
+        assert_eq!(comparison.regression_count(), 2);
+        assert!(comparison.report().contains("missing    b.json / fcose: only in the baseline"));
+
+        // Tighter time threshold, and a looser one for crossings
+        let thresholds = CompareThresholds {
+            time_percent: 10.0,
+            columns: HashMap::from([("edge_crossings".to_string(), f64::INFINITY), ("neighborhood_preservation".to_string(), 50.0)]),
+            ..CompareThresholds::default()
+        };
+        let comparison = compare_benchmark_csv(BASELINE, current, &thresholds).unwrap();
+        let regressions: Vec<&str> = comparison.regressions().map(|delta| delta.column.as_str()).collect();
+        assert_eq!(regressions, vec![TIME_COLUMN]);
+        assert_eq!(comparison.regression_count(), 2);
+    }
+
+    #[test]
+    fn test_reads_rows_written_by_the_benchmark() {
+        let result = BenchmarkResult {
+            graph_name: "g,1.json".to_string(),
+            node_count: 3,
+            edge_count: 2,
+            layout_name: "fcose[theta=0.5]".to_string(),
+            repeats: 1,
+            execution_time_ms: 5.0,
+            execution_time_stddev_ms: 0.0,
+            execution_time_min_ms: 5.0,
+            node_distribution_score: 1.0,
+            metrics: Default::default(),
+            #[cfg(feature = "cli")]
+            timestamp: String::new(),
+        };
+        let csv = format!("{}{}", BenchmarkResult::to_csv_header(), result.to_csv_row());
+        let comparison = compare_benchmark_csv(&csv, &csv, &CompareThresholds::default()).unwrap();
+
+        assert!(!comparison.has_regressions());
+        assert!(comparison.deltas.iter().all(|delta| delta.graph_name == "g,1.json" && delta.percent == 0.0));
+        assert!(comparison.deltas.iter().any(|delta| delta.column == "stress"));
+        assert!(compare_benchmark_csv("a,b\n1,2\n", &csv, &CompareThresholds::default()).is_err());
+    }
+
+    #[test]
+    fn test_thresholds_from_args() {
+        let args: Vec<String> = ["--time-threshold", "5", "--allow-missing", "--threshold", "stress=2.5%", "--metric-threshold", "1"]
+            .iter().map(|arg| arg.to_string()).collect();
+        let thresholds = CompareThresholds::from_args(&args).unwrap();
+        assert_eq!((thresholds.time_percent, thresholds.metric_percent), (5.0, 1.0));
+        assert_eq!(thresholds.for_column("stress"), 2.5);
+        assert_eq!(thresholds.for_column("edge_crossings"), 1.0);
+        assert!(thresholds.allow_missing && !CompareThresholds::default().allow_missing);
+
+        assert!(CompareThresholds::from_args(&["--time-threshold".to_string()]).is_err());
+        assert!(CompareThresholds::from_args(&["--bogus".to_string(), "1".to_string()]).is_err());
+    }
+}
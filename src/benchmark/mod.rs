@@ -1,57 +1,79 @@
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::Path;
 #[cfg(feature = "cli")]
 use chrono::Utc;
-use serde_json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::types::{Graph, GraphFile};
-use crate::layout::algorithms::fcose::{FcoseOptions, apply_layout};
+use crate::types::{Graph, LayoutAlgorithm};
+use crate::layout::{apply_layout, available_layouts};
+use crate::file_parsers::parse_graph_file;
+use crate::metrics::{compute_metrics, LayoutMetrics};
+
+mod compare;
+pub use compare::{compare_benchmark_csv, compare_benchmark_files, BenchmarkComparison, CompareThresholds, MetricDelta};
+
+/// File extensions of the graph formats `parse_graph_file` reads
+const GRAPH_EXTENSIONS: [&str; 5] = ["json", "csv", "dot", "graphml", "gexf"];
 
 pub struct BenchmarkResult {
     pub graph_name: String,
     pub node_count: usize,
     pub edge_count: usize,
     pub layout_name: String,
-    pub execution_time_ms: f64,
-    pub average_edge_length: f64,
+    pub repeats: usize,
+    pub execution_time_ms: f64,          // Mean over the repeats
+    pub execution_time_stddev_ms: f64,
+    pub execution_time_min_ms: f64,
     pub node_distribution_score: f64,
+    pub metrics: LayoutMetrics,          // Mean over the repeats
     #[cfg(feature = "cli")]
     pub timestamp: String,
 }
 
-impl BenchmarkResult {
-    pub fn to_csv_header() -> String {
-        "timestamp,graph_name,layout_name,node_count,edge_count,execution_time_ms,average_edge_length,node_distribution_score\n".to_string()
+/// Quote a CSV field if it contains a separator or a quote
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
     }
+}
 
-    #[cfg(feature = "cli")]
-    pub fn to_csv_row(&self) -> String {
+impl BenchmarkResult {
+    pub fn to_csv_header() -> String {
+        let metrics: Vec<&str> = LayoutMetrics::default().values().into_iter().map(|(name, _)| name).collect();
         format!(
-            "{},{},{},{},{},{:.2},{:.2},{:.2}\n",
-            self.timestamp,
-            self.graph_name,
-            self.layout_name,
-            self.node_count,
-            self.edge_count,
-            self.execution_time_ms,
-            self.average_edge_length,
-            self.node_distribution_score
+            "timestamp,graph_name,layout_name,node_count,edge_count,repeats,execution_time_ms,execution_time_stddev_ms,execution_time_min_ms,node_distribution_score,{}\n",
+            metrics.join(",")
         )
     }
 
-    #[cfg(not(feature = "cli"))]
     pub fn to_csv_row(&self) -> String {
+        #[cfg(feature = "cli")]
+        let timestamp = self.timestamp.as_str();
+        #[cfg(not(feature = "cli"))]
+        let timestamp = "";
+
+        // Counts are written as whole numbers
+        let metrics: Vec<String> = self.metrics.values().into_iter()
+            .map(|(_, value)| if value.fract() == 0.0 { value.to_string() } else { format!("{:.4}", value) })
+            .collect();
         format!(
-            "{},{},{},{},{},{:.2},{:.2},{:.2}\n",
-            "",
-            self.graph_name,
-            self.layout_name,
+            "{},{},{},{},{},{},{:.2},{:.2},{:.2},{:.2},{}\n",
+            timestamp,
+            csv_field(&self.graph_name),
+            csv_field(&self.layout_name),
             self.node_count,
             self.edge_count,
+            self.repeats,
             self.execution_time_ms,
-            self.average_edge_length,
-            self.node_distribution_score
+            self.execution_time_stddev_ms,
+            self.execution_time_min_ms,
+            self.node_distribution_score,
+            metrics.join(",")
         )
     }
 }
@@ -81,7 +103,11 @@ pub fn calculate_metrics(graph: &Graph) -> (f64, f64) {
     // Calculate node distribution score (standard deviation of distances to center)
     let mut center_x = 0.0;
     let mut center_y = 0.0;
-    let node_count = graph.nodes.len() as f64;
+    let positioned = graph.nodes.values().filter(|node| node.position.is_some()).count();
+    if positioned == 0 {
+        return (average_edge_length, 0.0);
+    }
+    let node_count = positioned as f64;
 
     for node in graph.nodes.values() {
         if let Some(pos) = node.position {
@@ -105,60 +131,221 @@ pub fn calculate_metrics(graph: &Graph) -> (f64, f64) {
     (average_edge_length, node_distribution_score)
 }
 
-pub fn run_benchmark(graph_path: &str) -> Result<BenchmarkResult, String> {
-    // Load graph from JSON file
-    let graph_content = fs::read_to_string(graph_path)
+/// One layout to benchmark, with options to sweep over
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LayoutSweep {
+    /// Name in the results; the layout's `name` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Values to try per option (`base.seed` for nested options); every combination is run
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub grid: BTreeMap<String, Vec<Value>>,
+    /// The layout's `name` and options held fixed, as in `LayoutAlgorithm` JSON
+    #[serde(flatten)]
+    pub options: Map<String, Value>,
+}
+
+impl LayoutSweep {
+    /// A sweep of a single layout with its default options
+    pub fn new(name: &str) -> Self {
+        let mut options = Map::new();
+        options.insert("name".to_string(), Value::from(name));
+        LayoutSweep { label: None, grid: BTreeMap::new(), options }
+    }
+
+    /// Every combination of the grid, as a labelled layout
+    pub fn variants(&self) -> Result<Vec<(String, LayoutAlgorithm)>, String> {
+        let mut combinations: Vec<Vec<(&String, &Value)>> = vec![Vec::new()];
+        for (path, values) in &self.grid {
+            combinations = combinations.into_iter()
+                .flat_map(|combination| values.iter().map(move |value| {
+                    let mut extended = combination.clone();
+                    extended.push((path, value));
+                    extended
+                }))
+                .collect();
+        }
+
+        let name = self.options.get("name").and_then(Value::as_str).unwrap_or("layout");
+        let label = self.label.as_deref().unwrap_or(name);
+        combinations.into_iter().map(|combination| {
+            let mut options = Value::Object(self.options.clone());
+            for (path, value) in &combination {
+                set_option(&mut options, path, (*value).clone());
+            }
+            let layout: LayoutAlgorithm = serde_json::from_value(options)
+                .map_err(|e| format!("Invalid options for {}: {}", label, e))?;
+            if combination.is_empty() {
+                return Ok((label.to_string(), layout));
+            }
+            // Semicolons keep the label a single CSV field
+            let settings: Vec<String> = combination.iter()
+                .map(|(path, value)| format!("{}={}", path, value.as_str().map_or_else(|| value.to_string(), str::to_string)))
+                .collect();
+            Ok((format!("{}[{}]", label, settings.join(";")), layout))
+        }).collect()
+    }
+}
+
+/// Set an option by a dotted path such as `base.seed`, creating objects on the way
+fn set_option(options: &mut Value, path: &str, value: Value) {
+    let mut current = options;
+    for key in path.split('.') {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current.as_object_mut().unwrap().entry(key.to_string()).or_insert(Value::Null);
+    }
+    *current = value;
+}
+
+/// Benchmark configuration, read from JSON or TOML
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BenchmarkConfig {
+    pub graphs: Vec<String>,             // Graph files, or directories to take every graph file from
+    pub repeats: usize,                  // Runs per graph and variant
+    pub layouts: Vec<LayoutSweep>,
+}
+
+impl Default for BenchmarkConfig {
+    /// Every registered layout with its defaults on the sample graphs, plus fCoSE with
+    /// exact repulsion as a baseline for the Barnes-Hut speedup
+    fn default() -> Self {
+        let mut layouts: Vec<LayoutSweep> = available_layouts().iter().map(|layout| LayoutSweep::new(layout.id())).collect();
+        let mut exact = LayoutSweep::new("fcose");
+        exact.label = Some("fcose-exact".to_string());
+        exact.options.insert("theta".to_string(), Value::from(0.0));
+        layouts.push(exact);
+        Self {
+            graphs: vec!["docs/sample".to_string()],
+            repeats: 3,
+            layouts,
+        }
+    }
+}
+
+/// Read a benchmark configuration, as TOML if the file ends in `.toml` and JSON otherwise
+pub fn load_benchmark_config(path: &str) -> Result<BenchmarkConfig, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read benchmark config: {}", e))?;
+    if Path::new(path).extension().and_then(|s| s.to_str()) == Some("toml") {
+        toml::from_str(&content).map_err(|e| format!("Failed to parse benchmark config: {}", e))
+    } else {
+        serde_json::from_str(&content).map_err(|e| format!("Failed to parse benchmark config: {}", e))
+    }
+}
+
+/// Load a graph file in the format given by its extension
+fn load_graph(graph_path: &str) -> Result<Graph, String> {
+    let content = fs::read_to_string(graph_path)
         .map_err(|e| format!("Failed to read graph file: {}", e))?;
-    
-    // Parse as GraphFile first, then convert to Graph
-    let graph_file: GraphFile = serde_json::from_str(&graph_content)
-        .map_err(|e| format!("Failed to parse graph JSON: {}", e))?;
-    let mut graph: Graph = graph_file.into();
+    let extension = Path::new(graph_path).extension().and_then(|s| s.to_str()).unwrap_or("json");
+    parse_graph_file(&content, extension)
+}
 
-    let graph_name = Path::new(graph_path)
+/// Graph files named by the configuration, with directories expanded in name order
+fn graph_files(config: &BenchmarkConfig) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for path in &config.graphs {
+        if !Path::new(path).is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut found = Vec::new();
+        for entry in fs::read_dir(path).map_err(|e| format!("Failed to read sample directory: {}", e))? {
+            let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+            let file = entry.path();
+            let extension = file.extension().and_then(|s| s.to_str()).unwrap_or("");
+            if GRAPH_EXTENSIONS.contains(&extension) {
+                found.push(file.to_string_lossy().into_owned());
+            }
+        }
+        found.sort();
+        files.extend(found);
+    }
+    Ok(files)
+}
+
+/// A graph and layout variant that could not be benchmarked
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkFailure {
+    pub graph_name: String,
+    pub layout_name: String,
+    pub error: String,
+}
+
+/// File name a graph's results are recorded under
+fn graph_name(graph_path: &str) -> String {
+    Path::new(graph_path)
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("unknown")
-        .to_string();
+        .to_string()
+}
 
-    // Run layout with default options
-    let options = FcoseOptions::default();
-    let start_time = std::time::Instant::now();
-    apply_layout(&mut graph, &options)?;
-    let execution_time = start_time.elapsed();
+/// Benchmark one layout on one graph file, recorded under `layout_name`: time `repeats`
+/// runs and average their quality metrics
+pub fn run_benchmark(graph_path: &str, layout_name: &str, layout: &LayoutAlgorithm, repeats: usize) -> Result<BenchmarkResult, String> {
+    let graph = load_graph(graph_path)?;
+    let graph_name = graph_name(graph_path);
 
-    // Calculate metrics
-    let (average_edge_length, node_distribution_score) = calculate_metrics(&graph);
+    let mut times = Vec::new();
+    let mut runs = Vec::new();
+    let mut distribution = 0.0;
+    for _ in 0..repeats.max(1) {
+        let mut laid_out = graph.clone();
+        let start_time = std::time::Instant::now();
+        apply_layout(&mut laid_out, layout)?;
+        times.push(start_time.elapsed().as_secs_f64() * 1000.0);
+        runs.push(compute_metrics(&laid_out));
+        distribution += calculate_metrics(&laid_out).1;
+    }
+
+    // Sample standard deviation; zero for a single run
+    let n = times.len() as f64;
+    let mean = times.iter().sum::<f64>() / n;
+    let stddev = if times.len() > 1 {
+        (times.iter().map(|time| (time - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+    } else {
+        0.0
+    };
 
     Ok(BenchmarkResult {
         graph_name,
         node_count: graph.nodes.len(),
         edge_count: graph.edges.len(),
-        layout_name: "fcose".to_string(),
-        execution_time_ms: execution_time.as_secs_f64() * 1000.0,
-        average_edge_length,
-        node_distribution_score,
+        layout_name: layout_name.to_string(),
+        repeats: times.len(),
+        execution_time_ms: mean,
+        execution_time_stddev_ms: stddev,
+        execution_time_min_ms: times.iter().copied().fold(f64::INFINITY, f64::min),
+        node_distribution_score: distribution / n,
+        metrics: LayoutMetrics::mean(&runs),
         #[cfg(feature = "cli")]
         timestamp: Utc::now().to_rfc3339(),
     })
 }
 
-pub fn run_all_benchmarks(output_path: &str) -> Result<(), String> {
-    let sample_dir = "docs/sample";
-    let mut results = Vec::new();
-
-    // Collect all JSON files from sample directory
-    let entries = fs::read_dir(sample_dir)
-        .map_err(|e| format!("Failed to read sample directory: {}", e))?;
+/// Run every layout variant of the configuration on every graph and write the results
+/// as CSV. Runs that fail are left out of the CSV and returned instead.
+pub fn run_benchmarks(config: &BenchmarkConfig, output_path: &str) -> Result<Vec<BenchmarkFailure>, String> {
+    let mut variants = Vec::new();
+    for sweep in &config.layouts {
+        variants.extend(sweep.variants()?);
+    }
 
-    for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
-        let path = entry.path();
-        
-        if path.extension().and_then(|s| s.to_str()) == Some("json") {
-            match run_benchmark(path.to_str().unwrap()) {
+    let mut results = Vec::new();
+    let mut failures = Vec::new();
+    for path in graph_files(config)? {
+        for (layout_name, layout) in &variants {
+            match run_benchmark(&path, layout_name, layout, config.repeats) {
                 Ok(result) => results.push(result),
-                Err(e) => eprintln!("Failed to benchmark {}: {}", path.display(), e),
+                Err(error) => failures.push(BenchmarkFailure {
+                    graph_name: graph_name(&path),
+                    layout_name: layout_name.clone(),
+                    error,
+                }),
             }
         }
     }
@@ -177,7 +364,12 @@ pub fn run_all_benchmarks(output_path: &str) -> Result<(), String> {
             .map_err(|e| format!("Failed to write result row: {}", e))?;
     }
 
-    Ok(())
+    Ok(failures)
+}
+
+/// Benchmark every layout on the sample graphs with the default configuration
+pub fn run_all_benchmarks(output_path: &str) -> Result<Vec<BenchmarkFailure>, String> {
+    run_benchmarks(&BenchmarkConfig::default(), output_path)
 }
 
 #[cfg(test)]
@@ -213,4 +405,100 @@ mod tests {
         // Distribution score should be 50.0 (standard deviation from center)
         assert!((distribution_score - 50.0).abs() < 0.001);
     }
+
+    #[test]
+    fn test_metrics_of_empty_graph() {
+        assert_eq!(calculate_metrics(&Graph::new()), (0.0, 0.0));
+
+        // Unpositioned nodes do not count towards the distribution
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("1"));
+        assert_eq!(calculate_metrics(&graph), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_grid_expands_to_every_combination() {
+        let config: BenchmarkConfig = toml::from_str(r#"
+            repeats = 2
+            graphs = ["docs/sample/dagre_layout.json"]
+
+            [[layouts]]
+            name = "dagre"
+            rank_direction = "LR"
+            grid = { ranker = ["longest-path", "tight-tree"], "base.edge_routing" = ["POLYLINE", "ORTHOGONAL"] }
+
+            [[layouts]]
+            name = "concentric"
+            label = "rings"
+        "#).unwrap();
+
+        let dagre = config.layouts[0].variants().unwrap();
+        assert_eq!(dagre.len(), 4);
+        assert_eq!(dagre[0].0, "dagre[base.edge_routing=POLYLINE;ranker=longest-path]");
+        match &dagre[0].1 {
+            LayoutAlgorithm::Dagre(options) => {
+                assert_eq!(options.rank_direction, "LR");
+                assert_eq!(options.ranker, "longest-path");
+                assert_eq!(options.base.edge_routing.as_deref(), Some("POLYLINE"));
+            }
+            other => panic!("expected dagre, got {}", other.id()),
+        }
+        assert_eq!(config.layouts[1].variants().unwrap()[0].0, "rings");
+
+        let invalid: LayoutSweep = serde_json::from_str(r#"{"name": "dagre", "grid": {"ranker": [3]}}"#).unwrap();
+        assert!(invalid.variants().is_err());
+    }
+
+    #[test]
+    fn test_benchmark_repeats_and_csv_columns() {
+        let path = format!("{}/docs/sample/dagre_layout.json", env!("CARGO_MANIFEST_DIR"));
+        let layout = LayoutSweep::new("dagre").variants().unwrap().remove(0).1;
+        let result = run_benchmark(&path, "dagre[a=1;b=2]", &layout, 3).unwrap();
+
+        assert_eq!(result.repeats, 3);
+        assert!(result.execution_time_min_ms <= result.execution_time_ms);
+        assert!(result.metrics.average_edge_length > 0.0);
+
+        let header = BenchmarkResult::to_csv_header();
+        let row = result.to_csv_row();
+        assert_eq!(header.split(',').count(), row.split(',').count());
+        assert!(row.contains(",dagre[a=1;b=2],"));
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn test_default_config_covers_every_layout() {
+        let config = BenchmarkConfig::default();
+        let labels: Vec<String> = config.layouts.iter().flat_map(|sweep| sweep.variants().unwrap()).map(|(label, _)| label).collect();
+        for layout in available_layouts() {
+            assert!(labels.iter().any(|label| label == layout.id()), "{}", layout.id());
+        }
+        assert!(labels.iter().any(|label| label == "fcose-exact"));
+    }
+
+    #[test]
+    fn test_failed_runs_are_returned() {
+        let dir = std::env::temp_dir().join(format!("rust-graph-layouts-benchmark-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("broken.json"), "{").unwrap();
+        let output = dir.join("out.csv");
+        let config = BenchmarkConfig {
+            graphs: vec![
+                format!("{}/docs/sample/dagre_layout.json", env!("CARGO_MANIFEST_DIR")),
+                dir.join("broken.json").to_str().unwrap().to_string(),
+            ],
+            repeats: 1,
+            layouts: vec![LayoutSweep::new("dagre")],
+        };
+
+        let failures = run_benchmarks(&config, output.to_str().unwrap()).unwrap();
+        let csv = fs::read_to_string(&output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(failures.len(), 1);
+        assert_eq!((failures[0].graph_name.as_str(), failures[0].layout_name.as_str()), ("broken.json", "dagre"));
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.contains(",dagre_layout.json,dagre,"));
+    }
 }
+
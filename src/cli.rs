@@ -0,0 +1,379 @@
+//! Command-line interface of the `rust-graph-layouts` binary.
+//!
+//! Graph formats are picked from file extensions unless `--from`/`--to` say otherwise,
+//! and `-` reads standard input or writes standard output (as JSON by default).
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use serde_json::{Map, Value};
+use crate::benchmark::{compare_benchmark_files, load_benchmark_config, run_benchmarks, BenchmarkConfig, CompareThresholds};
+use crate::file_parsers::{parse_graph_file, write_graph_file};
+use crate::layout::{apply_layout, layout_catalog};
+use crate::stats::compute_graph_stats;
+use crate::types::{Graph, LayoutAlgorithm};
+
+const USAGE: &str = "\
+Usage: rust-graph-layouts <command> [options]
+
+Commands:
+  layout      Lay out a graph and write it with positions
+  convert     Convert a graph between file formats
+  info        Print node and edge counts, components, degrees and cycles
+  benchmark   Time layouts on graph files and write a CSV
+  compare     Compare a benchmark CSV against a baseline
+  help        Show help for a command
+
+Graph formats: json, csv, dot, graphml, gexf (and svg for output).
+Run `rust-graph-layouts help <command>` for a command's options.
+";
+
+const LAYOUT_HELP: &str = "\
+Usage: rust-graph-layouts layout <input> [options]
+
+Options:
+  -a, --algorithm NAME   Layout to run (overrides a `name` in the options)
+      --options JSON     Layout options as inline JSON, or a .json or .toml file
+  -o, --output FILE      Where to write the graph (default: standard output)
+      --from FORMAT      Input format (default: from the input extension)
+      --to FORMAT        Output format (default: from the output extension, or json)
+
+Algorithms:
+";
+
+const CONVERT_HELP: &str = "\
+Usage: rust-graph-layouts convert <input> <output> [options]
+
+Options:
+      --from FORMAT      Input format (default: from the input extension)
+      --to FORMAT        Output format (default: from the output extension)
+";
+
+const INFO_HELP: &str = "\
+Usage: rust-graph-layouts info <input> [options]
+
+Options:
+      --from FORMAT      Input format (default: from the input extension)
+      --json             Print the statistics as JSON
+";
+
+const BENCHMARK_HELP: &str = "\
+Usage: rust-graph-layouts benchmark <output_csv> [config.toml|config.json]
+
+Without a config, every layout runs with its defaults on docs/sample.
+";
+
+const COMPARE_HELP: &str = "\
+Usage: rust-graph-layouts compare <baseline_csv> <current_csv> [options]
+
+Options:
+      --time-threshold PCT     Allowed run time increase (default: 25)
+      --metric-threshold PCT   Allowed worsening of quality metrics (default: 10)
+      --threshold COLUMN=PCT   Override the threshold of one column (repeatable)
+      --allow-missing          Do not fail on baseline rows missing from the current run
+
+Exits with an error when any column got worse by more than its threshold, or when
+a baseline row is missing from the current run.
+";
+
+/// An option a command accepts: long name, short name, and whether it takes a value
+type OptionSpec = (&'static str, Option<&'static str>, bool);
+
+/// A command's positional arguments, option values and switches
+#[derive(Debug, Default)]
+struct Arguments {
+    positional: Vec<String>,
+    values: HashMap<&'static str, String>,
+    switches: HashSet<&'static str>,
+}
+
+impl Arguments {
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// Split arguments into positionals and the options of `specs`; `--` ends the options
+fn parse_arguments(args: &[String], specs: &[OptionSpec]) -> Result<Arguments, String> {
+    let mut parsed = Arguments::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.positional.extend(args.cloned());
+            break;
+        }
+        if arg == "-" || !arg.starts_with('-') {
+            parsed.positional.push(arg.clone());
+            continue;
+        }
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let &(name, _, takes_value) = specs.iter()
+            .find(|(long, short, _)| flag.strip_prefix("--") == Some(*long) || flag.strip_prefix('-') == *short)
+            .ok_or_else(|| format!("Unknown option: {}", flag))?;
+        if takes_value {
+            let value = inline.or_else(|| args.next().cloned()).ok_or_else(|| format!("{} needs a value", flag))?;
+            parsed.values.insert(name, value);
+        } else if inline.is_some() {
+            return Err(format!("{} takes no value", flag));
+        } else {
+            parsed.switches.insert(name);
+        }
+    }
+    Ok(parsed)
+}
+
+/// Format named by `--from`/`--to`, or else by the file extension
+fn format_of(path: &str, explicit: Option<&str>) -> Option<String> {
+    explicit
+        .or_else(|| Path::new(path).extension().and_then(|s| s.to_str()))
+        .map(str::to_lowercase)
+}
+
+fn read_graph(path: &str, from: Option<&str>) -> Result<Graph, String> {
+    let content = if path == "-" {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content).map_err(|e| format!("Failed to read standard input: {}", e))?;
+        content
+    } else {
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?
+    };
+    let format = format_of(path, from).filter(|_| path != "-" || from.is_some()).unwrap_or_else(|| "json".to_string());
+    parse_graph_file(&content, &format)
+}
+
+/// Write a graph to a file, or to `out` when the path is `-` or missing
+fn write_graph(graph: &Graph, path: Option<&str>, to: Option<&str>, out: &mut dyn Write) -> Result<(), String> {
+    let path = path.filter(|&path| path != "-");
+    let format = match path {
+        Some(path) => format_of(path, to),
+        None => to.map(str::to_lowercase),
+    };
+    let content = write_graph_file(graph, format.as_deref().unwrap_or("json"))?;
+    match path {
+        Some(path) => fs::write(path, content).map_err(|e| format!("Failed to write {}: {}", path, e)),
+        None => out.write_all(content.as_bytes()).map_err(|e| format!("Failed to write output: {}", e)),
+    }
+}
+
+/// Build a layout from `--algorithm` and `--options` (inline JSON, or a JSON or TOML file)
+fn layout_algorithm(algorithm: Option<&str>, options: Option<&str>) -> Result<LayoutAlgorithm, String> {
+    let mut value = match options {
+        None => Value::Object(Map::new()),
+        Some(path) if Path::new(path).is_file() => {
+            let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            if format_of(path, None).as_deref() == Some("toml") {
+                toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?
+            } else {
+                serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path, e))?
+            }
+        }
+        Some(json) => serde_json::from_str(json).map_err(|e| format!("Failed to parse layout options: {}", e))?,
+    };
+    let object = value.as_object_mut().ok_or("Layout options must be an object")?;
+    if let Some(name) = algorithm {
+        object.insert("name".to_string(), Value::from(name));
+    }
+    if !object.contains_key("name") {
+        let names: Vec<&str> = layout_catalog().iter().map(|info| info.id).collect();
+        return Err(format!("No layout algorithm given; pass --algorithm with one of: {}", names.join(", ")));
+    }
+    serde_json::from_value(value).map_err(|e| format!("Invalid layout options: {}", e))
+}
+
+fn layout_command(args: &[String], out: &mut dyn Write) -> Result<(), String> {
+    let args = parse_arguments(args, &[
+        ("algorithm", Some("a"), true), ("options", None, true), ("output", Some("o"), true), ("from", None, true), ("to", None, true),
+    ])?;
+    let [input] = args.positional.as_slice() else {
+        return Err("layout takes one input file".to_string());
+    };
+    let layout = layout_algorithm(args.value("algorithm"), args.value("options"))?;
+    let mut graph = read_graph(input, args.value("from"))?;
+    apply_layout(&mut graph, &layout)?;
+    write_graph(&graph, args.value("output"), args.value("to"), out)
+}
+
+fn convert_command(args: &[String], out: &mut dyn Write) -> Result<(), String> {
+    let args = parse_arguments(args, &[("from", None, true), ("to", None, true)])?;
+    let [input, output] = args.positional.as_slice() else {
+        return Err("convert takes an input and an output file".to_string());
+    };
+    let graph = read_graph(input, args.value("from"))?;
+    write_graph(&graph, Some(output), args.value("to"), out)
+}
+
+fn info_command(args: &[String], out: &mut dyn Write) -> Result<(), String> {
+    let args = parse_arguments(args, &[("from", None, true), ("json", None, false)])?;
+    let [input] = args.positional.as_slice() else {
+        return Err("info takes one input file".to_string());
+    };
+    let stats = compute_graph_stats(&read_graph(input, args.value("from"))?);
+
+    let text = if args.switches.contains("json") {
+        serde_json::to_string_pretty(&stats).map_err(|e| format!("Failed to serialize statistics: {}", e))? + "\n"
+    } else {
+        let plural = |count: usize, word: &str| format!("{} {}{}", count, word, if count == 1 { "" } else { "s" });
+        let cycles = if stats.is_acyclic {
+            "none".to_string()
+        } else {
+            let mut cycle = stats.cycle.clone();
+            cycle.extend(stats.cycle.first().cloned());
+            format!("{}, {}, e.g. {}", plural(stats.back_edges, "back edge"), plural(stats.self_loops, "self-loop"), cycle.join(" -> "))
+        };
+        format!(
+            "nodes       {} ({} compound, {} positioned)\n\
+             edges       {} ({} parallel)\n\
+             components  {} (largest has {}, {} isolated)\n\
+             degree      min {}, max {}, mean {:.2} (max in {}, max out {})\n\
+             cycles      {}\n",
+            stats.node_count, stats.compound_count, stats.positioned_count,
+            stats.edge_count, stats.parallel_edges,
+            stats.components, plural(stats.largest_component, "node"), stats.isolated_nodes,
+            stats.min_degree, stats.max_degree, stats.mean_degree, stats.max_in_degree, stats.max_out_degree,
+            cycles
+        )
+    };
+    out.write_all(text.as_bytes()).map_err(|e| format!("Failed to write output: {}", e))
+}
+
+fn benchmark_command(args: &[String], out: &mut dyn Write) -> Result<(), String> {
+    let args = parse_arguments(args, &[])?;
+    let (output, config) = match args.positional.as_slice() {
+        [output] => (output, BenchmarkConfig::default()),
+        [output, config] => (output, load_benchmark_config(config)?),
+        _ => return Err("benchmark takes an output CSV and an optional config".to_string()),
+    };
+    let failures = run_benchmarks(&config, output)?;
+    let write_error = |e: std::io::Error| format!("Failed to write output: {}", e);
+    for failure in &failures {
+        writeln!(out, "FAILED {} / {}: {}", failure.graph_name, failure.layout_name, failure.error).map_err(write_error)?;
+    }
+    // Failed runs have no row in the CSV, so fail the command rather than hide them
+    match failures.len() {
+        0 => writeln!(out, "Benchmarks completed successfully").map_err(write_error),
+        count => Err(format!("{} benchmark runs failed", count)),
+    }
+}
+
+fn compare_command(args: &[String], out: &mut dyn Write) -> Result<(), String> {
+    let [baseline, current, options @ ..] = args else {
+        return Err("compare takes a baseline and a current CSV".to_string());
+    };
+    let comparison = compare_benchmark_files(baseline, current, &CompareThresholds::from_args(options)?)?;
+    out.write_all(comparison.report().as_bytes()).map_err(|e| format!("Failed to write output: {}", e))?;
+    // Regressions fail the run, so CI can gate on the exit status
+    match comparison.regression_count() {
+        0 => Ok(()),
+        count => Err(format!("{} regressions beyond their thresholds", count)),
+    }
+}
+
+/// Help text of a command, or the general usage
+fn help(command: Option<&str>) -> Result<String, String> {
+    Ok(match command {
+        None => USAGE.to_string(),
+        Some("layout") => {
+            let mut text = LAYOUT_HELP.to_string();
+            for info in layout_catalog() {
+                text.push_str(&format!("  {:<14} {}\n", info.id, info.description));
+            }
+            text
+        }
+        Some("convert") => CONVERT_HELP.to_string(),
+        Some("info") => INFO_HELP.to_string(),
+        Some("benchmark") => BENCHMARK_HELP.to_string(),
+        Some("compare") => COMPARE_HELP.to_string(),
+        Some(other) => return Err(format!("Unknown command: {}", other)),
+    })
+}
+
+/// Run a command line (without the program name), writing results to `out`
+pub fn run(args: &[String], out: &mut dyn Write) -> Result<(), String> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(format!("No command given\n\n{}", USAGE));
+    };
+    let wants_help = rest.iter().any(|arg| arg == "-h" || arg == "--help");
+    let text = match command.as_str() {
+        "help" | "-h" | "--help" => help(rest.first().map(String::as_str))?,
+        command if wants_help => help(Some(command))?,
+        "layout" => return layout_command(rest, out),
+        "convert" => return convert_command(rest, out),
+        "info" => return info_command(rest, out),
+        "benchmark" => return benchmark_command(rest, out),
+        "compare" => return compare_command(rest, out),
+        other => return Err(format!("Unknown command: {} (see `rust-graph-layouts help`)", other)),
+    };
+    out.write_all(text.as_bytes()).map_err(|e| format!("Failed to write output: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_args(args: &[&str]) -> Result<String, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut out = Vec::new();
+        run(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_argument_parsing() {
+        let args: Vec<String> = ["in.dot", "-a", "dagre", "--to=svg", "--json", "--", "-odd"].iter().map(|arg| arg.to_string()).collect();
+        let specs = [("algorithm", Some("a"), true), ("to", None, true), ("json", None, false)];
+        let parsed = parse_arguments(&args, &specs).unwrap();
+        assert_eq!(parsed.positional, vec!["in.dot", "-odd"]);
+        assert_eq!((parsed.value("algorithm"), parsed.value("to")), (Some("dagre"), Some("svg")));
+        assert!(parsed.switches.contains("json"));
+
+        assert!(parse_arguments(&args[..2], &specs).is_err());
+        assert!(parse_arguments(&["--bogus".to_string()], &specs).is_err());
+        assert!(run_args(&["frobnicate"]).is_err());
+        assert!(run_args(&["layout", "--help"]).unwrap().contains("dagre"));
+        assert!(run_args(&["help", "compare"]).unwrap().contains("--threshold"));
+    }
+
+    #[test]
+    fn test_layout_convert_and_info() {
+        let sample = format!("{}/docs/sample/dagre_layout.json", env!("CARGO_MANIFEST_DIR"));
+        let dot = std::env::temp_dir().join(format!("rust-graph-layouts-cli-{}.dot", std::process::id()));
+        let dot = dot.to_str().unwrap();
+
+        run_args(&["convert", &sample, dot]).unwrap();
+        let json = run_args(&["layout", dot, "--algorithm", "dagre", "--options", r#"{"rank_direction": "LR"}"#]).unwrap();
+        let graph = parse_graph_file(&json, "json").unwrap();
+        assert!(!graph.nodes.is_empty() && graph.nodes.values().all(|node| node.position.is_some()));
+
+        let info = run_args(&["info", dot]).unwrap();
+        assert!(info.starts_with(&format!("nodes       {} ", graph.nodes.len())), "{}", info);
+        let stats: Value = serde_json::from_str(&run_args(&["info", dot, "--json"]).unwrap()).unwrap();
+        assert_eq!(stats["edge_count"], graph.edges.len());
+        fs::remove_file(dot).unwrap();
+
+        assert!(layout_algorithm(None, None).unwrap_err().contains("fcose"));
+        assert!(layout_algorithm(None, Some(r#"{"name": "concentric"}"#)).is_ok());
+        assert!(layout_algorithm(Some("dagre"), Some(r#"{"rank_direction": 3}"#)).is_err());
+    }
+
+    #[test]
+    fn test_compare_fails_on_missing_rows() {
+        let header = "graph_name,layout_name,execution_time_ms\n";
+        let path = |name: &str| std::env::temp_dir().join(format!("rust-graph-layouts-cli-{}-{}.csv", std::process::id(), name));
+        let (baseline, current) = (path("baseline"), path("current"));
+        fs::write(&baseline, format!("{}a.json,fcose,10\na.json,dagre,5\n", header)).unwrap();
+        fs::write(&current, format!("{}a.json,fcose,10\n", header)).unwrap();
+        let (baseline, current) = (baseline.to_str().unwrap(), current.to_str().unwrap());
+
+        let missing = run_args(&["compare", baseline, current]);
+        let allowed = run_args(&["compare", baseline, current, "--allow-missing"]);
+        fs::remove_file(baseline).unwrap();
+        fs::remove_file(current).unwrap();
+
+        assert_eq!(missing.unwrap_err(), "1 regressions beyond their thresholds");
+        assert!(allowed.unwrap().contains("missing    a.json / dagre: only in the baseline"));
+    }
+}
@@ -0,0 +1,800 @@
+//! Graphviz DOT reader.
+//!
+//! Implements the DOT grammar (https://graphviz.org/doc/info/lang.html): quoted, HTML
+//! and numeral IDs, comments, attribute lists, default `node`/`edge` attributes,
+//! edge chains (`a -> b -> c`), subgraphs as edge operands and ports, which are
+//! dropped. Attributes map onto the graph as follows:
+//!
+//! - `label` becomes `Node.label` (edge labels go into metadata)
+//! - `pos` (`"x,y"` or `"x,y!"`, y pointing up as in Graphviz) becomes the position
+//! - `width`/`height` (inches) become the size in points, `shape` the shape
+//! - `type` becomes the node or edge type; edges also read `weight`, `minlen` and `id`
+//! - everything else is kept in metadata, as a number or boolean where it parses as one
+//!
+//! Subgraphs named `cluster...` become compound nodes around the nodes they contain.
+//!
+//! The writer maps the graph back the same way, with positions as pinned `pos="x,y!"`
+//! in points so that `neato -n` draws the layout as computed. Compound nodes are
+//! written as clusters, named `cluster_<id>` unless their ID already starts with
+//! `cluster`.
+
+use std::collections::{BTreeMap, HashMap};
+use super::graphml::shape_name;
+use crate::types::{Edge, Graph, Id, MetadataValue, Node, NodeShape};
+
+/// DOT sizes are given in inches; layouts work in points
+const POINTS_PER_INCH: f64 = 72.0;
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    /// Identifier or numeral
+    Id(String),
+    /// Double-quoted or HTML string
+    Quoted(String),
+    Punct(char),
+    /// `->` or `--`
+    EdgeOp(&'static str),
+    Plus,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn syntax_error(line: usize, column: usize, message: impl std::fmt::Display) -> String {
+    format!("DOT syntax error at line {}, column {}: {}", line, column, message)
+}
+
+/// Split DOT source into tokens, dropping whitespace and comments
+fn tokenize(content: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    let mut line_start = true;
+
+    // Move past `count` characters, keeping track of the location
+    let advance = |i: &mut usize, line: &mut usize, column: &mut usize, count: usize| {
+        for _ in 0..count {
+            if chars[*i] == '\n' {
+                *line += 1;
+                *column = 1;
+            } else {
+                *column += 1;
+            }
+            *i += 1;
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (start_line, start_column) = (line, column);
+
+        if c.is_whitespace() {
+            if c == '\n' {
+                line_start = true;
+            }
+            advance(&mut i, &mut line, &mut column, 1);
+            continue;
+        }
+        // Preprocessor output lines and comments
+        if (c == '#' && line_start) || (c == '/' && next == Some('/')) {
+            while i < chars.len() && chars[i] != '\n' {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            continue;
+        }
+        line_start = false;
+        if c == '/' && next == Some('*') {
+            advance(&mut i, &mut line, &mut column, 2);
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            if i >= chars.len() {
+                return Err(syntax_error(start_line, start_column, "unterminated comment"));
+            }
+            advance(&mut i, &mut line, &mut column, 2);
+            continue;
+        }
+
+        let kind = if c == '-' && (next == Some('>') || next == Some('-')) {
+            advance(&mut i, &mut line, &mut column, 2);
+            TokenKind::EdgeOp(if next == Some('>') { "->" } else { "--" })
+        } else if "{}[]=;,:".contains(c) {
+            advance(&mut i, &mut line, &mut column, 1);
+            TokenKind::Punct(c)
+        } else if c == '+' {
+            advance(&mut i, &mut line, &mut column, 1);
+            TokenKind::Plus
+        } else if c == '"' {
+            advance(&mut i, &mut line, &mut column, 1);
+            let mut text = String::new();
+            loop {
+                match chars.get(i) {
+                    None => return Err(syntax_error(start_line, start_column, "unterminated string")),
+                    Some('"') => break,
+                    // Escaped quotes and backslashes
+                    Some('\\') if matches!(chars.get(i + 1), Some('"') | Some('\\')) => {
+                        text.push(chars[i + 1]);
+                        advance(&mut i, &mut line, &mut column, 2);
+                    }
+                    // A backslash before a line break continues the string
+                    Some('\\') if chars.get(i + 1) == Some(&'\n') => advance(&mut i, &mut line, &mut column, 2),
+                    Some(&other) => {
+                        text.push(other);
+                        advance(&mut i, &mut line, &mut column, 1);
+                    }
+                }
+            }
+            advance(&mut i, &mut line, &mut column, 1);
+            TokenKind::Quoted(text)
+        } else if c == '<' {
+            // HTML strings nest angle brackets; the outermost pair is not part of the value
+            advance(&mut i, &mut line, &mut column, 1);
+            let mut depth = 1;
+            let mut text = String::new();
+            loop {
+                match chars.get(i) {
+                    None => return Err(syntax_error(start_line, start_column, "unterminated HTML string")),
+                    Some(&other) => {
+                        depth += match other { '<' => 1, '>' => -1, _ => 0 };
+                        advance(&mut i, &mut line, &mut column, 1);
+                        if depth == 0 {
+                            break;
+                        }
+                        text.push(other);
+                    }
+                }
+            }
+            TokenKind::Quoted(text)
+        } else if c.is_ascii_digit() || ((c == '-' || c == '.') && next.is_some_and(|n| n.is_ascii_digit() || n == '.')) {
+            let mut text = String::from(c);
+            advance(&mut i, &mut line, &mut column, 1);
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                text.push(chars[i]);
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            TokenKind::Id(text)
+        } else if c.is_alphanumeric() || c == '_' || !c.is_ascii() {
+            let mut text = String::new();
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || !chars[i].is_ascii()) {
+                text.push(chars[i]);
+                advance(&mut i, &mut line, &mut column, 1);
+            }
+            TokenKind::Id(text)
+        } else {
+            return Err(syntax_error(line, column, format!("unexpected character '{}'", c)));
+        };
+        tokens.push(Token { kind, line: start_line, column: start_column });
+    }
+    Ok(tokens)
+}
+
+/// Default attributes in effect in a graph or subgraph
+#[derive(Clone, Default)]
+struct Scope {
+    node_defaults: Vec<(String, String)>,
+    edge_defaults: Vec<(String, String)>,
+    /// Innermost cluster, with its nesting depth
+    cluster: Option<(Id, usize)>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Location just after the last token, for errors at the end of the input
+    end: (usize, usize),
+    directed: bool,
+    graph: Graph,
+    /// Nesting depth of the cluster each node was placed in
+    depth: HashMap<Id, usize>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|token| &token.kind)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&TokenKind> {
+        self.tokens.get(self.pos + offset).map(|token| &token.kind)
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        let (line, column) = self.tokens.get(self.pos).map_or(self.end, |token| (token.line, token.column));
+        syntax_error(line, column, message)
+    }
+
+    /// Description of the next token for error messages
+    fn found(&self) -> String {
+        match self.peek() {
+            None => "end of input".to_string(),
+            Some(TokenKind::Id(text)) => format!("'{}'", text),
+            Some(TokenKind::Quoted(text)) => format!("\"{}\"", text),
+            Some(TokenKind::Punct(c)) => format!("'{}'", c),
+            Some(TokenKind::EdgeOp(op)) => format!("'{}'", op),
+            Some(TokenKind::Plus) => "'+'".to_string(),
+        }
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        self.peek() == Some(&TokenKind::Punct(c))
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        let found = self.is_punct(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), String> {
+        if self.eat_punct(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{}', found {}", c, self.found())))
+        }
+    }
+
+    /// Whether the next token is the given (case-insensitive, unquoted) keyword
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(TokenKind::Id(text)) if text.eq_ignore_ascii_case(keyword))
+    }
+
+    fn is_id(&self) -> bool {
+        matches!(self.peek(), Some(TokenKind::Id(_) | TokenKind::Quoted(_)))
+    }
+
+    /// An ID, joining quoted strings concatenated with `+`
+    fn parse_id(&mut self) -> Result<String, String> {
+        match self.peek().cloned() {
+            Some(TokenKind::Id(text)) => {
+                self.pos += 1;
+                Ok(text)
+            }
+            Some(TokenKind::Quoted(mut text)) => {
+                self.pos += 1;
+                while self.peek() == Some(&TokenKind::Plus) {
+                    self.pos += 1;
+                    match self.peek().cloned() {
+                        Some(TokenKind::Quoted(more)) => {
+                            self.pos += 1;
+                            text.push_str(&more);
+                        }
+                        _ => return Err(self.error(format!("expected a quoted string after '+', found {}", self.found()))),
+                    }
+                }
+                Ok(text)
+            }
+            _ => Err(self.error(format!("expected an ID, found {}", self.found()))),
+        }
+    }
+
+    /// `[a=b, c=d] [e=f]`, or nothing
+    fn parse_attr_lists(&mut self) -> Result<Vec<(String, String)>, String> {
+        let mut attributes = Vec::new();
+        while self.eat_punct('[') {
+            while !self.eat_punct(']') {
+                let key = self.parse_id()?;
+                if !self.eat_punct('=') {
+                    return Err(self.error(format!("expected '=' after attribute '{}', found {}", key, self.found())));
+                }
+                let value = self.parse_id()?;
+                attributes.push((key, value));
+                if !self.eat_punct(',') {
+                    self.eat_punct(';');
+                }
+            }
+        }
+        Ok(attributes)
+    }
+
+    fn parse_graph(&mut self) -> Result<(), String> {
+        if self.is_keyword("strict") {
+            self.pos += 1;
+        }
+        if self.is_keyword("digraph") {
+            self.directed = true;
+        } else if !self.is_keyword("graph") {
+            return Err(self.error(format!("expected 'graph' or 'digraph', found {}", self.found())));
+        }
+        self.pos += 1;
+        if self.is_id() {
+            self.parse_id()?;
+        }
+        self.expect_punct('{')?;
+        self.parse_statements(&mut Scope::default())?;
+        self.expect_punct('}')?;
+        Ok(())
+    }
+
+    /// Statements up to the closing brace, returning the nodes they mention
+    fn parse_statements(&mut self, scope: &mut Scope) -> Result<Vec<Id>, String> {
+        let mut mentioned = Vec::new();
+        while !self.is_punct('}') {
+            if self.peek().is_none() {
+                return Err(self.error("expected '}', found end of input"));
+            }
+            self.parse_statement(scope, &mut mentioned)?;
+            self.eat_punct(';');
+        }
+        Ok(mentioned)
+    }
+
+    fn parse_statement(&mut self, scope: &mut Scope, mentioned: &mut Vec<Id>) -> Result<(), String> {
+        // Default attributes
+        for keyword in ["graph", "node", "edge"] {
+            if self.is_keyword(keyword) {
+                self.pos += 1;
+                if !self.is_punct('[') {
+                    return Err(self.error(format!("expected '[' after '{}', found {}", keyword, self.found())));
+                }
+                let attributes = self.parse_attr_lists()?;
+                match keyword {
+                    "graph" => self.apply_graph_attributes(scope, attributes),
+                    "node" => scope.node_defaults.extend(attributes),
+                    _ => scope.edge_defaults.extend(attributes),
+                }
+                return Ok(());
+            }
+        }
+
+        // Graph attribute assignment
+        if self.is_id() && !self.is_keyword("subgraph") && self.peek_at(1) == Some(&TokenKind::Punct('=')) {
+            let key = self.parse_id()?;
+            self.pos += 1;
+            let value = self.parse_id()?;
+            self.apply_graph_attributes(scope, vec![(key, value)]);
+            return Ok(());
+        }
+
+        // Node or edge statement; the first operand may be a subgraph
+        let first = self.parse_operand(scope)?;
+        mentioned.extend(first.iter().cloned());
+        if !matches!(self.peek(), Some(TokenKind::EdgeOp(_))) {
+            let attributes = self.parse_attr_lists()?;
+            if let [id] = &first[..] {
+                if let Some(node) = self.graph.nodes.get_mut(id) {
+                    for (key, value) in attributes {
+                        apply_node_attribute(node, &key, &value);
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let mut operands = vec![first];
+        while let Some(TokenKind::EdgeOp(op)) = self.peek() {
+            let expected = if self.directed { "->" } else { "--" };
+            if *op != expected {
+                return Err(self.error(format!("'{}' in a {} graph", op, if self.directed { "directed" } else { "undirected" })));
+            }
+            self.pos += 1;
+            let operand = self.parse_operand(scope)?;
+            mentioned.extend(operand.iter().cloned());
+            operands.push(operand);
+        }
+        let mut attributes = scope.edge_defaults.clone();
+        attributes.extend(self.parse_attr_lists()?);
+
+        for pair in operands.windows(2) {
+            for source in &pair[0] {
+                for target in &pair[1] {
+                    self.add_edge(source, target, &attributes);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A node ID (ports dropped) or a subgraph, as the nodes it stands for
+    fn parse_operand(&mut self, scope: &mut Scope) -> Result<Vec<Id>, String> {
+        if self.is_keyword("subgraph") || self.is_punct('{') {
+            return self.parse_subgraph(scope);
+        }
+        let id = self.parse_id()?;
+        // Port and compass point
+        for _ in 0..2 {
+            if self.eat_punct(':') {
+                self.parse_id()?;
+            }
+        }
+        self.mention(&id, scope);
+        Ok(vec![id])
+    }
+
+    fn parse_subgraph(&mut self, scope: &mut Scope) -> Result<Vec<Id>, String> {
+        let mut name = None;
+        if self.is_keyword("subgraph") {
+            self.pos += 1;
+            if self.is_id() {
+                name = Some(self.parse_id()?);
+            }
+        }
+        self.expect_punct('{')?;
+
+        let mut inner = scope.clone();
+        if let Some(name) = name.filter(|name| name.starts_with("cluster")) {
+            let depth = scope.cluster.as_ref().map_or(1, |(_, depth)| depth + 1);
+            let mut cluster = Node::new(name.clone());
+            cluster.parent = scope.cluster.as_ref().map(|(parent, _)| parent.clone());
+            self.graph.add_node(cluster);
+            inner.cluster = Some((name, depth));
+        }
+        let mentioned = self.parse_statements(&mut inner)?;
+        self.expect_punct('}')?;
+        Ok(mentioned)
+    }
+
+    /// Create a node on first mention and move it into the innermost cluster it appears in
+    fn mention(&mut self, id: &Id, scope: &Scope) {
+        if !self.graph.nodes.contains_key(id) {
+            let mut node = Node::new(id.clone());
+            for (key, value) in &scope.node_defaults {
+                apply_node_attribute(&mut node, key, value);
+            }
+            self.graph.add_node(node);
+        }
+        if let Some((cluster, depth)) = &scope.cluster {
+            if cluster != id && self.depth.get(id).is_none_or(|placed| depth > placed) {
+                self.depth.insert(id.clone(), *depth);
+                if let Some(node) = self.graph.nodes.get_mut(id) {
+                    node.parent = Some(cluster.clone());
+                }
+            }
+        }
+    }
+
+    /// Graph attributes only matter inside clusters, where they describe the compound node
+    fn apply_graph_attributes(&mut self, scope: &Scope, attributes: Vec<(String, String)>) {
+        if let Some(node) = scope.cluster.as_ref().and_then(|(cluster, _)| self.graph.nodes.get_mut(cluster)) {
+            for (key, value) in attributes {
+                apply_node_attribute(node, &key, &value);
+            }
+        }
+    }
+
+    fn add_edge(&mut self, source: &Id, target: &Id, attributes: &[(String, String)]) {
+        let mut edge = Edge::new(String::new(), source.clone(), target.clone());
+        let mut explicit_id = None;
+        for (key, value) in attributes {
+            match key.as_str() {
+                "id" => explicit_id = Some(value.clone()),
+                "weight" => match value.parse() {
+                    Ok(weight) => edge.weight = weight,
+                    Err(_) => edge = edge.with_metadata(key.clone(), value.clone()),
+                },
+                "minlen" => match value.parse() {
+                    Ok(minlen) => edge.minlen = minlen,
+                    Err(_) => edge = edge.with_metadata(key.clone(), value.clone()),
+                },
+                "type" => edge.r#type = value.clone(),
+                _ => edge = edge.with_metadata(key.clone(), metadata_value(value)),
+            }
+        }
+
+        // Number repeated edges so that they do not replace each other
+        let base_id = explicit_id.unwrap_or_else(|| format!("e{}_{}", source, target));
+        let mut id = base_id.clone();
+        let mut count = 1;
+        while self.graph.edges.contains_key(&id) {
+            id = format!("{}_{}", base_id, count);
+            count += 1;
+        }
+        edge.id = id;
+        self.graph.add_edge(edge);
+    }
+}
+
+fn apply_node_attribute(node: &mut Node, key: &str, value: &str) {
+    match key {
+        // `\N` stands for the node name, which is what an empty label shows anyway
+        "label" if value == "\\N" => {}
+        "label" => node.label = value.to_string(),
+        "type" => node.r#type = value.to_string(),
+        "pos" => match parse_pos(value) {
+            Some(position) => node.position = Some(position),
+            None => {
+                node.metadata.insert(key.to_string(), value.into());
+            }
+        },
+        "width" | "height" => match value.parse::<f64>() {
+            Ok(inches) if key == "width" => node.width = Some(inches * POINTS_PER_INCH),
+            Ok(inches) => node.height = Some(inches * POINTS_PER_INCH),
+            Err(_) => {
+                node.metadata.insert(key.to_string(), value.into());
+            }
+        },
+        // Graphviz has many more shapes; those are kept in metadata
+        "shape" => match value.parse::<NodeShape>() {
+            Ok(shape) => node.shape = shape,
+            Err(_) => {
+                node.metadata.insert(key.to_string(), value.into());
+            }
+        },
+        _ => {
+            node.metadata.insert(key.to_string(), metadata_value(value));
+        }
+    }
+}
+
+/// `"x,y"` or `"x,y!"` with y pointing up, as a position with y pointing down
+fn parse_pos(value: &str) -> Option<(f64, f64)> {
+    let (x, y) = value.trim().trim_end_matches('!').split_once(',')?;
+    Some((x.trim().parse().ok()?, -y.trim().parse::<f64>().ok()?))
+}
+
+fn metadata_value(value: &str) -> MetadataValue {
+    if let Ok(number) = value.parse::<f64>() {
+        MetadataValue::Number(number)
+    } else if let Ok(flag) = value.parse::<bool>() {
+        MetadataValue::Boolean(flag)
+    } else {
+        MetadataValue::String(value.to_string())
+    }
+}
+
+/// Parse a DOT graph file
+pub fn parse_dot_graph(content: &str) -> Result<Graph, String> {
+    let tokens = tokenize(content)?;
+    let end = match content.rsplit_once('\n') {
+        Some((before, last)) => (before.matches('\n').count() + 2, last.chars().count() + 1),
+        None => (1, content.chars().count() + 1),
+    };
+    let mut parser = Parser { tokens, pos: 0, end, directed: false, graph: Graph::new(), depth: HashMap::new() };
+    parser.parse_graph()?;
+    Ok(parser.graph)
+}
+
+/// Node and edge attributes set from fields, which metadata doesn't override
+const NODE_FIELDS: [&str; 6] = ["label", "type", "pos", "width", "height", "shape"];
+const EDGE_FIELDS: [&str; 4] = ["id", "weight", "minlen", "type"];
+
+/// An ID as written in DOT: bare if it is a plain identifier, quoted otherwise
+fn quote(id: &str) -> String {
+    let plain = id.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !["node", "edge", "graph", "digraph", "subgraph", "strict"].contains(&id.to_lowercase().as_str());
+    if plain {
+        id.to_string()
+    } else {
+        format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn attribute_value(value: &MetadataValue) -> String {
+    match value {
+        MetadataValue::String(text) => quote(text),
+        MetadataValue::Number(number) => number.to_string(),
+        MetadataValue::Boolean(flag) => flag.to_string(),
+    }
+}
+
+/// `[key=value, ...]`, or nothing when there are no attributes
+fn attribute_list(attributes: &[(String, String)]) -> String {
+    if attributes.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = attributes.iter().map(|(key, value)| format!("{}={}", quote(key), value)).collect();
+    format!(" [{}]", pairs.join(", "))
+}
+
+fn node_attributes(node: &Node) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    if !node.label.is_empty() {
+        attributes.push(("label".to_string(), quote(&node.label)));
+    }
+    if !node.r#type.is_empty() {
+        attributes.push(("type".to_string(), quote(&node.r#type)));
+    }
+    if let Some((x, y)) = node.position {
+        // y points up in Graphviz (`0.0 - y` rather than `-y` avoids writing -0)
+        attributes.push(("pos".to_string(), format!("\"{},{}!\"", x, 0.0 - y)));
+    }
+    if let Some(width) = node.width {
+        attributes.push(("width".to_string(), (width / POINTS_PER_INCH).to_string()));
+    }
+    if let Some(height) = node.height {
+        attributes.push(("height".to_string(), (height / POINTS_PER_INCH).to_string()));
+    }
+    // A Graphviz shape we don't lay out is kept in metadata; write that one back
+    let shape = match node.metadata.get("shape") {
+        Some(shape) => attribute_value(shape),
+        None => shape_name(node.shape).to_string(),
+    };
+    attributes.push(("shape".to_string(), shape));
+    let metadata: BTreeMap<&String, &MetadataValue> = node.metadata.iter().collect();
+    for (key, value) in metadata {
+        if !NODE_FIELDS.contains(&key.as_str()) {
+            attributes.push((key.clone(), attribute_value(value)));
+        }
+    }
+    attributes
+}
+
+fn edge_attributes(edge: &Edge) -> Vec<(String, String)> {
+    let mut attributes = vec![
+        ("id".to_string(), quote(&edge.id)),
+        ("weight".to_string(), edge.weight.to_string()),
+        ("minlen".to_string(), edge.minlen.to_string()),
+    ];
+    if !edge.r#type.is_empty() {
+        attributes.push(("type".to_string(), quote(&edge.r#type)));
+    }
+    let metadata: BTreeMap<&String, &MetadataValue> = edge.metadata.iter().collect();
+    for (key, value) in metadata {
+        if !EDGE_FIELDS.contains(&key.as_str()) {
+            attributes.push((key.clone(), attribute_value(value)));
+        }
+    }
+    attributes
+}
+
+/// Write the nodes inside `parent`, with compound nodes as clusters
+fn write_nodes(out: &mut String, children: &BTreeMap<Option<&Id>, Vec<&Node>>, parent: Option<&Id>, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for node in children.get(&parent).into_iter().flatten() {
+        if children.contains_key(&Some(&node.id)) {
+            let name = if node.id.starts_with("cluster") { node.id.clone() } else { format!("cluster_{}", node.id) };
+            out.push_str(&format!("{}subgraph {} {{\n", indent, quote(&name)));
+            out.push_str(&format!("{}  graph{};\n", indent, attribute_list(&node_attributes(node))));
+            write_nodes(out, children, Some(&node.id), depth + 1);
+            out.push_str(&format!("{}}}\n", indent));
+        } else {
+            out.push_str(&format!("{}{}{};\n", indent, quote(&node.id), attribute_list(&node_attributes(node))));
+        }
+    }
+}
+
+/// Write a graph as DOT, with positions pinned where the layout put them
+pub fn write_dot(graph: &Graph) -> String {
+    let mut children: BTreeMap<Option<&Id>, Vec<&Node>> = BTreeMap::new();
+    for (_, node) in graph.sorted_nodes() {
+        let parent = node.parent.as_ref().filter(|parent| graph.nodes.contains_key(*parent));
+        children.entry(parent).or_default().push(node);
+    }
+
+    let mut out = String::from("digraph G {\n");
+    write_nodes(&mut out, &children, None, 1);
+    for edge in graph.sorted_edges() {
+        out.push_str(&format!(
+            "  {} -> {}{};\n",
+            quote(&edge.source), quote(&edge.target), attribute_list(&edge_attributes(edge))
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements_chains_and_attributes() {
+        let content = r#"
+            /* A small graph */
+            strict digraph "G" {
+                rankdir=LR; node [shape=box, fontsize=12]
+                a [label="Start \"here\"", pos="10,20!", type=task]; b; "c -> d" [label=<<b>bold</b>>]
+                a -> b -> "c -> d" [weight=3, label=flows, minlen=2]
+                # a preprocessor line
+                b:out:s -> { e f } // to both
+                a -> b [id=second]
+            }
+        "#;
+        let graph = parse_dot_graph(content).unwrap();
+
+        assert_eq!(graph.nodes.len(), 5);
+        let a = &graph.nodes["a"];
+        assert_eq!(a.label, "Start \"here\"");
+        assert_eq!(a.position, Some((10.0, -20.0)));
+        assert_eq!(a.r#type, "task");
+        assert_eq!(a.shape, NodeShape::Rectangle);
+        assert_eq!(a.metadata["fontsize"], MetadataValue::Number(12.0));
+        assert_eq!(graph.nodes["c -> d"].label, "<b>bold</b>");
+
+        assert_eq!(graph.edges.len(), 5);
+        let chained = &graph.edges["eb_c -> d"];
+        assert_eq!((chained.weight, chained.minlen), (3.0, 2));
+        assert_eq!(chained.metadata["label"], MetadataValue::String("flows".to_string()));
+        assert!(graph.edges.contains_key("eb_e") && graph.edges.contains_key("eb_f"));
+        assert_eq!(graph.edges["second"].source, "a");
+    }
+
+    #[test]
+    fn test_clusters_become_compound_nodes() {
+        let content = "graph {
+            subgraph cluster_outer {
+                label = \"Outer\"
+                a -- b
+                subgraph cluster_inner { graph [label=Inner]; c }
+                subgraph { d }
+            }
+            c -- e
+        }";
+        let graph = parse_dot_graph(content).unwrap();
+
+        assert_eq!(graph.nodes["cluster_outer"].label, "Outer");
+        assert_eq!(graph.nodes["cluster_inner"].label, "Inner");
+        assert_eq!(graph.nodes["cluster_inner"].parent.as_deref(), Some("cluster_outer"));
+        for (id, parent) in [("a", Some("cluster_outer")), ("c", Some("cluster_inner")), ("d", Some("cluster_outer")), ("e", None)] {
+            assert_eq!(graph.nodes[id].parent.as_deref(), parent, "{}", id);
+        }
+        assert!(graph.validate_hierarchy().is_ok());
+    }
+
+    #[test]
+    fn test_errors_report_line_and_column() {
+        let cases = [
+            ("digraph {\n  a -> ;\n}", "line 2, column 8"),
+            ("digraph {\n  a [label \"x\"]\n}", "line 2, column 12"),
+            ("graph {\n  a -> b\n}", "line 2, column 5"),
+            ("digraph {\n  a -> b\n", "line 3, column 1"),
+            ("digraph {\n  a [label=\"open\n}", "line 2, column 12"),
+        ];
+        for (content, location) in cases {
+            let error = parse_dot_graph(content).unwrap_err();
+            assert!(error.contains(location), "{:?}: {}", content, error);
+        }
+    }
+
+    #[test]
+    fn test_sample_dot_files_parse() {
+        for name in ["knowledge_graph.dot", "undirected_graph.dot"] {
+            let path = format!("{}/docs/sample/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let graph = parse_dot_graph(&std::fs::read_to_string(path).unwrap()).unwrap();
+            assert!(!graph.edges.is_empty(), "{}", name);
+            assert!(graph.nodes.values().all(|node| !node.label.is_empty()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_write_pins_positions_and_reads_back() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("group").with_position(0.0, 0.0))
+             .add_node(Node::new("a").with_parent("group").with_position(10.0, 20.0).with_size(36.0, 18.0).with_shape(NodeShape::Rectangle))
+             .add_node(Node::new("say \"hi\"").with_metadata("fontsize", 9.0).with_metadata("shape", "cylinder"));
+        graph.nodes.get_mut("a").unwrap().label = "Node A".to_string();
+        graph.nodes.get_mut("a").unwrap().r#type = "task".to_string();
+        graph.add_edge(Edge::new("e1", "a", "say \"hi\"").with_weight(2.5).with_minlen(2).with_metadata("label", "calls"));
+
+        let dot = write_dot(&graph);
+        assert!(dot.contains("pos=\"10,-20!\""), "{}", dot);
+        assert!(dot.contains("subgraph cluster_group {"), "{}", dot);
+
+        let read = parse_dot_graph(&dot).unwrap();
+        let a = &read.nodes["a"];
+        assert_eq!(a.position, Some((10.0, 20.0)));
+        assert_eq!((a.label.as_str(), a.r#type.as_str(), a.shape), ("Node A", "task", NodeShape::Rectangle));
+        assert!((a.width.unwrap() - 36.0).abs() < 1e-9 && (a.height.unwrap() - 18.0).abs() < 1e-9);
+        assert_eq!(a.parent.as_deref(), Some("cluster_group"));
+        let quoted = &read.nodes["say \"hi\""];
+        assert_eq!(quoted.metadata["fontsize"], MetadataValue::Number(9.0));
+        assert_eq!(quoted.metadata["shape"], MetadataValue::String("cylinder".to_string()));
+
+        let edge = &read.edges["e1"];
+        assert_eq!((edge.source.as_str(), edge.weight, edge.minlen), ("a", 2.5, 2));
+        assert_eq!(edge.metadata["label"], MetadataValue::String("calls".to_string()));
+    }
+
+    #[test]
+    fn test_backslashes_round_trip() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("C:\\"))
+             .add_node(Node::new("b"));
+        graph.nodes.get_mut("b").unwrap().label = "a\\\"b\\".to_string();
+        graph.add_edge(Edge::new("e1", "C:\\", "b"));
+
+        let dot = write_dot(&graph);
+        assert!(dot.contains("\"C:\\\\\""), "{}", dot);
+
+        let read = parse_dot_graph(&dot).unwrap();
+        assert!(read.nodes.contains_key("C:\\"));
+        assert_eq!(read.nodes["b"].label, "a\\\"b\\");
+        assert_eq!(read.edges.values().next().unwrap().source, "C:\\");
+    }
+}
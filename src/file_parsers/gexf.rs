@@ -0,0 +1,411 @@
+//! GEXF reader and writer, for graphs shared with Gephi.
+//!
+//! `<attvalue>`s are typed by their `<attribute>` declarations and stored in metadata
+//! under the attribute's title, except `type`, `width`, `height` and `shape`, which set
+//! those fields. `viz:position` gives the position (Gephi's y axis points up, so y is
+//! negated), `viz:size` the node's radius, `viz:shape` its shape and `viz:color` a
+//! `"#rrggbb"` `color` metadata entry. Nodes nested in a node's `<nodes>`, or naming
+//! it as `pid`, become its children.
+
+use std::collections::{BTreeMap, HashMap};
+use roxmltree::{Document, Node as XmlNode};
+use super::graphml::{children, escape_xml, metadata_text, metadata_type, number_attribute, shape_name, typed_value};
+use crate::types::{Edge, Graph, Id, MetadataValue, Node, NodeShape};
+
+const GEXF_NAMESPACE: &str = "http://gexf.net/1.3";
+const VIZ_NAMESPACE: &str = "http://gexf.net/1.3/viz";
+
+/// Node attributes that set fields, or are written as `viz:` elements
+const NODE_FIELDS: [&str; 5] = ["type", "width", "height", "shape", "color"];
+const EDGE_FIELDS: [&str; 2] = ["type", "label"];
+
+/// An `<attribute>` declaration
+struct Attribute {
+    title: String,
+    kind: String,
+    default: Option<String>,
+}
+
+/// Parse a GEXF file
+pub fn parse_gexf_graph(content: &str) -> Result<Graph, String> {
+    let document = Document::parse(content).map_err(|e| format!("Failed to parse GEXF: {}", e))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "gexf" {
+        return Err(format!("Expected a <gexf> root element, found <{}>", root.tag_name().name()));
+    }
+    let top = children(root, "graph").next().ok_or("GEXF file has no <graph> element")?;
+
+    let mut attributes: HashMap<(&str, &str), Attribute> = HashMap::new();
+    for declarations in children(top, "attributes") {
+        let class = declarations.attribute("class").unwrap_or("node");
+        for attribute in children(declarations, "attribute") {
+            let Some(id) = attribute.attribute("id") else { continue };
+            attributes.insert((class, id), Attribute {
+                title: attribute.attribute("title").unwrap_or(id).to_string(),
+                kind: attribute.attribute("type").unwrap_or("string").to_string(),
+                default: children(attribute, "default").next().and_then(|default| default.text()).map(str::to_string),
+            });
+        }
+    }
+
+    let mut graph = Graph::new();
+    for element in top.descendants().filter(|element| element.is_element() && element.tag_name().name() == "node") {
+        let id = element.attribute("id").ok_or_else(|| format!("<node> without an id at {}", document.text_pos_at(element.range().start)))?;
+        let mut node = Node::new(id);
+        node.label = element.attribute("label").unwrap_or_default().to_string();
+        node.parent = element.ancestors().skip(1)
+            .find(|ancestor| ancestor.is_element() && ancestor.tag_name().name() == "node")
+            .and_then(|parent| parent.attribute("id"))
+            .or_else(|| element.attribute("pid"))
+            .map(str::to_string);
+
+        for viz in element.children().filter(|child| child.is_element()) {
+            match viz.tag_name().name() {
+                "position" => {
+                    let (x, y) = (number_attribute(viz, "x").unwrap_or(0.0), number_attribute(viz, "y").unwrap_or(0.0));
+                    node.position = Some((x, -y));
+                }
+                "size" => {
+                    let diameter = number_attribute(viz, "value").map(|radius| radius * 2.0);
+                    node.width = diameter;
+                    node.height = diameter;
+                }
+                "shape" => {
+                    if let Some(shape) = viz.attribute("value").and_then(parse_shape) {
+                        node.shape = shape;
+                    }
+                }
+                "color" => {
+                    if let Some(color) = parse_color(viz) {
+                        node.metadata.insert("color".to_string(), color.into());
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (name, value) in attribute_values(element, &attributes, "node") {
+            match (name.as_str(), value) {
+                ("type", MetadataValue::String(kind)) => node.r#type = kind,
+                ("width", MetadataValue::Number(width)) => node.width = Some(width),
+                ("height", MetadataValue::Number(height)) => node.height = Some(height),
+                ("shape", MetadataValue::String(shape)) => match shape.parse() {
+                    Ok(shape) => node.shape = shape,
+                    Err(_) => {
+                        node.metadata.insert("shape".to_string(), shape.into());
+                    }
+                },
+                (name, value) => {
+                    node.metadata.insert(name.to_string(), value);
+                }
+            }
+        }
+        graph.add_node(node);
+    }
+
+    for (index, element) in top.descendants().filter(|element| element.is_element() && element.tag_name().name() == "edge").enumerate() {
+        let location = || document.text_pos_at(element.range().start);
+        let source = element.attribute("source").ok_or_else(|| format!("<edge> without a source at {}", location()))?;
+        let target = element.attribute("target").ok_or_else(|| format!("<edge> without a target at {}", location()))?;
+        let mut id = element.attribute("id").map_or_else(|| format!("e{}", index), str::to_string);
+        while graph.edges.contains_key(&id) {
+            id.push('_');
+        }
+        let mut edge = Edge::new(id, source, target);
+        if let Some(weight) = number_attribute(element, "weight") {
+            edge.weight = weight;
+        }
+        if let Some(label) = element.attribute("label") {
+            edge.metadata.insert("label".to_string(), label.into());
+        }
+        for (name, value) in attribute_values(element, &attributes, "edge") {
+            match (name.as_str(), value) {
+                ("type", MetadataValue::String(kind)) => edge.r#type = kind,
+                (name, value) => {
+                    edge.metadata.insert(name.to_string(), value);
+                }
+            }
+        }
+        graph.add_edge(edge);
+    }
+
+    Ok(graph)
+}
+
+/// Titled `<attvalue>`s of an element, with attribute defaults for the ones it leaves out
+fn attribute_values(element: XmlNode, attributes: &HashMap<(&str, &str), Attribute>, class: &str) -> BTreeMap<String, MetadataValue> {
+    let mut values = BTreeMap::new();
+    for ((_, _), attribute) in attributes.iter().filter(|((declared, _), _)| *declared == class) {
+        if let Some(default) = &attribute.default {
+            values.insert(attribute.title.clone(), typed_value(&attribute.kind, default));
+        }
+    }
+    for value in children(element, "attvalues").flat_map(|attvalues| children(attvalues, "attvalue")) {
+        // GEXF 1.1 names the attribute with `id` rather than `for`
+        let Some(id) = value.attribute("for").or_else(|| value.attribute("id")) else { continue };
+        if let Some(attribute) = attributes.get(&(class, id)) {
+            values.insert(attribute.title.clone(), typed_value(&attribute.kind, value.attribute("value").unwrap_or("")));
+        }
+    }
+    values.into_iter().collect()
+}
+
+/// `"#rrggbb"` from a `viz:color`'s `hex` or `r`, `g` and `b`
+fn parse_color(color: XmlNode) -> Option<String> {
+    if let Some(hex) = color.attribute("hex") {
+        return Some(hex.to_lowercase());
+    }
+    let channel = |name| number_attribute(color, name).map(|value| value.clamp(0.0, 255.0) as u8);
+    Some(format!("#{:02x}{:02x}{:02x}", channel("r")?, channel("g")?, channel("b")?))
+}
+
+fn color_channels(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |range| u8::from_str_radix(&hex[range], 16).ok();
+    Some((channel(0..2)?, channel(2..4)?, channel(4..6)?))
+}
+
+fn parse_shape(shape: &str) -> Option<NodeShape> {
+    match shape {
+        "disc" => Some(NodeShape::Circle),
+        "square" => Some(NodeShape::Rectangle),
+        "triangle" => Some(NodeShape::Triangle),
+        "diamond" => Some(NodeShape::Diamond),
+        _ => None,
+    }
+}
+
+/// `viz:shape` of our shapes, where GEXF has one
+fn shape_value(shape: NodeShape) -> Option<&'static str> {
+    match shape {
+        NodeShape::Circle => Some("disc"),
+        NodeShape::Rectangle => Some("square"),
+        NodeShape::Triangle => Some("triangle"),
+        NodeShape::Diamond => Some("diamond"),
+        NodeShape::Ellipse | NodeShape::Hexagon => None,
+    }
+}
+
+/// Node attributes written as attvalues: metadata, and the fields `viz:` can't express
+fn node_attributes(node: &Node) -> Vec<(&str, MetadataValue)> {
+    let mut values: Vec<(&str, MetadataValue)> = Vec::new();
+    if !node.r#type.is_empty() {
+        values.push(("type", node.r#type.as_str().into()));
+    }
+    if let (Some(width), Some(height)) = (node.width, node.height) {
+        if width != height {
+            values.push(("width", width.into()));
+            values.push(("height", height.into()));
+        }
+    }
+    // A shape we don't lay out is kept in metadata; write that one back
+    if let Some(shape) = node.metadata.get("shape") {
+        values.push(("shape", shape.clone()));
+    } else if shape_value(node.shape).is_none() {
+        values.push(("shape", shape_name(node.shape).into()));
+    }
+    if let Some(color) = node.metadata.get("color").filter(|color| !matches!(color, MetadataValue::String(hex) if color_channels(hex).is_some())) {
+        values.push(("color", color.clone()));
+    }
+    let mut metadata: Vec<_> = node.metadata.iter().filter(|(name, _)| !NODE_FIELDS.contains(&name.as_str())).collect();
+    metadata.sort_by(|a, b| a.0.cmp(b.0));
+    values.extend(metadata.into_iter().map(|(name, value)| (name.as_str(), value.clone())));
+    values
+}
+
+fn edge_attributes(edge: &Edge) -> Vec<(&str, MetadataValue)> {
+    let mut values: Vec<(&str, MetadataValue)> = Vec::new();
+    if !edge.r#type.is_empty() {
+        values.push(("type", edge.r#type.as_str().into()));
+    }
+    let mut metadata: Vec<_> = edge.metadata.iter().filter(|(name, _)| !EDGE_FIELDS.contains(&name.as_str())).collect();
+    metadata.sort_by(|a, b| a.0.cmp(b.0));
+    values.extend(metadata.into_iter().map(|(name, value)| (name.as_str(), value.clone())));
+    values
+}
+
+/// Declare the attributes used by a class, returning their IDs by title
+fn declare_attributes<'a>(out: &mut String, class: &str, values: impl Iterator<Item = (&'a str, MetadataValue)>) -> BTreeMap<&'a str, String> {
+    let mut by_title: BTreeMap<&str, Vec<MetadataValue>> = BTreeMap::new();
+    for (title, value) in values {
+        by_title.entry(title).or_default().push(value);
+    }
+    if by_title.is_empty() {
+        return BTreeMap::new();
+    }
+    out.push_str(&format!("    <attributes class=\"{}\">\n", class));
+    let mut ids = BTreeMap::new();
+    for (index, (title, values)) in by_title.into_iter().enumerate() {
+        out.push_str(&format!(
+            "      <attribute id=\"{}\" title=\"{}\" type=\"{}\"/>\n",
+            index, escape_xml(title), metadata_type(values.iter())
+        ));
+        ids.insert(title, index.to_string());
+    }
+    out.push_str("    </attributes>\n");
+    ids
+}
+
+fn write_attvalues(out: &mut String, indent: &str, ids: &BTreeMap<&str, String>, values: Vec<(&str, MetadataValue)>) {
+    if values.is_empty() {
+        return;
+    }
+    out.push_str(&format!("{}<attvalues>\n", indent));
+    for (title, value) in values {
+        out.push_str(&format!(
+            "{}  <attvalue for=\"{}\" value=\"{}\"/>\n",
+            indent, ids[title], escape_xml(&metadata_text(&value))
+        ));
+    }
+    out.push_str(&format!("{}</attvalues>\n", indent));
+}
+
+/// Write a graph as GEXF, with positions in `viz:position`
+pub fn write_gexf(graph: &Graph) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<gexf xmlns=\"{}\" xmlns:viz=\"{}\" version=\"1.3\">\n",
+        GEXF_NAMESPACE, VIZ_NAMESPACE
+    ));
+    out.push_str("  <graph defaultedgetype=\"directed\" mode=\"static\">\n");
+
+    let node_ids = declare_attributes(&mut out, "node", graph.nodes.values().flat_map(node_attributes));
+    let edge_ids = declare_attributes(&mut out, "edge", graph.edges.values().flat_map(edge_attributes));
+
+    let mut children_of: BTreeMap<Option<&Id>, Vec<&Node>> = BTreeMap::new();
+    for (_, node) in graph.sorted_nodes() {
+        let parent = node.parent.as_ref().filter(|parent| graph.nodes.contains_key(*parent));
+        children_of.entry(parent).or_default().push(node);
+    }
+    write_nodes(&mut out, &children_of, None, &node_ids, 2);
+
+    out.push_str("    <edges>\n");
+    for edge in graph.sorted_edges() {
+        let label = match edge.metadata.get("label") {
+            Some(label) => format!(" label=\"{}\"", escape_xml(&metadata_text(label))),
+            None => String::new(),
+        };
+        out.push_str(&format!(
+            "      <edge id=\"{}\" source=\"{}\" target=\"{}\" weight=\"{}\"{}>\n",
+            escape_xml(&edge.id), escape_xml(&edge.source), escape_xml(&edge.target), edge.weight, label
+        ));
+        write_attvalues(&mut out, "        ", &edge_ids, edge_attributes(edge));
+        out.push_str("      </edge>\n");
+    }
+    out.push_str("    </edges>\n  </graph>\n</gexf>\n");
+    out
+}
+
+fn write_nodes(out: &mut String, children_of: &BTreeMap<Option<&Id>, Vec<&Node>>, parent: Option<&Id>, ids: &BTreeMap<&str, String>, depth: usize) {
+    let indent = "  ".repeat(depth);
+    out.push_str(&format!("{}<nodes>\n", indent));
+    for node in children_of.get(&parent).into_iter().flatten() {
+        let label = if node.label.is_empty() { String::new() } else { format!(" label=\"{}\"", escape_xml(&node.label)) };
+        out.push_str(&format!("{}  <node id=\"{}\"{}>\n", indent, escape_xml(&node.id), label));
+        let inner = format!("{}    ", indent);
+        write_attvalues(out, &inner, ids, node_attributes(node));
+
+        if let Some((r, g, b)) = node.metadata.get("color").and_then(|color| match color {
+            MetadataValue::String(hex) => color_channels(hex),
+            _ => None,
+        }) {
+            out.push_str(&format!("{}<viz:color r=\"{}\" g=\"{}\" b=\"{}\"/>\n", inner, r, g, b));
+        }
+        if let Some((x, y)) = node.position {
+            out.push_str(&format!("{}<viz:position x=\"{}\" y=\"{}\" z=\"0\"/>\n", inner, x, 0.0 - y));
+        }
+        if let (Some(width), Some(height)) = (node.width, node.height) {
+            out.push_str(&format!("{}<viz:size value=\"{}\"/>\n", inner, width.max(height) / 2.0));
+        }
+        if let Some(shape) = shape_value(node.shape) {
+            out.push_str(&format!("{}<viz:shape value=\"{}\"/>\n", inner, shape));
+        }
+
+        if children_of.contains_key(&Some(&node.id)) {
+            write_nodes(out, children_of, Some(&node.id), ids, depth + 2);
+        }
+        out.push_str(&format!("{}  </node>\n", indent));
+    }
+    out.push_str(&format!("{}</nodes>\n", indent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gephi_attributes_and_viz() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gexf xmlns="http://gexf.net/1.2" xmlns:viz="http://gexf.net/1.2/viz" version="1.2">
+  <graph defaultedgetype="directed">
+    <attributes class="node">
+      <attribute id="0" title="degree" type="integer"><default>1</default></attribute>
+      <attribute id="1" title="community" type="string"/>
+    </attributes>
+    <attributes class="edge">
+      <attribute id="0" title="strong" type="boolean"/>
+    </attributes>
+    <nodes>
+      <node id="a" label="Alpha">
+        <attvalues><attvalue for="0" value="4"/><attvalue for="1" value="red team"/></attvalues>
+        <viz:color r="255" g="128" b="0"/>
+        <viz:position x="10.5" y="20" z="0"/>
+        <viz:size value="8"/>
+        <viz:shape value="diamond"/>
+      </node>
+      <node id="b" pid="a"/>
+    </nodes>
+    <edges>
+      <edge id="0" source="a" target="b" weight="3" label="knows">
+        <attvalues><attvalue for="0" value="true"/></attvalues>
+      </edge>
+    </edges>
+  </graph>
+</gexf>"#;
+        let graph = parse_gexf_graph(content).unwrap();
+
+        let a = &graph.nodes["a"];
+        assert_eq!(a.label, "Alpha");
+        assert_eq!(a.position, Some((10.5, -20.0)));
+        assert_eq!((a.width, a.height, a.shape), (Some(16.0), Some(16.0), NodeShape::Diamond));
+        assert_eq!(a.metadata["color"], MetadataValue::String("#ff8000".to_string()));
+        assert_eq!(a.metadata["degree"], MetadataValue::Number(4.0));
+        assert_eq!(a.metadata["community"], MetadataValue::String("red team".to_string()));
+        assert_eq!(graph.nodes["b"].parent.as_deref(), Some("a"));
+        assert_eq!(graph.nodes["b"].metadata["degree"], MetadataValue::Number(1.0));
+
+        let edge = &graph.edges["0"];
+        assert_eq!(edge.weight, 3.0);
+        assert_eq!(edge.metadata["label"], MetadataValue::String("knows".to_string()));
+        assert_eq!(edge.metadata["strong"], MetadataValue::Boolean(true));
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("g").with_position(0.0, 0.0))
+             .add_node(Node::new("a&b").with_parent("g").with_position(-3.0, 4.5).with_size(20.0, 20.0).with_metadata("color", "#00ff7f"))
+             .add_node(Node::new("c").with_size(30.0, 10.0).with_shape(NodeShape::Hexagon).with_metadata("rank", 2.0))
+             .add_node(Node::new("d").with_metadata("shape", "cylinder"));
+        graph.nodes.get_mut("c").unwrap().r#type = "service".to_string();
+        graph.add_edge(Edge::new("e1", "a&b", "c").with_weight(0.5).with_metadata("label", "<calls>").with_metadata("sync", false));
+
+        let read = parse_gexf_graph(&write_gexf(&graph)).unwrap();
+
+        let a = &read.nodes["a&b"];
+        assert_eq!(a.parent.as_deref(), Some("g"));
+        assert_eq!(a.position, Some((-3.0, 4.5)));
+        assert_eq!((a.width, a.height), (Some(20.0), Some(20.0)));
+        assert_eq!(a.metadata["color"], MetadataValue::String("#00ff7f".to_string()));
+        let c = &read.nodes["c"];
+        assert_eq!((c.width, c.height, c.shape), (Some(30.0), Some(10.0), NodeShape::Hexagon));
+        assert_eq!(c.r#type, "service");
+        assert_eq!(c.metadata["rank"], MetadataValue::Number(2.0));
+        assert_eq!(read.nodes["d"].metadata["shape"], MetadataValue::String("cylinder".to_string()));
+
+        let edge = &read.edges["e1"];
+        assert_eq!(edge.weight, 0.5);
+        assert_eq!(edge.metadata["label"], MetadataValue::String("<calls>".to_string()));
+        assert_eq!(edge.metadata["sync"], MetadataValue::Boolean(false));
+    }
+}
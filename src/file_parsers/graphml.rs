@@ -0,0 +1,480 @@
+//! GraphML reader and writer.
+//!
+//! `<key>` declarations give `<data>` values their names and types: `int`, `long`,
+//! `float` and `double` become numbers, `boolean` becomes a boolean and anything else
+//! a string. Keys named `label`, `type`, `x`, `y`, `width`, `height` and `shape` (and
+//! `weight` and `minlen` on edges) set the matching node or edge fields; all others
+//! go into metadata. yEd's node graphics set the position (from the top-left corner
+//! of the geometry), size, shape and label, and its edge paths set bend points.
+//! Nodes inside a node's nested `<graph>` become that node's children.
+//!
+//! The writer emits both the plain keys and yEd graphics, so laid-out graphs open
+//! with their positions in yEd as well as in NetworkX or Gephi.
+
+use std::collections::{BTreeMap, HashMap};
+use roxmltree::{Document, Node as XmlNode};
+use crate::types::{Edge, Graph, Id, MetadataValue, Node, NodeShape};
+
+const GRAPHML_NAMESPACE: &str = "http://graphml.graphdrawing.org/xmlns";
+const YED_NAMESPACE: &str = "http://www.yworks.com/xml/graphml";
+
+/// Node fields written as keys of their own rather than as metadata
+const NODE_FIELDS: [&str; 7] = ["label", "type", "x", "y", "width", "height", "shape"];
+const EDGE_FIELDS: [&str; 3] = ["weight", "minlen", "type"];
+
+/// A `<key>` declaration
+struct Key {
+    name: Option<String>,
+    domain: String,
+    kind: String,
+    default: Option<String>,
+}
+
+impl Key {
+    fn applies_to(&self, domain: &str) -> bool {
+        self.domain == domain || self.domain == "all"
+    }
+
+    fn value(&self, text: &str) -> MetadataValue {
+        typed_value(&self.kind, text)
+    }
+}
+
+/// A value of a declared attribute type, kept as a string if it doesn't parse
+pub(crate) fn typed_value(kind: &str, text: &str) -> MetadataValue {
+    let text = text.trim();
+    match kind {
+        "int" | "integer" | "long" | "float" | "double" => text.parse().map(MetadataValue::Number).unwrap_or_else(|_| text.into()),
+        "boolean" => text.parse().map(MetadataValue::Boolean).unwrap_or_else(|_| text.into()),
+        _ => text.into(),
+    }
+}
+
+pub(crate) fn children<'a, 'input>(element: XmlNode<'a, 'input>, name: &'a str) -> impl Iterator<Item = XmlNode<'a, 'input>> + 'a {
+    element.children().filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+pub(crate) fn descendant<'a, 'input>(element: XmlNode<'a, 'input>, name: &str) -> Option<XmlNode<'a, 'input>> {
+    element.descendants().find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+pub(crate) fn number_attribute(element: XmlNode, name: &str) -> Option<f64> {
+    element.attribute(name).and_then(|value| value.parse().ok())
+}
+
+/// Parse a GraphML file
+pub fn parse_graphml_graph(content: &str) -> Result<Graph, String> {
+    let document = Document::parse(content).map_err(|e| format!("Failed to parse GraphML: {}", e))?;
+    let root = document.root_element();
+    if root.tag_name().name() != "graphml" {
+        return Err(format!("Expected a <graphml> root element, found <{}>", root.tag_name().name()));
+    }
+
+    let keys: HashMap<&str, Key> = children(root, "key")
+        .filter_map(|key| {
+            let id = key.attribute("id")?;
+            let parsed = Key {
+                name: key.attribute("attr.name").map(str::to_string),
+                domain: key.attribute("for").unwrap_or("all").to_string(),
+                kind: key.attribute("attr.type").unwrap_or("string").to_string(),
+                default: children(key, "default").next().and_then(|default| default.text()).map(str::to_string),
+            };
+            Some((id, parsed))
+        })
+        .collect();
+    let top = children(root, "graph").next().ok_or("GraphML file has no <graph> element")?;
+
+    let mut graph = Graph::new();
+    for element in top.descendants().filter(|element| element.is_element() && element.tag_name().name() == "node") {
+        let id = element.attribute("id").ok_or_else(|| format!("<node> without an id at {}", document.text_pos_at(element.range().start)))?;
+        let mut node = Node::new(id);
+        // Nested graphs make the enclosing node a group
+        node.parent = element.ancestors().skip(1)
+            .find(|ancestor| ancestor.is_element() && ancestor.tag_name().name() == "node")
+            .and_then(|parent| parent.attribute("id"))
+            .map(str::to_string);
+
+        if let Some(geometry) = children(element, "data").find_map(|data| descendant(data, "Geometry")) {
+            apply_yed_geometry(&mut node, geometry);
+        }
+        for (name, value) in data_values(element, &keys, "node") {
+            apply_node_value(&mut node, name, value);
+        }
+        graph.add_node(node);
+    }
+
+    for (index, element) in top.descendants().filter(|element| element.is_element() && element.tag_name().name() == "edge").enumerate() {
+        let location = || document.text_pos_at(element.range().start);
+        let source = element.attribute("source").ok_or_else(|| format!("<edge> without a source at {}", location()))?;
+        let target = element.attribute("target").ok_or_else(|| format!("<edge> without a target at {}", location()))?;
+        let mut id = element.attribute("id").map_or_else(|| format!("e{}", index), str::to_string);
+        while graph.edges.contains_key(&id) {
+            id.push('_');
+        }
+        let mut edge = Edge::new(id, source, target);
+
+        if let Some(path) = children(element, "data").find_map(|data| descendant(data, "Path")) {
+            edge.bend_points = children(path, "Point")
+                .filter_map(|point| Some((number_attribute(point, "x")?, number_attribute(point, "y")?)))
+                .collect();
+        }
+        for (name, value) in data_values(element, &keys, "edge") {
+            match (name, value) {
+                ("weight", MetadataValue::Number(weight)) => edge.weight = weight,
+                ("minlen", MetadataValue::Number(minlen)) => edge.minlen = minlen.max(0.0) as u32,
+                ("type", MetadataValue::String(kind)) => edge.r#type = kind,
+                (name, value) => {
+                    edge.metadata.insert(name.to_string(), value);
+                }
+            }
+        }
+        graph.add_edge(edge);
+    }
+
+    Ok(graph)
+}
+
+/// Named `<data>` values of an element, with key defaults for the ones it leaves out
+fn data_values<'a>(element: XmlNode, keys: &'a HashMap<&str, Key>, domain: &str) -> Vec<(&'a str, MetadataValue)> {
+    let mut values: BTreeMap<&str, MetadataValue> = BTreeMap::new();
+    for key in keys.values().filter(|key| key.applies_to(domain)) {
+        if let (Some(name), Some(default)) = (&key.name, &key.default) {
+            values.insert(name, key.value(default));
+        }
+    }
+    for data in children(element, "data") {
+        let Some(key) = data.attribute("key").and_then(|id| keys.get(id)) else { continue };
+        if let Some(name) = &key.name {
+            values.insert(name, key.value(data.text().unwrap_or("")));
+        }
+    }
+    values.into_iter().collect()
+}
+
+fn apply_node_value(node: &mut Node, name: &str, value: MetadataValue) {
+    match (name, value) {
+        ("label", MetadataValue::String(label)) => node.label = label,
+        ("type", MetadataValue::String(kind)) => node.r#type = kind,
+        ("x", MetadataValue::Number(x)) => node.position = Some((x, node.position.map_or(0.0, |p| p.1))),
+        ("y", MetadataValue::Number(y)) => node.position = Some((node.position.map_or(0.0, |p| p.0), y)),
+        ("width", MetadataValue::Number(width)) => node.width = Some(width),
+        ("height", MetadataValue::Number(height)) => node.height = Some(height),
+        ("shape", MetadataValue::String(shape)) => match parse_shape(&shape) {
+            Some(shape) => node.shape = shape,
+            None => {
+                node.metadata.insert(name.to_string(), shape.into());
+            }
+        },
+        (name, value) => {
+            node.metadata.insert(name.to_string(), value);
+        }
+    }
+}
+
+/// Position, size, shape and label from yEd node graphics
+fn apply_yed_geometry(node: &mut Node, geometry: XmlNode) {
+    let (width, height) = (number_attribute(geometry, "width"), number_attribute(geometry, "height"));
+    node.width = width;
+    node.height = height;
+    if let (Some(x), Some(y)) = (number_attribute(geometry, "x"), number_attribute(geometry, "y")) {
+        node.position = Some((x + width.unwrap_or(0.0) / 2.0, y + height.unwrap_or(0.0) / 2.0));
+    }
+    let graphics = geometry.parent().unwrap_or(geometry);
+    if let Some(shape) = children(graphics, "Shape").next().and_then(|shape| shape.attribute("type")).and_then(parse_shape) {
+        node.shape = shape;
+    }
+    if let Some(label) = children(graphics, "NodeLabel").next().and_then(|label| label.text()) {
+        node.label = label.trim().to_string();
+    }
+}
+
+/// Our shapes and yEd's names for them
+fn parse_shape(shape: &str) -> Option<NodeShape> {
+    match shape {
+        "roundrectangle" | "rectangle3d" => Some(NodeShape::Rectangle),
+        _ => shape.parse().ok(),
+    }
+}
+
+/// Escape text for XML content and attribute values
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// GraphML type of a metadata key: numbers and booleans if all values agree
+pub(crate) fn metadata_type<'a>(values: impl Iterator<Item = &'a MetadataValue>) -> &'static str {
+    let mut kind = None;
+    for value in values {
+        let this = match value {
+            MetadataValue::Number(_) => "double",
+            MetadataValue::Boolean(_) => "boolean",
+            MetadataValue::String(_) => "string",
+        };
+        if kind.is_some_and(|kind| kind != this) {
+            return "string";
+        }
+        kind = Some(this);
+    }
+    kind.unwrap_or("string")
+}
+
+pub(crate) fn metadata_text(value: &MetadataValue) -> String {
+    match value {
+        MetadataValue::String(text) => text.clone(),
+        MetadataValue::Number(number) => number.to_string(),
+        MetadataValue::Boolean(flag) => flag.to_string(),
+    }
+}
+
+/// Write a graph as GraphML, with positions, sizes, bend points and metadata
+pub fn write_graphml(graph: &Graph) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<graphml xmlns=\"{}\" xmlns:y=\"{}\">\n",
+        GRAPHML_NAMESPACE, YED_NAMESPACE
+    ));
+
+    // Keys: fixed fields first, then metadata in name order
+    let node_types = ["string", "string", "double", "double", "double", "double", "string"];
+    let edge_types = ["double", "int", "string"];
+    let mut node_keys: BTreeMap<String, String> = BTreeMap::new();
+    let mut edge_keys: BTreeMap<String, String> = BTreeMap::new();
+    let mut next_key = 0;
+    let mut declare = |out: &mut String, keys: &mut BTreeMap<String, String>, domain: &str, name: &str, kind: &str| {
+        let id = format!("d{}", next_key);
+        next_key += 1;
+        out.push_str(&format!("  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>\n", id, domain, escape_xml(name), kind));
+        keys.insert(name.to_string(), id);
+    };
+    for (name, kind) in NODE_FIELDS.iter().zip(node_types) {
+        declare(&mut out, &mut node_keys, "node", name, kind);
+    }
+    for (name, kind) in EDGE_FIELDS.iter().zip(edge_types) {
+        declare(&mut out, &mut edge_keys, "edge", name, kind);
+    }
+    let mut node_metadata: BTreeMap<&str, Vec<&MetadataValue>> = BTreeMap::new();
+    for node in graph.nodes.values() {
+        for (name, value) in &node.metadata {
+            node_metadata.entry(name).or_default().push(value);
+        }
+    }
+    let mut edge_metadata: BTreeMap<&str, Vec<&MetadataValue>> = BTreeMap::new();
+    for edge in graph.edges.values() {
+        for (name, value) in &edge.metadata {
+            edge_metadata.entry(name).or_default().push(value);
+        }
+    }
+    for (name, values) in &node_metadata {
+        if !NODE_FIELDS.contains(name) {
+            declare(&mut out, &mut node_keys, "node", name, metadata_type(values.iter().copied()));
+        }
+    }
+    for (name, values) in &edge_metadata {
+        if !EDGE_FIELDS.contains(name) {
+            declare(&mut out, &mut edge_keys, "edge", name, metadata_type(values.iter().copied()));
+        }
+    }
+    out.push_str("  <key id=\"ng\" for=\"node\" yfiles.type=\"nodegraphics\"/>\n");
+    out.push_str("  <key id=\"eg\" for=\"edge\" yfiles.type=\"edgegraphics\"/>\n");
+
+    // Nodes, nested in their compound nodes
+    let mut children_of: BTreeMap<Option<&Id>, Vec<&Node>> = BTreeMap::new();
+    for (_, node) in graph.sorted_nodes() {
+        let parent = node.parent.as_ref().filter(|parent| graph.nodes.contains_key(*parent));
+        children_of.entry(parent).or_default().push(node);
+    }
+    out.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+    write_nodes(&mut out, &children_of, None, &node_keys, 2);
+
+    for edge in graph.sorted_edges() {
+        out.push_str(&format!(
+            "    <edge id=\"{}\" source=\"{}\" target=\"{}\">\n",
+            escape_xml(&edge.id), escape_xml(&edge.source), escape_xml(&edge.target)
+        ));
+        let data = |out: &mut String, name: &str, text: String| {
+            out.push_str(&format!("      <data key=\"{}\">{}</data>\n", edge_keys[name], escape_xml(&text)));
+        };
+        data(&mut out, "weight", edge.weight.to_string());
+        data(&mut out, "minlen", edge.minlen.to_string());
+        if !edge.r#type.is_empty() {
+            data(&mut out, "type", edge.r#type.clone());
+        }
+        let metadata: BTreeMap<&String, &MetadataValue> = edge.metadata.iter().collect();
+        for (name, value) in metadata {
+            if !EDGE_FIELDS.contains(&name.as_str()) {
+                data(&mut out, name, metadata_text(value));
+            }
+        }
+        if !edge.bend_points.is_empty() {
+            out.push_str("      <data key=\"eg\"><y:PolyLineEdge><y:Path sx=\"0\" sy=\"0\" tx=\"0\" ty=\"0\">");
+            for (x, y) in &edge.bend_points {
+                out.push_str(&format!("<y:Point x=\"{}\" y=\"{}\"/>", x, y));
+            }
+            out.push_str("</y:Path></y:PolyLineEdge></data>\n");
+        }
+        out.push_str("    </edge>\n");
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+fn write_nodes(out: &mut String, children_of: &BTreeMap<Option<&Id>, Vec<&Node>>, parent: Option<&Id>, keys: &BTreeMap<String, String>, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for node in children_of.get(&parent).into_iter().flatten() {
+        out.push_str(&format!("{}<node id=\"{}\">\n", indent, escape_xml(&node.id)));
+        let data = |out: &mut String, name: &str, text: String| {
+            out.push_str(&format!("{}  <data key=\"{}\">{}</data>\n", indent, keys[name], escape_xml(&text)));
+        };
+        if !node.label.is_empty() {
+            data(out, "label", node.label.clone());
+        }
+        if !node.r#type.is_empty() {
+            data(out, "type", node.r#type.clone());
+        }
+        if let Some((x, y)) = node.position {
+            data(out, "x", x.to_string());
+            data(out, "y", y.to_string());
+        }
+        if let Some(width) = node.width {
+            data(out, "width", width.to_string());
+        }
+        if let Some(height) = node.height {
+            data(out, "height", height.to_string());
+        }
+        // A shape we don't lay out is kept in metadata; write that one back
+        let shape = match node.metadata.get("shape") {
+            Some(shape) => metadata_text(shape),
+            None => shape_name(node.shape).to_string(),
+        };
+        data(out, "shape", shape);
+        let metadata: BTreeMap<&String, &MetadataValue> = node.metadata.iter().collect();
+        for (name, value) in metadata {
+            if !NODE_FIELDS.contains(&name.as_str()) {
+                data(out, name, metadata_text(value));
+            }
+        }
+
+        // yEd draws nodes from the top-left corner of their geometry
+        if let Some((x, y)) = node.position {
+            let (width, height) = node.size_or(30.0);
+            out.push_str(&format!(
+                "{}  <data key=\"ng\"><y:ShapeNode><y:Geometry x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/><y:Shape type=\"{}\"/><y:NodeLabel>{}</y:NodeLabel></y:ShapeNode></data>\n",
+                indent, x - width / 2.0, y - height / 2.0, width, height, shape_name(node.shape), escape_xml(&node.label)
+            ));
+        }
+
+        if children_of.contains_key(&Some(&node.id)) {
+            out.push_str(&format!("{}  <graph id=\"{}:\" edgedefault=\"directed\">\n", indent, escape_xml(&node.id)));
+            write_nodes(out, children_of, Some(&node.id), keys, depth + 2);
+            out.push_str(&format!("{}  </graph>\n", indent));
+        }
+        out.push_str(&format!("{}</node>\n", indent));
+    }
+}
+
+pub(crate) fn shape_name(shape: NodeShape) -> &'static str {
+    match shape {
+        NodeShape::Rectangle => "rectangle",
+        NodeShape::Ellipse => "ellipse",
+        NodeShape::Circle => "circle",
+        NodeShape::Diamond => "diamond",
+        NodeShape::Triangle => "triangle",
+        NodeShape::Hexagon => "hexagon",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_keys_and_yed_geometry() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<graphml xmlns="http://graphml.graphdrawing.org/xmlns" xmlns:y="http://www.yworks.com/xml/graphml">
+  <key id="d0" for="node" attr.name="rank" attr.type="int"><default>3</default></key>
+  <key id="d1" for="node" attr.name="active" attr.type="boolean"/>
+  <key id="d2" for="edge" attr.name="weight" attr.type="double"/>
+  <key id="d3" for="all" attr.name="note" attr.type="string"/>
+  <key id="d4" for="node" yfiles.type="nodegraphics"/>
+  <graph id="G" edgedefault="directed">
+    <node id="group">
+      <graph id="group:">
+        <node id="a">
+          <data key="d1">true</data>
+          <data key="d4"><y:ShapeNode><y:Geometry x="10" y="20" width="40" height="30"/><y:Shape type="roundrectangle"/><y:NodeLabel>Alpha &amp; co</y:NodeLabel></y:ShapeNode></data>
+        </node>
+      </graph>
+    </node>
+    <node id="b"><data key="d0">7</data><data key="d3">hello</data></node>
+    <edge source="a" target="b"><data key="d2">2.5</data></edge>
+  </graph>
+</graphml>"#;
+        let graph = parse_graphml_graph(content).unwrap();
+
+        let a = &graph.nodes["a"];
+        assert_eq!(a.parent.as_deref(), Some("group"));
+        assert_eq!(a.position, Some((30.0, 35.0)));
+        assert_eq!((a.width, a.height, a.shape), (Some(40.0), Some(30.0), NodeShape::Rectangle));
+        assert_eq!(a.label, "Alpha & co");
+        assert_eq!(a.metadata["active"], MetadataValue::Boolean(true));
+        assert_eq!(a.metadata["rank"], MetadataValue::Number(3.0));
+
+        let b = &graph.nodes["b"];
+        assert_eq!(b.metadata["rank"], MetadataValue::Number(7.0));
+        assert_eq!(b.metadata["note"], MetadataValue::String("hello".to_string()));
+        assert_eq!(graph.edges["e0"].weight, 2.5);
+    }
+
+    #[test]
+    fn test_write_and_read_back() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("g"))
+             .add_node(Node::new("a <1>").with_parent("g").with_position(1.5, -2.0).with_size(40.0, 20.0).with_shape(NodeShape::Diamond).with_metadata("score", 0.5))
+             .add_node(Node::new("b").with_metadata("team", "x\"y").with_metadata("flag", true).with_metadata("shape", "cylinder"));
+        graph.nodes.get_mut("b").unwrap().label = "Bee".to_string();
+        let mut edge = Edge::new("e1", "a <1>", "b").with_weight(4.0).with_minlen(2).with_metadata("kind", "uses");
+        edge.bend_points = vec![(5.0, 6.0), (7.0, 8.0)];
+        graph.add_edge(edge);
+
+        let written = write_graphml(&graph);
+        // Metadata values are written in name order
+        assert!(written.find(">true</data>").unwrap() < written.find(">x&quot;y</data>").unwrap());
+        let read = parse_graphml_graph(&written).unwrap();
+
+        assert_eq!(read.nodes.len(), 3);
+        let a = &read.nodes["a <1>"];
+        assert_eq!(a.parent.as_deref(), Some("g"));
+        assert_eq!(a.position, Some((1.5, -2.0)));
+        assert_eq!((a.width, a.height, a.shape), (Some(40.0), Some(20.0), NodeShape::Diamond));
+        assert_eq!(a.metadata["score"], MetadataValue::Number(0.5));
+        let b = &read.nodes["b"];
+        assert_eq!(b.label, "Bee");
+        assert_eq!(b.metadata["team"], MetadataValue::String("x\"y".to_string()));
+        assert_eq!(b.metadata["flag"], MetadataValue::Boolean(true));
+        assert_eq!(b.metadata["shape"], MetadataValue::String("cylinder".to_string()));
+
+        let edge = &read.edges["e1"];
+        assert_eq!((edge.weight, edge.minlen), (4.0, 2));
+        assert_eq!(edge.metadata["kind"], MetadataValue::String("uses".to_string()));
+        assert_eq!(edge.bend_points, vec![(5.0, 6.0), (7.0, 8.0)]);
+    }
+
+    #[test]
+    fn test_malformed_graphml_is_rejected() {
+        assert!(parse_graphml_graph("<graphml><graph>").unwrap_err().contains("Failed to parse GraphML"));
+        assert!(parse_graphml_graph("<graph/>").is_err());
+        assert!(parse_graphml_graph("<graphml><graph><edge target=\"a\"/></graph></graphml>").unwrap_err().contains("source"));
+    }
+}
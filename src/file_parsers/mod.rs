@@ -1,16 +1,37 @@
-use crate::types::{Graph, Node, Edge};
+use crate::types::{Graph, Node, Edge, SvgOptions};
 use std::collections::HashMap;
 
+mod dot;
+mod gexf;
+mod graphml;
+mod svg;
+
 /// Parse a graph file based on its format
 pub fn parse_graph_file(content: &str, file_type: &str) -> Result<Graph, String> {
     match file_type.to_lowercase().as_str() {
         "json" => parse_json_graph(content),
         "csv" => parse_csv_graph(content),
-        "dot" => parse_dot_graph(content),
+        "dot" => dot::parse_dot_graph(content),
+        "graphml" => graphml::parse_graphml_graph(content),
+        "gexf" => gexf::parse_gexf_graph(content),
         _ => Err(format!("Unsupported file type: {}", file_type)),
     }
 }
 
+pub use svg::write_svg;
+
+/// Write a graph, with its current layout, in one of the file formats
+pub fn write_graph_file(graph: &Graph, file_type: &str) -> Result<String, String> {
+    match file_type.to_lowercase().as_str() {
+        "json" => serde_json::to_string_pretty(graph).map_err(|e| format!("Failed to serialize graph: {}", e)),
+        "graphml" => Ok(graphml::write_graphml(graph)),
+        "gexf" => Ok(gexf::write_gexf(graph)),
+        "dot" => Ok(dot::write_dot(graph)),
+        "svg" => svg::write_svg(graph, &SvgOptions::default()),
+        _ => Err(format!("Unsupported file type for writing: {}", file_type)),
+    }
+}
+
 /// Parse a JSON graph file
 fn parse_json_graph(content: &str) -> Result<Graph, String> {
     // Try to parse as a complete Graph structure first
@@ -33,6 +54,12 @@ fn parse_json_graph(content: &str) -> Result<Graph, String> {
                 x: Option<f64>,
                 #[serde(default)]
                 y: Option<f64>,
+                #[serde(default)]
+                width: Option<f64>,
+                #[serde(default)]
+                height: Option<f64>,
+                #[serde(default)]
+                shape: Option<String>,
                 #[serde(flatten)]
                 extra: HashMap<String, serde_json::Value>,
             }
@@ -59,6 +86,19 @@ fn parse_json_graph(content: &str) -> Result<Graph, String> {
                             node.position = Some((x, y));
                         }
                         
+                        // Set size and shape if available
+                        node.width = node_data.width;
+                        node.height = node_data.height;
+                        // Shapes we don't lay out are kept in metadata
+                        if let Some(shape) = node_data.shape {
+                            match shape.parse() {
+                                Ok(shape) => node.shape = shape,
+                                Err(_) => {
+                                    node.metadata.insert("shape".to_string(), shape.into());
+                                }
+                            }
+                        }
+                        
                         // Add label as metadata if available
                         if let Some(label) = node_data.label {
                             node = node.with_metadata("label", label);
@@ -139,6 +179,12 @@ fn parse_csv_node_list(graph: &mut Graph, content: &str) -> Result<Graph, String
     let x_index = headers.iter().position(|h| h.to_lowercase() == "x");
     let y_index = headers.iter().position(|h| h.to_lowercase() == "y");
     
+    // Find optional size and shape columns
+    let width_index = headers.iter().position(|h| h.to_lowercase() == "width");
+    let height_index = headers.iter().position(|h| h.to_lowercase() == "height");
+    let shape_index = headers.iter().position(|h| h.to_lowercase() == "shape");
+    let reserved = [Some(id_index), x_index, y_index, width_index, height_index, shape_index];
+    
     for result in reader.records() {
         let record = result.map_err(|e| format!("Failed to read CSV record: {}", e))?;
         
@@ -158,12 +204,24 @@ fn parse_csv_node_list(graph: &mut Graph, content: &str) -> Result<Graph, String
             }
         }
         
+        // Set size and shape if available
+        let field = |index: Option<usize>| index.and_then(|i| record.get(i)).filter(|value| !value.is_empty());
+        node.width = field(width_index).and_then(|value| value.parse::<f64>().ok());
+        node.height = field(height_index).and_then(|value| value.parse::<f64>().ok());
+        // Shapes we don't lay out are kept in metadata
+        if let Some(shape) = field(shape_index) {
+            match shape.parse() {
+                Ok(shape) => node.shape = shape,
+                Err(_) => {
+                    node.metadata.insert("shape".to_string(), shape.into());
+                }
+            }
+        }
+        
         // Add all other columns as metadata
         for (i, header) in headers.iter().enumerate() {
-            if i != id_index && i != x_index.unwrap_or(usize::MAX) && i != y_index.unwrap_or(usize::MAX) {
-                if i < record.len() {
-                    node = node.with_metadata(header.clone(), record[i].to_string());
-                }
+            if !reserved.contains(&Some(i)) && i < record.len() {
+                node = node.with_metadata(header.clone(), record[i].to_string());
             }
         }
         
@@ -234,10 +292,8 @@ fn parse_csv_edge_list(graph: &mut Graph, content: &str) -> Result<Graph, String
         
         // Add all other columns as metadata
         for (i, header) in headers.iter().enumerate() {
-            if i != source_index && i != target_index && i != id_index.unwrap_or(usize::MAX) {
-                if i < record.len() {
-                    edge = edge.with_metadata(header.clone(), record[i].to_string());
-                }
+            if i != source_index && i != target_index && i != id_index.unwrap_or(usize::MAX) && i < record.len() {
+                edge = edge.with_metadata(header.clone(), record[i].to_string());
             }
         }
         
@@ -247,93 +303,95 @@ fn parse_csv_edge_list(graph: &mut Graph, content: &str) -> Result<Graph, String
     Ok(graph.clone())
 }
 
-/// Parse a DOT graph file
-fn parse_dot_graph(content: &str) -> Result<Graph, String> {
-    let mut graph = Graph::new();
-    let mut lines = content.lines();
-    let mut node_ids = std::collections::HashSet::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MetadataValue, NodeShape};
+
+    #[test]
+    fn test_json_node_size_and_shape() {
+        let content = r#"{"nodes": [{"id": "a", "width": 120, "height": 30, "shape": "box"}], "edges": []}"#;
+        let graph = parse_graph_file(content, "json").unwrap();
+        
+        let node = &graph.nodes["a"];
+        assert_eq!((node.width, node.height), (Some(120.0), Some(30.0)));
+        assert_eq!(node.shape, NodeShape::Rectangle);
+        
+        // Unknown shapes don't fail the parse and are kept in metadata
+        let content = r#"{"nodes": [{"id": "a", "shape": "star"}], "edges": []}"#;
+        let node = &parse_graph_file(content, "json").unwrap().nodes["a"];
+        assert_eq!(node.shape, NodeShape::default());
+        assert_eq!(node.metadata["shape"], MetadataValue::String("star".to_string()));
+    }
+
+    #[test]
+    fn test_csv_node_size_and_shape() {
+        let content = "id,width,height,shape,group\na,120,30,rectangle,x\nb,,,circle,y\n";
+        let graph = parse_graph_file(content, "csv").unwrap();
+        
+        let a = &graph.nodes["a"];
+        assert_eq!((a.width, a.height, a.shape), (Some(120.0), Some(30.0), NodeShape::Rectangle));
+        assert_eq!(graph.nodes["b"].shape, NodeShape::Circle);
+        assert_eq!(graph.nodes["b"].width, None);
+        
+        // Size and shape columns are not duplicated into metadata
+        assert_eq!(a.metadata.len(), 1);
+        
+        let graph = parse_graph_file("id,shape\na,star\n", "csv").unwrap();
+        assert_eq!(graph.nodes["a"].metadata["shape"], MetadataValue::String("star".to_string()));
+    }
+
+    #[test]
+    fn test_dot_node_size_and_shape() {
+        let content = "digraph G {\n  node [shape=box];\n  a -> b;\n  a [width=2, height=\"0.5\", shape=circle];\n}\n";
+        let graph = parse_graph_file(content, "dot").unwrap();
+        
+        assert_eq!(graph.nodes.len(), 2);
+        let a = &graph.nodes["a"];
+        assert_eq!((a.width, a.height), (Some(144.0), Some(36.0)));
+        assert_eq!(a.shape, NodeShape::Circle);
+    }
     
-    // Skip until we find the graph definition
-    while let Some(line) = lines.next() {
-        let line = line.trim();
-        if line.starts_with("digraph") || line.starts_with("graph") {
-            break;
+    #[test]
+    fn test_dot_parallel_edges_keep_unique_ids() {
+        let content = "digraph G {\n  a -> b;\n  a -> b;\n  a -> b;\n  a -> a;\n}\n";
+        let graph = parse_graph_file(content, "dot").unwrap();
+        
+        assert_eq!(graph.edges.len(), 4);
+        for id in ["ea_b", "ea_b_1", "ea_b_2", "ea_a"] {
+            assert!(graph.edges.contains_key(id), "missing {}", id);
         }
     }
     
-    // Parse the graph content
-    for line in lines {
-        let line = line.trim();
-        
-        // Skip comments and empty lines
-        if line.is_empty() || line.starts_with("//") || line.starts_with("#") {
-            continue;
+    #[test]
+    fn test_sample_graphs_with_polygon_shapes_parse() {
+        for name in ["dagre_layout.json", "fcose_layout.json", "concentric_layout.json"] {
+            let path = format!("{}/docs/sample/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let content = std::fs::read_to_string(path).unwrap();
+            let graph = parse_graph_file(&content, "json").unwrap();
+            assert!(graph.nodes.values().any(|node| node.shape == NodeShape::Diamond), "{}", name);
         }
-        
-        // Skip graph attributes and closing brace
-        if line.starts_with("}") || (line.contains("=") && !line.contains("->") && !line.contains("--")) {
-            continue;
+    }
+    
+    #[test]
+    fn test_xml_round_trips_keep_layout() {
+        let path = format!("{}/docs/sample/dagre_layout.json", env!("CARGO_MANIFEST_DIR"));
+        let mut graph = parse_graph_file(&std::fs::read_to_string(path).unwrap(), "json").unwrap();
+        for (index, (_, node)) in graph.sorted_nodes_mut().into_iter().enumerate() {
+            node.position = Some((index as f64 * 10.0, -(index as f64)));
         }
         
-        // Check if this is an edge definition
-        if line.contains("->") || line.contains("--") {
-            // This is an edge
-            let parts: Vec<&str> = if line.contains("->") {
-                line.split("->").collect()
-            } else {
-                line.split("--").collect()
-            };
-            
-            if parts.len() < 2 {
-                continue;
-            }
-            
-            let source = parts[0].trim().trim_matches('"').to_string();
-            let mut target_parts = parts[1].trim().split(';').collect::<Vec<&str>>();
-            let target_with_attrs = target_parts.remove(0);
-            
-            // Extract target and attributes
-            let target_parts: Vec<&str> = target_with_attrs.split('[').collect();
-            let target = target_parts[0].trim().trim_matches('"').to_string();
-            
-            // Add nodes if they don't exist
-            if !node_ids.contains(&source) {
-                graph.add_node(Node::new(source.clone()));
-                node_ids.insert(source.clone());
-            }
-            
-            if !node_ids.contains(&target) {
-                graph.add_node(Node::new(target.clone()));
-                node_ids.insert(target.clone());
-            }
+        for format in ["graphml", "gexf", "dot"] {
+            let written = write_graph_file(&graph, format).unwrap();
+            let read = parse_graph_file(&written, &format.to_uppercase()).unwrap();
             
-            // Create edge
-            let edge_id = format!("e{}_{}", source, target);
-            let edge = Edge::new(edge_id, source, target);
-            graph.add_edge(edge);
-        } else if !line.contains("->") && !line.contains("--") && line.contains("[") {
-            // This is a node with attributes
-            let parts: Vec<&str> = line.split('[').collect();
-            if parts.len() < 1 {
-                continue;
-            }
-            
-            let node_id = parts[0].trim().trim_matches('"').to_string();
-            
-            if !node_ids.contains(&node_id) {
-                graph.add_node(Node::new(node_id.clone()));
-                node_ids.insert(node_id);
-            }
-        } else if !line.contains("[") && !line.contains("]") && !line.contains("->") && !line.contains("--") {
-            // This is a simple node
-            let node_id = line.trim().trim_matches('"').trim_end_matches(';').to_string();
-            
-            if !node_id.is_empty() && !node_ids.contains(&node_id) {
-                graph.add_node(Node::new(node_id.clone()));
-                node_ids.insert(node_id);
+            assert_eq!(read.nodes.len(), graph.nodes.len(), "{}", format);
+            assert_eq!(read.edges.len(), graph.edges.len(), "{}", format);
+            for (id, node) in &graph.nodes {
+                assert_eq!(read.nodes[id].position, node.position, "{}", format);
+                assert_eq!(read.nodes[id].shape, node.shape, "{}", format);
             }
         }
+        assert!(write_graph_file(&graph, "xlsx").is_err());
     }
-    
-    Ok(graph)
 }
@@ -0,0 +1,248 @@
+//! SVG drawing of a laid-out graph.
+//!
+//! Compound nodes are drawn first as translucent boxes, outermost first, then edges
+//! along their routes (or straight between node boundaries when unrouted), then leaf
+//! nodes in their shapes with their labels. Nodes without a position are left out.
+
+use std::fmt::Write;
+use super::graphml::escape_xml;
+use crate::layout::compound::Hierarchy;
+use crate::types::{Edge, Graph, Node, NodeShape, RouteStyle, SvgNodeStyle, SvgOptions};
+
+/// An edge with its points, and whether they are Bézier segments
+type DrawnEdge<'a> = (&'a Edge, Vec<(f64, f64)>, bool);
+
+/// Round to hundredths and drop trailing zeros, to keep output small and stable
+fn number(value: f64) -> String {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded == 0.0 { "0".to_string() } else { rounded.to_string() }
+}
+
+/// Points of an edge in layout coordinates, and whether they are Bézier segments
+fn edge_points(graph: &Graph, edge: &Edge, default_size: f64) -> Option<(Vec<(f64, f64)>, bool)> {
+    if let Some(route) = edge.route.as_ref().filter(|route| route.points.len() >= 2) {
+        return Some((route.points.clone(), route.style == RouteStyle::Spline));
+    }
+    if edge.source == edge.target && edge.bend_points.is_empty() {
+        return None;
+    }
+    let source = graph.nodes.get(&edge.source)?;
+    let target = graph.nodes.get(&edge.target)?;
+    let mut points = vec![source.position?];
+    points.extend(edge.bend_points.iter().copied());
+    points.push(target.position?);
+
+    // Start and end on the node boundaries
+    let clip = |node: &Node, from: (f64, f64), toward: (f64, f64)| {
+        let (dx, dy) = (toward.0 - from.0, toward.1 - from.1);
+        let length = (dx * dx + dy * dy).sqrt();
+        if length < 1e-9 {
+            return from;
+        }
+        let distance = node.shape.boundary_distance(node.size_or(default_size), (dx / length, dy / length)).min(length);
+        (from.0 + dx / length * distance, from.1 + dy / length * distance)
+    };
+    let last = points.len() - 1;
+    let start = clip(source, points[0], points[1]);
+    let end = clip(target, points[last], points[last - 1]);
+    points[0] = start;
+    points[last] = end;
+    Some((points, false))
+}
+
+/// Bounding box of everything drawn, in layout coordinates
+fn bounds(graph: &Graph, edges: &[DrawnEdge], default_size: f64) -> Option<(f64, f64, f64, f64)> {
+    let mut corners = Vec::new();
+    for node in graph.nodes.values() {
+        if let Some((x, y)) = node.position {
+            let (width, height) = node.size_or(default_size);
+            corners.push((x - width / 2.0, y - height / 2.0));
+            corners.push((x + width / 2.0, y + height / 2.0));
+        }
+    }
+    corners.extend(edges.iter().flat_map(|(_, points, _)| points.iter().copied()));
+    let first = *corners.first()?;
+    Some(corners.iter().fold((first.0, first.1, first.0, first.1), |(min_x, min_y, max_x, max_y), &(x, y)| {
+        (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+    }))
+}
+
+/// Draw a laid-out graph as an SVG document
+pub fn write_svg(graph: &Graph, options: &SvgOptions) -> Result<String, String> {
+    let hierarchy = Hierarchy::new(graph)?;
+    let default_size = options.default_node_size;
+    let edges: Vec<DrawnEdge> = graph.sorted_edges().into_iter()
+        .filter_map(|edge| edge_points(graph, edge, default_size).map(|(points, curved)| (edge, points, curved)))
+        .collect();
+
+    let padding = options.base.padding as f64;
+    let scale = options.scale;
+    let (min_x, min_y, max_x, max_y) = bounds(graph, &edges, default_size).unwrap_or((0.0, 0.0, 0.0, 0.0));
+    let map = |(x, y): (f64, f64)| ((x - min_x) * scale + padding, (y - min_y) * scale + padding);
+    let (width, height) = ((max_x - min_x) * scale + 2.0 * padding, (max_y - min_y) * scale + 2.0 * padding);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" font-family=\"sans-serif\" font-size=\"{}\">",
+        number(options.font_size), w = number(width), h = number(height)
+    );
+    if options.arrows {
+        let _ = writeln!(
+            out,
+            "  <defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"6\" markerHeight=\"6\" orient=\"auto-start-reverse\"><path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"{}\"/></marker></defs>",
+            escape_xml(&options.edge_color)
+        );
+    }
+
+    let style = |node: &Node| -> &SvgNodeStyle { options.type_styles.get(&node.r#type).unwrap_or(&options.node_style) };
+    let label = |node: &Node| if node.label.is_empty() { node.id.clone() } else { node.label.clone() };
+    let open_group = |out: &mut String, node: &Node| {
+        let kind = if node.r#type.is_empty() { String::new() } else { format!(" data-type=\"{}\"", escape_xml(&node.r#type)) };
+        let _ = writeln!(out, "  <g class=\"node\" data-id=\"{}\"{}>", escape_xml(&node.id), kind);
+    };
+    let mut positioned: Vec<&Node> = graph.sorted_nodes().into_iter().map(|(_, node)| node).filter(|node| node.position.is_some()).collect();
+    positioned.sort_by_key(|node| hierarchy.depth(&node.id));
+
+    // Compound nodes, outermost first, with their label along the top
+    for node in positioned.iter().filter(|node| hierarchy.is_compound(&node.id)) {
+        let node_style = style(node);
+        let (x, y) = map(node.position.unwrap_or_default());
+        let (w, h) = node.size_or(default_size);
+        let (w, h) = (w * scale, h * scale);
+        open_group(&mut out, node);
+        let _ = writeln!(
+            out,
+            "    <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\" fill-opacity=\"0.3\" stroke=\"{}\" stroke-width=\"{}\"/>",
+            number(x - w / 2.0), number(y - h / 2.0), number(w), number(h),
+            escape_xml(&node_style.fill), escape_xml(&node_style.stroke), number(node_style.stroke_width)
+        );
+        if options.show_labels {
+            let _ = writeln!(
+                out,
+                "    <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"{}\">{}</text>",
+                number(x), number(y - h / 2.0 + options.font_size), escape_xml(&node_style.label_color), escape_xml(&label(node))
+            );
+        }
+        out.push_str("  </g>\n");
+    }
+
+    for (edge, points, curved) in &edges {
+        let points: Vec<(f64, f64)> = points.iter().map(|&point| map(point)).collect();
+        let mut path = format!("M {} {}", number(points[0].0), number(points[0].1));
+        // Splines need three points per cubic segment; draw anything else as lines
+        if *curved && (points.len() - 1).is_multiple_of(3) {
+            for segment in points[1..].chunks(3) {
+                path.push_str(" C");
+                for (x, y) in segment {
+                    let _ = write!(path, " {} {}", number(*x), number(*y));
+                }
+            }
+        } else {
+            for (x, y) in &points[1..] {
+                let _ = write!(path, " L {} {}", number(*x), number(*y));
+            }
+        }
+        let arrow = if options.arrows { " marker-end=\"url(#arrow)\"" } else { "" };
+        let _ = writeln!(
+            out,
+            "  <path class=\"edge\" data-id=\"{}\" d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"{}/>",
+            escape_xml(&edge.id), path, escape_xml(&options.edge_color), number(options.edge_width), arrow
+        );
+    }
+
+    for node in positioned.iter().filter(|node| !hierarchy.is_compound(&node.id)) {
+        let node_style = style(node);
+        let (x, y) = map(node.position.unwrap_or_default());
+        let (w, h) = node.size_or(default_size);
+        let (rx, ry) = (w * scale / 2.0, h * scale / 2.0);
+        let paint = format!(
+            "fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\"",
+            escape_xml(&node_style.fill), escape_xml(&node_style.stroke), number(node_style.stroke_width)
+        );
+        open_group(&mut out, node);
+        let polygon = |corners: &[(f64, f64)]| {
+            let points: Vec<String> = corners.iter().map(|(dx, dy)| format!("{},{}", number(x + dx * rx), number(y + dy * ry))).collect();
+            format!("    <polygon points=\"{}\" {}/>", points.join(" "), paint)
+        };
+        let shape = match node.shape {
+            NodeShape::Rectangle => format!(
+                "    <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                number(x - rx), number(y - ry), number(2.0 * rx), number(2.0 * ry), paint
+            ),
+            NodeShape::Ellipse | NodeShape::Circle => format!(
+                "    <ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" {}/>",
+                number(x), number(y), number(rx), number(ry), paint
+            ),
+            NodeShape::Diamond => polygon(&[(0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)]),
+            NodeShape::Triangle => polygon(&[(0.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]),
+            NodeShape::Hexagon => polygon(&[(-1.0, 0.0), (-0.5, -1.0), (0.5, -1.0), (1.0, 0.0), (0.5, 1.0), (-0.5, 1.0)]),
+        };
+        out.push_str(&shape);
+        out.push('\n');
+        if options.show_labels {
+            let _ = writeln!(
+                out,
+                "    <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" fill=\"{}\">{}</text>",
+                number(x), number(y), escape_xml(&node_style.label_color), escape_xml(&label(node))
+            );
+        }
+        out.push_str("  </g>\n");
+    }
+
+    out.push_str("</svg>\n");
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EdgeRoute;
+    use crate::layout::{apply_layout, available_layouts};
+    use crate::file_parsers::parse_graph_file;
+
+    #[test]
+    fn test_shapes_routes_and_styles() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_position(0.0, 0.0).with_size(20.0, 10.0).with_shape(NodeShape::Rectangle))
+             .add_node(Node::new("b").with_position(100.0, 50.0).with_shape(NodeShape::Diamond))
+             .add_node(Node::new("loose"));
+        graph.nodes.get_mut("b").unwrap().r#type = "db".to_string();
+        graph.nodes.get_mut("b").unwrap().label = "B & co".to_string();
+        let mut edge = Edge::new("e1", "a", "b");
+        edge.route = Some(EdgeRoute { style: RouteStyle::Spline, points: vec![(10.0, 0.0), (50.0, 0.0), (50.0, 50.0), (90.0, 50.0)] });
+        graph.add_edge(edge);
+        let mut edge = Edge::new("e2", "b", "a");
+        edge.route = Some(EdgeRoute { style: RouteStyle::Spline, points: vec![(90.0, 50.0), (50.0, 25.0), (10.0, 0.0)] });
+        graph.add_edge(edge);
+
+        let mut options = SvgOptions::default();
+        options.base.padding = 10;
+        options.scale = 2.0;
+        options.type_styles.insert("db".to_string(), SvgNodeStyle { fill: "#ffcc00".to_string(), ..SvgNodeStyle::default() });
+        let svg = write_svg(&graph, &options).unwrap();
+
+        // Drawing spans x -10..110 and y -5..60, doubled, plus 10 on each side
+        assert!(svg.contains("width=\"260\" height=\"150\""), "{}", svg);
+        assert!(svg.contains("<rect x=\"10\" y=\"10\" width=\"40\" height=\"20\""));
+        assert!(svg.contains("d=\"M 50 20 C 130 20 130 120 210 120\""));
+        assert!(svg.contains("d=\"M 210 120 L 130 70 L 50 20\""), "{}", svg);
+        assert!(svg.contains("<polygon points=\"230,100 250,120 230,140 210,120\" fill=\"#ffcc00\""));
+        assert!(svg.contains(">B &amp; co</text>"));
+        assert!(svg.contains(">a</text>"));
+        assert!(!svg.contains("data-id=\"loose\""));
+    }
+
+    #[test]
+    fn test_every_layout_of_a_sample_draws_every_node() {
+        let path = format!("{}/docs/sample/dagre_layout.json", env!("CARGO_MANIFEST_DIR"));
+        let content = std::fs::read_to_string(path).unwrap();
+        for layout in available_layouts() {
+            let mut graph = parse_graph_file(&content, "json").unwrap();
+            apply_layout(&mut graph, &layout).unwrap();
+            let svg = write_svg(&graph, &SvgOptions::default()).unwrap();
+            assert_eq!(svg.matches("class=\"node\"").count(), graph.nodes.len(), "{}", layout.id());
+            assert_eq!(svg.matches("class=\"edge\"").count(), graph.edges.len(), "{}", layout.id());
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::types::{Graph, CiseLayoutOptions};
 use crate::layout::traits::{LayoutEngine, CircularLayout};
+use crate::layout::parallel_edges::{restore_parallel_edges, take_parallel_edges};
+use crate::layout::routing::route_edges;
 
 pub struct CiseLayoutEngine {
     options: CiseLayoutOptions,
@@ -13,12 +15,19 @@ impl CiseLayoutEngine {
 
 impl LayoutEngine for CiseLayoutEngine {
     fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
+        graph.clear_bend_points();
+        let parallel = take_parallel_edges(graph, self.options.base.merge_edges);
+        
         // Step 1: Arrange nodes in clusters on circles
         self.arrange_clusters(graph)?;
         
         // Step 2: Optimize node ordering to minimize edge crossings
         self.optimize_ordering(graph)?;
         
+        // Edge paths for drawing, with merged edges drawn like the edge they were merged into
+        restore_parallel_edges(graph, parallel);
+        route_edges(graph, &self.options.base, "SPLINES");
+        
         Ok(())
     }
     
@@ -40,7 +49,7 @@ impl CircularLayout for CiseLayoutEngine {
         
         let angle_step = 2.0 * std::f64::consts::PI / node_count as f64;
         
-        for (i, node) in graph.nodes.values_mut().enumerate() {
+        for (i, (_, node)) in graph.sorted_nodes_mut().into_iter().enumerate() {
             let angle = angle_step * i as f64;
             let x = radius * angle.cos();
             let y = radius * angle.sin();
@@ -56,7 +65,7 @@ impl CircularLayout for CiseLayoutEngine {
         // to minimize edge crossings
         
         // For now, we'll just sort nodes by their degree
-        let mut node_degrees: Vec<(String, usize)> = graph.nodes.keys()
+        let mut node_degrees: Vec<(String, usize)> = graph.sorted_node_ids().into_iter()
             .map(|id| {
                 let degree = graph.edges.values()
                     .filter(|e| e.source == *id || e.target == *id)
@@ -132,7 +141,7 @@ impl CiseLayoutEngine {
         }
         
         // Handle nodes not in any cluster
-        let unclustered = graph.nodes.keys()
+        let unclustered = graph.sorted_node_ids().into_iter()
             .filter(|id| !self.options.clusters.iter().any(|cluster| cluster.contains(id)))
             .cloned()
             .collect::<Vec<_>>();
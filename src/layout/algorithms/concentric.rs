@@ -1,5 +1,7 @@
 use crate::types::{Graph, ConcentricLayoutOptions};
 use crate::layout::traits::{LayoutEngine, HierarchicalLayout};
+use crate::layout::parallel_edges::{restore_parallel_edges, take_parallel_edges};
+use crate::layout::routing::route_edges;
 
 pub struct ConcentricLayoutEngine {
     options: ConcentricLayoutOptions,
@@ -13,12 +15,19 @@ impl ConcentricLayoutEngine {
 
 impl LayoutEngine for ConcentricLayoutEngine {
     fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
+        graph.clear_bend_points();
+        let parallel = take_parallel_edges(graph, self.options.base.merge_edges);
+        
         // Step 1: Assign nodes to levels based on the concentric_by property
         let levels = self.assign_levels(graph)?;
         
         // Step 2: Position nodes in concentric circles
         self.position_nodes(graph, &levels)?;
         
+        // Edge paths for drawing, with merged edges drawn like the edge they were merged into
+        restore_parallel_edges(graph, parallel);
+        route_edges(graph, &self.options.base, "STRAIGHT");
+        
         Ok(())
     }
     
@@ -45,7 +54,7 @@ impl HierarchicalLayout for ConcentricLayoutEngine {
         match self.options.concentric_by.as_str() {
             "degree" => {
                 // Calculate node degrees
-                let mut node_degrees: Vec<(String, usize)> = graph.nodes.keys()
+                let mut node_degrees: Vec<(String, usize)> = graph.sorted_node_ids().into_iter()
                     .map(|id| {
                         let degree = graph.edges.values()
                             .filter(|e| e.source == *id || e.target == *id)
@@ -54,8 +63,8 @@ impl HierarchicalLayout for ConcentricLayoutEngine {
                     })
                     .collect();
                 
-                // Sort by degree
-                node_degrees.sort_by_key(|(_, degree)| *degree);
+                // Sort by degree, highest degree in the innermost level
+                node_degrees.sort_by_key(|(_, degree)| std::cmp::Reverse(*degree));
                 
                 // Group by degree
                 let mut current_degree = None;
@@ -82,7 +91,7 @@ impl HierarchicalLayout for ConcentricLayoutEngine {
             }
             "id" => {
                 // Simple level assignment based on node IDs
-                levels.push(graph.nodes.keys().cloned().collect());
+                levels.push(graph.sorted_node_ids().into_iter().cloned().collect());
             }
             _ => return Err(format!("Unsupported concentric_by value: {}", self.options.concentric_by)),
         }
@@ -144,8 +153,10 @@ mod tests {
             graph.add_edge(edge);
         }
         
-        let mut options = ConcentricLayoutOptions::default();
-        options.concentric_by = "degree".to_string();
+        let options = ConcentricLayoutOptions {
+            concentric_by: "degree".to_string(),
+            ..Default::default()
+        };
         
         let engine = ConcentricLayoutEngine::new(options);
         let levels = engine.assign_levels(&graph).unwrap();
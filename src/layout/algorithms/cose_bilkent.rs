@@ -1,5 +1,10 @@
-use crate::types::{Graph, CoseBilkentLayoutOptions};
-use crate::layout::traits::{LayoutEngine, ForceDirectedLayout};
+use crate::types::{Graph, CoseBilkentLayoutOptions, LayoutTick};
+use crate::layout::traits::{LayoutEngine, ForceDirectedLayout, LayoutSimulation};
+use crate::layout::compound::{Hierarchy, leaf_repulsion};
+use crate::layout::parallel_edges::{restore_parallel_edges, take_parallel_edges, ParallelEdges};
+use crate::layout::routing::route_edges;
+use crate::layout::session::run_to_completion;
+use rand::Rng;
 
 pub struct CoseBilkentLayoutEngine {
     options: CoseBilkentLayoutOptions,
@@ -11,36 +16,100 @@ impl CoseBilkentLayoutEngine {
     }
 }
 
-impl LayoutEngine for CoseBilkentLayoutEngine {
-    fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
+impl CoseBilkentLayoutEngine {
+    /// Set up a layout run: initial positions and merged edges
+    pub fn start(&self, graph: &mut Graph) -> Result<CoseBilkentSimulation, String> {
+        let hierarchy = Hierarchy::new(graph)?;
+        let padding = self.options.base.compound_padding;
+        graph.clear_bend_points();
+        let parallel = take_parallel_edges(graph, self.options.base.merge_edges);
+        
         // Initialize node positions if not already set
-        self.initialize_positions(graph);
+        self.initialize_positions(graph, &mut self.options.base.rng());
+        hierarchy.fit_bounds(graph, 0.0, padding);
         
-        // Run the force-directed algorithm for a fixed number of iterations
-        let max_iterations = 50;
-        for _ in 0..max_iterations {
-            // Calculate repulsive forces between all pairs of nodes
-            let repulsion_forces = self.calculate_repulsion(graph);
-            
-            // Calculate attractive forces along edges
-            let attraction_forces = self.calculate_attraction(graph);
-            
-            // Combine forces
-            let mut combined_forces = vec![(0.0, 0.0); graph.nodes.len()];
-            for i in 0..graph.nodes.len() {
-                combined_forces[i] = (
-                    repulsion_forces[i].0 + attraction_forces[i].0,
-                    repulsion_forces[i].1 + attraction_forces[i].1
-                );
-            }
-            
-            // Apply forces to update node positions
-            self.apply_forces(graph, &combined_forces)?;
+        Ok(CoseBilkentSimulation {
+            engine: CoseBilkentLayoutEngine::new(self.options.clone()),
+            hierarchy,
+            parallel,
+            iteration: 0,
+        })
+    }
+}
+
+/// Iterations of every CoSE Bilkent run
+const MAX_ITERATIONS: usize = 50;
+
+/// Fraction of the net force applied as displacement in each iteration
+const DAMPING: f64 = 0.1;
+
+/// A CoSE Bilkent layout in progress, see `CoseBilkentLayoutEngine::start`
+pub struct CoseBilkentSimulation {
+    engine: CoseBilkentLayoutEngine,
+    hierarchy: Hierarchy,
+    parallel: ParallelEdges,
+    iteration: usize,
+}
+
+impl LayoutSimulation for CoseBilkentSimulation {
+    fn tick(&mut self, graph: &mut Graph) -> Result<LayoutTick, String> {
+        let engine = &self.engine;
+        
+        // Calculate repulsive forces between all pairs of nodes
+        let repulsion_forces = engine.calculate_repulsion(graph);
+        
+        // Calculate attractive forces along edges
+        let attraction_forces = engine.calculate_attraction(graph);
+        
+        // Combine forces
+        let mut combined_forces = vec![(0.0, 0.0); graph.nodes.len()];
+        for i in 0..graph.nodes.len() {
+            combined_forces[i] = (
+                repulsion_forces[i].0 + attraction_forces[i].0,
+                repulsion_forces[i].1 + attraction_forces[i].1
+            );
+        }
+        
+        // Move compound nodes through their children and keep children near their parents
+        if self.hierarchy.has_compounds() {
+            self.hierarchy.apply_compound_forces(graph, &mut combined_forces, engine.options.compound_gravity);
         }
         
+        // Apply forces to update node positions and resize compound nodes
+        engine.apply_forces(graph, &combined_forces)?;
+        self.hierarchy.fit_bounds(graph, 0.0, engine.options.base.compound_padding);
+        
+        self.iteration += 1;
+        Ok(LayoutTick {
+            iteration: self.iteration,
+            energy: combined_forces.iter().map(|(x, y)| x * x + y * y).sum(),
+            temperature: 1.0,
+            max_displacement: combined_forces.iter().map(|(x, y)| (x * x + y * y).sqrt() * DAMPING).fold(0.0, f64::max),
+        })
+    }
+    
+    fn is_converged(&self) -> bool {
+        self.iteration >= MAX_ITERATIONS
+    }
+    
+    fn finish(self: Box<Self>, graph: &mut Graph) -> Result<(), String> {
+        // Edge paths for drawing, with merged edges drawn like the edge they were merged into
+        restore_parallel_edges(graph, self.parallel);
+        route_edges(graph, &self.engine.options.base, "SPLINES");
+        
         Ok(())
     }
     
+    fn cancel(self: Box<Self>, graph: &mut Graph) {
+        restore_parallel_edges(graph, self.parallel);
+    }
+}
+
+impl LayoutEngine for CoseBilkentLayoutEngine {
+    fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
+        run_to_completion(Box::new(self.start(graph)?), graph)
+    }
+    
     fn name(&self) -> &'static str {
         "CoSE Bilkent"
     }
@@ -52,47 +121,8 @@ impl LayoutEngine for CoseBilkentLayoutEngine {
 
 impl ForceDirectedLayout for CoseBilkentLayoutEngine {
     fn calculate_repulsion(&self, graph: &Graph) -> Vec<(f64, f64)> {
-        let node_count = graph.nodes.len();
-        let mut forces = vec![(0.0, 0.0); node_count];
-        let node_repulsion = self.options.node_repulsion;
-        
-        // Get node positions as a vector for easier indexing
-        let nodes: Vec<(&String, &crate::types::Node)> = graph.nodes.iter().collect();
-        
-        // Calculate repulsive forces between all pairs of nodes
-        for i in 0..node_count {
-            let (id_i, node_i) = nodes[i];
-            let pos_i = node_i.position.unwrap_or((0.0, 0.0));
-            
-            for j in 0..node_count {
-                if i == j { continue; }
-                
-                let (_, node_j) = nodes[j];
-                let pos_j = node_j.position.unwrap_or((0.0, 0.0));
-                
-                // Calculate distance between nodes
-                let dx = pos_i.0 - pos_j.0;
-                let dy = pos_i.1 - pos_j.1;
-                let distance_squared = dx * dx + dy * dy;
-                
-                // Avoid division by zero
-                if distance_squared < 0.1 {
-                    continue;
-                }
-                
-                // Calculate repulsive force (inverse square law)
-                let force = node_repulsion / distance_squared;
-                
-                // Calculate force components
-                let force_x = force * dx / distance_squared.sqrt();
-                let force_y = force * dy / distance_squared.sqrt();
-                
-                // Add to total forces for node i
-                forces[i] = (forces[i].0 + force_x, forces[i].1 + force_y);
-            }
-        }
-        
-        forces
+        // Repulsion between leaf nodes, in ascending ID order
+        leaf_repulsion(graph, self.options.theta, self.options.node_repulsion)
     }
     
     fn calculate_attraction(&self, graph: &Graph) -> Vec<(f64, f64)> {
@@ -101,14 +131,14 @@ impl ForceDirectedLayout for CoseBilkentLayoutEngine {
         let ideal_edge_length = self.options.ideal_edge_length;
         
         // Get node positions and create a map from ID to index
-        let nodes: Vec<(&String, &crate::types::Node)> = graph.nodes.iter().collect();
+        let nodes: Vec<(&String, &crate::types::Node)> = graph.sorted_nodes();
         let mut id_to_index = std::collections::HashMap::new();
         for (i, (id, _)) in nodes.iter().enumerate() {
             id_to_index.insert(*id, i);
         }
         
         // Calculate attractive forces along edges
-        for edge in graph.edges.values() {
+        for edge in graph.sorted_edges() {
             if let (Some(&source_idx), Some(&target_idx)) = (id_to_index.get(&edge.source), id_to_index.get(&edge.target)) {
                 let source_pos = nodes[source_idx].1.position.unwrap_or((0.0, 0.0));
                 let target_pos = nodes[target_idx].1.position.unwrap_or((0.0, 0.0));
@@ -141,7 +171,7 @@ impl ForceDirectedLayout for CoseBilkentLayoutEngine {
     
     fn apply_forces(&self, graph: &mut Graph, forces: &[(f64, f64)]) -> Result<(), String> {
         // Get mutable references to nodes
-        let mut nodes: Vec<(&String, &mut crate::types::Node)> = graph.nodes.iter_mut().collect();
+        let mut nodes: Vec<(&String, &mut crate::types::Node)> = graph.sorted_nodes_mut();
         
         // Apply forces to update positions
         for (i, (_, node)) in nodes.iter_mut().enumerate() {
@@ -153,9 +183,8 @@ impl ForceDirectedLayout for CoseBilkentLayoutEngine {
             let current_pos = node.position.unwrap_or((0.0, 0.0));
             
             // Update position with damping
-            let damping = 0.1;
-            let new_x = current_pos.0 + force_x * damping;
-            let new_y = current_pos.1 + force_y * damping;
+            let new_x = current_pos.0 + force_x * DAMPING;
+            let new_y = current_pos.1 + force_y * DAMPING;
             
             node.position = Some((new_x, new_y));
         }
@@ -166,14 +195,14 @@ impl ForceDirectedLayout for CoseBilkentLayoutEngine {
 
 impl CoseBilkentLayoutEngine {
     /// Initialize random positions for nodes that don't have positions
-    fn initialize_positions(&self, graph: &mut Graph) {
+    fn initialize_positions(&self, graph: &mut Graph, rng: &mut impl Rng) {
         let radius = 100.0;
         
-        for node in graph.nodes.values_mut() {
+        for (_, node) in graph.sorted_nodes_mut() {
             if node.position.is_none() {
                 // Generate random angle and distance from center
-                let angle = rand::random::<f64>() * 2.0 * std::f64::consts::PI;
-                let distance = rand::random::<f64>() * radius;
+                let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+                let distance = rng.gen::<f64>() * radius;
                 
                 // Convert to Cartesian coordinates
                 let x = distance * angle.cos();
@@ -189,4 +218,63 @@ impl CoseBilkentLayoutEngine {
 pub fn apply_layout(graph: &mut Graph, options: &CoseBilkentLayoutOptions) -> Result<(), String> {
     let engine = CoseBilkentLayoutEngine::new(options.clone());
     engine.apply_layout(graph)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Node, Edge, LayoutOptions};
+    use crate::layout::compound::test_support::{assert_compounds_contain_children, compound_graph};
+
+    #[test]
+    fn test_positions_assigned() {
+        let mut graph = Graph::new();
+        
+        for i in 0..4 {
+            graph.add_node(Node::new(format!("node{}", i)));
+        }
+        
+        for i in 0..3 {
+            let edge = Edge::new(
+                format!("edge{}", i),
+                format!("node{}", i),
+                format!("node{}", i + 1),
+            );
+            graph.add_edge(edge);
+        }
+        
+        let engine = CoseBilkentLayoutEngine::new(CoseBilkentLayoutOptions::default());
+        engine.apply_layout(&mut graph).unwrap();
+        
+        for node in graph.nodes.values() {
+            let pos = node.position.unwrap();
+            assert!(pos.0.is_finite() && pos.1.is_finite());
+        }
+    }
+
+    #[test]
+    fn test_repulsion_pushes_nodes_apart() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("A").with_position(0.0, 0.0))
+             .add_node(Node::new("B").with_position(10.0, 0.0));
+        
+        let engine = CoseBilkentLayoutEngine::new(CoseBilkentLayoutOptions::default());
+        let forces = engine.calculate_repulsion(&graph);
+        
+        // Forces on the two nodes are equal and opposite
+        assert!((forces[0].0 + forces[1].0).abs() < 1e-9);
+        assert!(forces[0].0.abs() > 0.0);
+    }
+
+    #[test]
+    fn test_compound_nodes_fit_children() {
+        let mut graph = compound_graph();
+        let options = CoseBilkentLayoutOptions {
+            base: LayoutOptions { seed: Some(7), ..LayoutOptions::default() },
+            ..CoseBilkentLayoutOptions::default()
+        };
+        apply_layout(&mut graph, &options).unwrap();
+        
+        assert_compounds_contain_children(&graph, 0.0);
+    }
+}
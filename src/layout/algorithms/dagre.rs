@@ -1,6 +1,13 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use crate::types::{Graph, DagreLayoutOptions};
 use crate::layout::traits::{LayoutEngine, LayeredLayout};
+use crate::layout::compound::Hierarchy;
+use crate::layout::ordering::{count_crossings, interactive_order, layer_sweep};
+use crate::layout::long_edges::{dummy_ids, edge_sources, join_long_edges, restore_edge_directions, split_long_edges};
+use crate::layout::placement::{place_nodes, NodePlacement};
+use crate::layout::ranking::{rank_layers, Ranker};
+use crate::layout::parallel_edges::{restore_parallel_edges, take_parallel_edges};
+use crate::layout::routing::{route_edges, self_loop_room};
 
 /// Dagre layout engine implementation
 pub struct DagreLayoutEngine {
@@ -16,19 +23,19 @@ impl DagreLayoutEngine {
 
 impl LayoutEngine for DagreLayoutEngine {
     fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
-        // Step 1: Assign nodes to ranks (layers)
-        let mut layers = self.assign_layers(graph)?;
+        let hierarchy = Hierarchy::new(graph)?;
+        graph.clear_bend_points();
+        let parallel = take_parallel_edges(graph, self.options.base.merge_edges);
         
-        // Step 2: Break cycles if needed (if acyclic option is enabled)
-        if self.options.acyclic {
-            self.break_cycles(graph, &mut layers)?;
-        }
+        // Lay out the inside of each compound node first, then place it as a single
+        // node among its siblings; the top level comes last
+        hierarchy.layout_levels(graph, self.options.base.compound_padding, |level| self.layout_level(level))?;
         
-        // Step 3: Order nodes within ranks to minimize crossings
-        self.minimize_crossings(&mut layers, graph)?;
+        // Edge paths for drawing, with merged edges drawn like the edge they were merged into
+        restore_parallel_edges(graph, parallel);
+        route_edges(graph, &self.options.base, "SPLINES");
         
-        // Step 4: Assign coordinates based on rank and position
-        self.assign_coordinates(graph, &layers)
+        Ok(())
     }
     
     fn name(&self) -> &'static str {
@@ -77,291 +84,135 @@ impl LayeredLayout for DagreLayoutEngine {
     }
     
     fn minimize_crossings(&self, layers: &mut Vec<Vec<String>>, graph: &Graph) -> Result<(), String> {
-        // For each pair of adjacent layers
-        for i in 0..layers.len().saturating_sub(1) {
-            let mut improved = true;
-            
-            // Keep trying to improve until no more improvements can be made
-            while improved {
-                improved = false;
-                
-                // Clone the current layer for comparison
-                let current_layer = layers[i].clone();
-                
-                // Get mutable reference to the next layer
-                let next_layer = &mut layers[i + 1];
-                
-                // Count crossings between current positions
-                let mut best_crossings = self.count_crossings(&current_layer, next_layer, graph);
-                
-                // Try swapping adjacent nodes in the next layer
-                for j in 0..next_layer.len().saturating_sub(1) {
-                    next_layer.swap(j, j + 1);
-                    
-                    let new_crossings = self.count_crossings(&current_layer, next_layer, graph);
-                    if new_crossings < best_crossings {
-                        best_crossings = new_crossings;
-                        improved = true;
-                    } else {
-                        // Swap back if no improvement
-                        next_layer.swap(j, j + 1);
-                    }
-                }
+        match self.options.cross_minimization.as_str() {
+            "INTERACTIVE" => {
+                // Keep the order the nodes already have across the ranks
+                let is_horizontal = self.options.rank_direction == "LR" || self.options.rank_direction == "RL";
+                interactive_order(layers, graph, if is_horizontal { 1 } else { 0 });
             }
+            _ => layer_sweep(layers, graph), // "LAYER_SWEEP"
         }
         
         Ok(())
     }
     
     fn count_crossings(&self, layer1: &[String], layer2: &[String], graph: &Graph) -> usize {
-        let mut crossings = 0;
-        
-        // For each pair of edges between the layers
-        for (i1, n1) in layer1.iter().enumerate() {
-            for (i2, n2) in layer1.iter().enumerate().skip(i1 + 1) {
-                for edge1 in graph.edges.values() {
-                    if edge1.source != *n1 { continue; }
-                    
-                    for edge2 in graph.edges.values() {
-                        if edge2.source != *n2 { continue; }
-                        
-                        let j1 = layer2.iter().position(|n| *n == edge1.target);
-                        let j2 = layer2.iter().position(|n| *n == edge2.target);
-                        
-                        if let (Some(j1), Some(j2)) = (j1, j2) {
-                            // Check if edges cross
-                            if (i1 < i2 && j1 > j2) || (i1 > i2 && j1 < j2) {
-                                crossings += 1;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        crossings
+        count_crossings(layer1, layer2, graph)
     }
 }
 
 impl DagreLayoutEngine {
-    /// Assign coordinates to nodes based on their layer and position
-    fn assign_coordinates(&self, graph: &mut Graph, layers: &[Vec<String>]) -> Result<(), String> {
-        let is_horizontal = self.options.rank_direction == "LR" || self.options.rank_direction == "RL";
-        let is_reversed = self.options.rank_direction == "BT" || self.options.rank_direction == "RL";
+    /// Lay out a graph without compound nodes
+    fn layout_level(&self, graph: &mut Graph) -> Result<(), String> {
+        let sources = edge_sources(graph);
         
-        let rank_separation = self.options.rank_separation;
-        let node_separation = self.options.node_separation;
+        // Step 1: Assign nodes to ranks (layers)
+        let mut layers = self.assign_layers(graph)?;
         
-        // Assign coordinates based on rank direction
-        for (layer_idx, layer) in layers.iter().enumerate() {
-        let layer_pos = if is_reversed && layers.len() > 0 {
-            // Ensure we don't underflow when calculating the reversed position
-            if layer_idx < layers.len() {
-                let reversed_idx = layers.len() - 1 - layer_idx;
-                reversed_idx as f64 * rank_separation
-            } else {
-                0.0 // Default position if layer_idx is out of bounds
-            }
-        } else {
-            layer_idx as f64 * rank_separation
-        };
-            
-            // Assign positions within layer
-            let layer_width = if layer.len() > 0 {
-                (layer.len() - 1) as f64 * node_separation
-            } else {
-                0.0
-            };
-            let start_pos = -layer_width / 2.0;
-            
-            for (node_idx, node_id) in layer.iter().enumerate() {
-                if let Some(node) = graph.nodes.get_mut(node_id) {
-                    let node_pos = start_pos + node_idx as f64 * node_separation;
-                    
-                    // Set position based on rank direction
-                    if is_horizontal {
-                        node.position = Some((layer_pos, node_pos));
-                    } else {
-                        node.position = Some((node_pos, layer_pos));
-                    }
-                }
-            }
+        // Step 2: Break cycles if needed (if acyclic option is enabled)
+        if self.options.acyclic {
+            self.break_cycles(graph, &mut layers)?;
         }
         
+        // Step 3: Route edges spanning several ranks through a dummy node per rank
+        let long_edges = split_long_edges(graph, &mut layers);
+        
+        // Step 4: Order nodes within ranks to minimize crossings
+        self.minimize_crossings(&mut layers, graph)?;
+        
+        // Step 5: Assign coordinates based on rank and position
+        self.assign_coordinates(graph, &layers, &dummy_ids(&long_edges))?;
+        
+        // Dummy positions become bend points of the original edges
+        join_long_edges(graph, &mut layers, long_edges);
+        restore_edge_directions(graph, &sources);
         Ok(())
     }
     
-    /// Longest path ranking algorithm
-    fn longest_path_ranking(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String> {
-        let mut layers: Vec<Vec<String>> = Vec::new();
-        let mut assigned = HashSet::new();
+    /// Assign coordinates to nodes based on their layer and position.
+    ///
+    /// Separations are measured between node borders, using each node's width and
+    /// height (unsized nodes count as points). Positions within ranks come from the
+    /// `node_placement` strategy.
+    fn assign_coordinates(&self, graph: &mut Graph, layers: &[Vec<String>], dummies: &HashSet<String>) -> Result<(), String> {
+        let is_horizontal = self.options.rank_direction == "LR" || self.options.rank_direction == "RL";
+        let is_reversed = self.options.rank_direction == "BT" || self.options.rank_direction == "RL";
         
-        // Find root nodes (nodes with no incoming edges)
-        let mut roots: Vec<String> = graph.nodes.keys()
-            .filter(|node_id| !graph.edges.values().any(|e| e.target == **node_id))
-            .cloned()
-            .collect();
+        let rank_separation = self.options.rank_separation;
+        let node_separation = self.options.node_separation;
         
-        // If no root nodes found, start with any node
-        if roots.is_empty() && !graph.nodes.is_empty() {
-            roots.push(graph.nodes.keys().next().unwrap().clone());
-        }
+        // Node extent across ranks and along its rank
+        // Self-loops stick out to the right, so nodes with loops are widened on both sides
+        let loop_room = self_loop_room(graph, self.options.base.merge_edges);
+        let extent = |node_id: &String| -> (f64, f64) {
+            let (width, height) = graph.nodes.get(node_id).map(|n| n.size_or(0.0)).unwrap_or((0.0, 0.0));
+            let width = width + 2.0 * loop_room.get(node_id).copied().unwrap_or(0.0);
+            if is_horizontal { (width, height) } else { (height, width) }
+        };
         
-        // Assign initial nodes to layer 0
-        layers.push(roots.clone());
-        for root in &roots {
-            assigned.insert(root.clone());
+        // Stack ranks so that the tallest nodes of neighbouring ranks are rank_separation apart
+        let rank_depths: Vec<f64> = layers.iter()
+            .map(|layer| layer.iter().map(|id| extent(id).0).fold(0.0, f64::max))
+            .collect();
+        let mut rank_positions = Vec::with_capacity(layers.len());
+        for (layer_idx, depth) in rank_depths.iter().enumerate() {
+            let pos = match layer_idx {
+                0 => 0.0,
+                _ => rank_positions[layer_idx - 1] + rank_depths[layer_idx - 1] / 2.0 + rank_separation + depth / 2.0,
+            };
+            rank_positions.push(pos);
         }
-        
-        // Build subsequent layers
-        let mut current_layer = 0;
-        while current_layer < layers.len() {
-            let mut next_layer = Vec::new();
-            
-            for node_id in &layers[current_layer] {
-                // Find all unassigned nodes that this node points to
-                for edge in graph.edges.values() {
-                    if edge.source == *node_id && !assigned.contains(&edge.target) {
-                        next_layer.push(edge.target.clone());
-                        assigned.insert(edge.target.clone());
-                    }
-                }
+        if is_reversed {
+            let last = rank_positions.last().copied().unwrap_or(0.0);
+            for pos in &mut rank_positions {
+                *pos = last - *pos;
             }
-            
-            if !next_layer.is_empty() {
-                layers.push(next_layer);
-            }
-            
-            current_layer += 1;
         }
         
-        // Handle any remaining nodes (disconnected or in cycles)
-        let remaining: Vec<String> = graph.nodes.keys()
-            .filter(|node_id| !assigned.contains(*node_id))
-            .cloned()
-            .collect();
-        
-        if !remaining.is_empty() {
-            layers.push(remaining);
-        }
-        
-        Ok(layers)
-    }
-    
-    /// Network simplex ranking algorithm (simplified version)
-    fn network_simplex_ranking(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String> {
-        // For simplicity, we'll use a modified longest path algorithm
-        // A full network simplex implementation would be more complex
-        
-        // First, get initial ranking using longest path
-        let mut layers = self.longest_path_ranking(graph)?;
-        
-        // Then, try to optimize the ranking to minimize edge lengths
-        self.optimize_ranking(&mut layers, graph)?;
-        
-        Ok(layers)
-    }
-    
-    /// Tight tree ranking algorithm
-    fn tight_tree_ranking(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String> {
-        // Similar to longest path but with tighter constraints
-        let mut layers = self.longest_path_ranking(graph)?;
-        
-        // Try to make the tree more compact
-        self.compact_layers(&mut layers, graph)?;
+        // Place nodes within their ranks
+        let placement = match self.options.node_placement.as_str() {
+            "SIMPLE" => NodePlacement::Simple,
+            "LINEAR_SEGMENTS" => NodePlacement::LinearSegments,
+            _ => NodePlacement::BrandesKoepf, // "BRANDES_KOEPF"
+        };
+        let offsets = place_nodes(layers, graph, dummies, |id| extent(id).1, node_separation, placement);
         
-        Ok(layers)
-    }
-    
-    /// Optimize node ranking to minimize edge lengths
-    fn optimize_ranking(&self, layers: &mut Vec<Vec<String>>, graph: &Graph) -> Result<(), String> {
-        // Create a map of node to layer
-        let mut node_to_layer = HashMap::new();
+        let mut positions = Vec::new();
         for (layer_idx, layer) in layers.iter().enumerate() {
             for node_id in layer {
-                node_to_layer.insert(node_id.clone(), layer_idx);
+                let node_pos = offsets[node_id];
+                
+                // Set position based on rank direction
+                if is_horizontal {
+                    positions.push((node_id.clone(), (rank_positions[layer_idx], node_pos)));
+                } else {
+                    positions.push((node_id.clone(), (node_pos, rank_positions[layer_idx])));
+                }
             }
         }
         
-        // Try to move nodes to minimize edge lengths
-        let mut improved = true;
-        while improved {
-            improved = false;
-            
-            for layer_idx in 0..layers.len() {
-                let mut i = 0;
-                while i < layers[layer_idx].len() {
-                    let node_id = &layers[layer_idx][i];
-                    
-                    // Calculate current edge length sum
-                    let mut current_sum: usize = 0;
-                    for edge in graph.edges.values() {
-                        if edge.source == *node_id || edge.target == *node_id {
-                            let other_node = if edge.source == *node_id { &edge.target } else { &edge.source };
-                            if let Some(other_layer) = node_to_layer.get(other_node) {
-                                // Safely calculate the absolute difference to avoid overflow
-                                let diff = if layer_idx > *other_layer {
-                                    layer_idx - *other_layer
-                                } else {
-                                    *other_layer - layer_idx
-                                };
-                                current_sum = current_sum.saturating_add(diff);
-                            }
-                        }
-                    }
-                    
-                    // Try moving to adjacent layers
-                    for new_layer_idx in [layer_idx.saturating_sub(1), layer_idx + 1] {
-                        if new_layer_idx >= layers.len() {
-                            continue;
-                        }
-                        
-                        // Calculate new edge length sum if moved
-                        let mut new_sum: usize = 0;
-                        for edge in graph.edges.values() {
-                            if edge.source == *node_id || edge.target == *node_id {
-                                let other_node = if edge.source == *node_id { &edge.target } else { &edge.source };
-                                if let Some(other_layer) = node_to_layer.get(other_node) {
-                                    // Safely calculate the absolute difference to avoid overflow
-                                    let diff = if new_layer_idx > *other_layer {
-                                        new_layer_idx - *other_layer
-                                    } else {
-                                        *other_layer - new_layer_idx
-                                    };
-                                    new_sum = new_sum.saturating_add(diff);
-                                }
-                            }
-                        }
-                        
-                        // If moving improves the sum, do it
-                        if new_sum < current_sum {
-                            let node = layers[layer_idx].remove(i);
-                            layers[new_layer_idx].push(node.clone());
-                            node_to_layer.insert(node, new_layer_idx);
-                            improved = true;
-                            
-                            // Adjust index only if i > 0 to avoid underflow
-                            if i > 0 {
-                                i -= 1;
-                            }
-                            break;
-                        }
-                    }
-                    
-                    i += 1;
-                }
+        for (node_id, position) in positions {
+            if let Some(node) = graph.nodes.get_mut(&node_id) {
+                node.position = Some(position);
             }
         }
         
         Ok(())
     }
     
-    /// Make layers more compact
-    fn compact_layers(&self, layers: &mut Vec<Vec<String>>, _graph: &Graph) -> Result<(), String> {
-        // Remove empty layers
-        layers.retain(|layer| !layer.is_empty());
-        
-        Ok(())
+    /// Longest path ranking: every node as close to its successors as `minlen` allows
+    fn longest_path_ranking(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String> {
+        Ok(rank_layers(graph, Ranker::LongestPath))
+    }
+    
+    /// Network simplex ranking: minimises the total `weight * length` of the edges
+    fn network_simplex_ranking(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String> {
+        Ok(rank_layers(graph, Ranker::NetworkSimplex))
+    }
+    
+    /// Tight tree ranking: longest path, tightened along a spanning tree
+    fn tight_tree_ranking(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String> {
+        Ok(rank_layers(graph, Ranker::TightTree))
     }
 }
 
@@ -375,6 +226,7 @@ pub fn apply_layout(graph: &mut Graph, options: &DagreLayoutOptions) -> Result<(
 mod tests {
     use super::*;
     use crate::types::{Node, Edge};
+    use crate::layout::compound::test_support::{assert_compounds_contain_children, compound_graph};
 
     #[test]
     fn test_simple_chain() {
@@ -425,8 +277,10 @@ mod tests {
              .add_edge(edge2);
         
         // Create options with left-to-right direction
-        let mut options = DagreLayoutOptions::default();
-        options.rank_direction = "LR".to_string();
+        let options = DagreLayoutOptions {
+            rank_direction: "LR".to_string(),
+            ..Default::default()
+        };
         
         let engine = DagreLayoutEngine::new(options);
         engine.apply_layout(&mut graph).unwrap();
@@ -456,8 +310,10 @@ mod tests {
         graph.add_edge(edge1)
              .add_edge(edge2);
         
-        let mut options = DagreLayoutOptions::default();
-        options.acyclic = true;
+        let options = DagreLayoutOptions {
+            acyclic: true,
+            ..Default::default()
+        };
         
         let engine = DagreLayoutEngine::new(options);
         let mut layers = engine.assign_layers(&graph).unwrap();
@@ -478,4 +334,154 @@ mod tests {
         assert_eq!(forward_count + backward_count, 2);
         assert!(forward_count == 2 || backward_count == 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_wide_nodes_do_not_overlap() {
+        let mut graph = Graph::new();
+        
+        // Three wide labels in the same rank below a common root
+        graph.add_node(Node::new("root"));
+        for id in ["A", "B", "C"] {
+            graph.add_node(Node::new(id).with_size(120.0, 30.0));
+            graph.add_edge(Edge::new(format!("root-{}", id), "root", id));
+        }
+        
+        let options = DagreLayoutOptions::default();
+        let separation = options.node_separation;
+        let engine = DagreLayoutEngine::new(options);
+        engine.apply_layout(&mut graph).unwrap();
+        
+        let mut xs: Vec<f64> = ["A", "B", "C"].iter()
+            .map(|id| graph.nodes[*id].position.unwrap().0)
+            .collect();
+        xs.sort_by(f64::total_cmp);
+        
+        // Neighbouring centres are a full width plus the separation apart
+        for pair in xs.windows(2) {
+            assert!((pair[1] - pair[0] - (120.0 + separation)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_node_placement_strategies() {
+        // A chain hanging off the left of two siblings
+        let build = || {
+            let mut graph = Graph::new();
+            for id in ["root", "A", "B", "C", "D"] {
+                graph.add_node(Node::new(id).with_size(40.0, 20.0));
+            }
+            graph.add_edge(Edge::new("e1", "root", "A"))
+                 .add_edge(Edge::new("e2", "root", "B"))
+                 .add_edge(Edge::new("e3", "A", "C"))
+                 .add_edge(Edge::new("e4", "C", "D"));
+            graph
+        };
+        let x = |graph: &Graph, id: &str| graph.nodes[id].position.unwrap().0;
+        
+        // Brandes-Koepf keeps the chain straight
+        let mut graph = build();
+        DagreLayoutEngine::new(DagreLayoutOptions::default()).apply_layout(&mut graph).unwrap();
+        assert!((x(&graph, "A") - x(&graph, "C")).abs() < 1e-9);
+        assert!((x(&graph, "C") - x(&graph, "D")).abs() < 1e-9);
+        
+        // Simple placement centres every rank
+        let mut graph = build();
+        let options = DagreLayoutOptions { node_placement: "SIMPLE".to_string(), ..Default::default() };
+        DagreLayoutEngine::new(options).apply_layout(&mut graph).unwrap();
+        assert_eq!(x(&graph, "root"), 0.0);
+        assert_eq!(x(&graph, "C"), 0.0);
+        assert!((x(&graph, "A") + x(&graph, "B")).abs() < 1e-9);
+    }
+    
+    #[test]
+    fn test_compound_nodes_fit_children() {
+        let mut graph = compound_graph();
+        let engine = DagreLayoutEngine::new(DagreLayoutOptions::default());
+        engine.apply_layout(&mut graph).unwrap();
+        
+        assert_compounds_contain_children(&graph, 0.0);
+        
+        // Edges were laid out on a copy, so their direction is untouched
+        assert_eq!(graph.edges["between"].source, "g1-0");
+    }
+    
+    fn rank_of(layers: &[Vec<String>], id: &str) -> usize {
+        layers.iter().position(|layer| layer.iter().any(|n| n == id)).unwrap()
+    }
+
+    #[test]
+    fn test_ranking_honours_minlen_and_weight() {
+        // m sits between a (rank 0) and the end of a chain a -> b -> c -> d (rank 3);
+        // the heavier of its two edges is kept short
+        for (heavy_in, expected) in [(true, 1), (false, 2)] {
+            let mut graph = Graph::new();
+            for id in ["a", "b", "c", "d", "m"] {
+                graph.add_node(Node::new(id));
+            }
+            graph.add_edge(Edge::new("ab", "a", "b"))
+                 .add_edge(Edge::new("bc", "b", "c"))
+                 .add_edge(Edge::new("cd", "c", "d"))
+                 .add_edge(Edge::new("am", "a", "m").with_weight(if heavy_in { 5.0 } else { 1.0 }))
+                 .add_edge(Edge::new("md", "m", "d").with_weight(if heavy_in { 1.0 } else { 5.0 }));
+
+            for ranker in ["network-simplex", "tight-tree"] {
+                let engine = DagreLayoutEngine::new(DagreLayoutOptions { ranker: ranker.to_string(), ..Default::default() });
+                let layers = engine.assign_layers(&graph).unwrap();
+                assert_eq!(rank_of(&layers, "d"), 3, "{}", ranker);
+                if ranker == "network-simplex" {
+                    assert_eq!(rank_of(&layers, "m"), expected);
+                }
+            }
+        }
+
+        // A minlen of 2 leaves an empty rank, which shows up as twice the separation
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a")).add_node(Node::new("b")).add_node(Node::new("c"));
+        graph.add_edge(Edge::new("ab", "a", "b").with_minlen(2)).add_edge(Edge::new("bc", "b", "c"));
+        let options = DagreLayoutOptions::default();
+        DagreLayoutEngine::new(options.clone()).apply_layout(&mut graph).unwrap();
+        let y = |id: &str| graph.nodes[id].position.unwrap().1;
+        assert!((y("b") - y("a") - 2.0 * options.rank_separation).abs() < 1e-9);
+        assert!((y("c") - y("b") - options.rank_separation).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interactive_cross_minimization_keeps_order() {
+        let mut graph = Graph::new();
+        for (id, x) in [("a", 0.0), ("b", 100.0), ("c", 100.0), ("d", 0.0)] {
+            graph.add_node(Node::new(id).with_position(x, 0.0));
+        }
+        // Crossing edges: a layer sweep would swap c and d, INTERACTIVE keeps them
+        graph.add_edge(Edge::new("e1", "a", "c")).add_edge(Edge::new("e2", "b", "d"));
+        
+        let options = DagreLayoutOptions { cross_minimization: "INTERACTIVE".to_string(), ..Default::default() };
+        DagreLayoutEngine::new(options).apply_layout(&mut graph).unwrap();
+        let x = |graph: &Graph, id: &str| graph.nodes[id].position.unwrap().0;
+        assert!(x(&graph, "d") < x(&graph, "c"));
+        
+        DagreLayoutEngine::new(DagreLayoutOptions::default()).apply_layout(&mut graph).unwrap();
+        assert_eq!(x(&graph, "a") < x(&graph, "b"), x(&graph, "c") < x(&graph, "d"));
+    }
+
+    #[test]
+    fn test_reversed_long_edges_bend_from_source_to_target() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d"] {
+            graph.add_node(Node::new(id));
+        }
+        graph.add_edge(Edge::new("ab", "a", "b"))
+             .add_edge(Edge::new("bc", "b", "c"))
+             .add_edge(Edge::new("cd", "c", "d"))
+             .add_edge(Edge::new("da", "d", "a"));
+        
+        let options = DagreLayoutOptions { rank_direction: "LR".to_string(), ..Default::default() };
+        DagreLayoutEngine::new(options).apply_layout(&mut graph).unwrap();
+        
+        // The back edge keeps its direction and its bends run from d back towards a
+        let edge = &graph.edges["da"];
+        assert_eq!((edge.source.as_str(), edge.target.as_str()), ("d", "a"));
+        let x = |id: &str| graph.nodes[id].position.unwrap().0;
+        let bends: Vec<f64> = edge.bend_points.iter().map(|p| p.0).collect();
+        assert_eq!(bends, vec![x("c"), x("b")]);
+    }
+}
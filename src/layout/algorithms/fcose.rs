@@ -1,15 +1,61 @@
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
-use crate::types::{Graph, LayoutOptions};
-use crate::layout::{LayoutEngine, ForceDirectedLayout};
+use crate::types::{Graph, Id, LayoutOptions, LayoutTick, NodeShape};
+use crate::layout::{LayoutEngine, ForceDirectedLayout, LayoutSimulation};
+use crate::layout::compound::{Hierarchy, leaf_repulsion};
+use crate::layout::parallel_edges::{restore_parallel_edges, take_parallel_edges, ParallelEdges};
+use crate::layout::routing::route_edges;
+use crate::layout::session::run_to_completion;
+use rand::rngs::StdRng;
 use rand::Rng;
 
+/// Size of nodes without an explicit width or height
+const DEFAULT_NODE_SIZE: f64 = 20.0;
+
+/// Fraction of the net force applied as displacement in each iteration
+const APPLY_DAMPING: f64 = 0.1;
+
+/// Pins a node to a position
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixedNodeConstraint {
+    pub node_id: Id,
+    pub position: (f64, f64),
+}
+
+/// Groups of nodes sharing an x coordinate (`vertical`) or a y coordinate (`horizontal`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlignmentConstraint {
+    pub vertical: Vec<Vec<Id>>,
+    pub horizontal: Vec<Vec<Id>>,
+}
+
+/// Keeps `left` to the left of `right` (or `top` above `bottom`) with at least `gap`
+/// between their borders. Exactly one of the two pairs must be set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelativePlacementConstraint {
+    pub left: Option<Id>,
+    pub right: Option<Id>,
+    pub top: Option<Id>,
+    pub bottom: Option<Id>,
+    pub gap: Option<f64>,  // Defaults to the ideal edge length
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FcoseOptions {
     pub base: LayoutOptions,
     pub quality: String,         // "draft" or "default" or "proof"
     pub node_repulsion: f64,
     pub ideal_edge_length: f64,
     pub node_overlap: f64,
+    pub theta: f64,              // Barnes-Hut accuracy; 0 computes exact all-pairs repulsion
+    pub compound_gravity: f64,   // Pull of compound nodes on their children
+    pub fixed_node_constraint: Vec<FixedNodeConstraint>,
+    pub alignment_constraint: AlignmentConstraint,
+    pub relative_placement_constraint: Vec<RelativePlacementConstraint>,
+    pub randomize: bool,         // When false, refine existing positions incrementally
 }
 
 impl Default for FcoseOptions {
@@ -20,6 +66,12 @@ impl Default for FcoseOptions {
             node_repulsion: 4500.0,
             ideal_edge_length: 50.0,
             node_overlap: 10.0,
+            theta: 0.8,
+            compound_gravity: 0.1,
+            fixed_node_constraint: Vec::new(),
+            alignment_constraint: AlignmentConstraint::default(),
+            relative_placement_constraint: Vec::new(),
+            randomize: true,
         }
     }
 }
@@ -28,17 +80,202 @@ pub struct FcoseLayoutEngine {
     options: FcoseOptions,
 }
 
+/// Constraints checked against the graph, with node IDs resolved
+struct Constraints {
+    fixed: HashMap<Id, (f64, f64)>,
+    /// Alignment groups per axis (0: shared x, 1: shared y)
+    groups: [Vec<Vec<Id>>; 2],
+    /// Index into `groups[axis]` for every aligned node
+    group_of: [HashMap<Id, usize>; 2],
+    /// (before, after, axis, gap): `after` must follow `before` along `axis`
+    relative: Vec<(Id, Id, usize, f64)>,
+}
+
+impl Constraints {
+    fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.groups.iter().all(Vec::is_empty) && self.relative.is_empty()
+    }
+
+    /// The node with everything aligned with it along `axis`
+    fn members(&self, id: &Id, axis: usize) -> Vec<Id> {
+        match self.group_of[axis].get(id) {
+            Some(&group) => self.groups[axis][group].clone(),
+            None => vec![id.clone()],
+        }
+    }
+}
+
 impl FcoseLayoutEngine {
     pub fn new(options: FcoseOptions) -> Self {
         Self { options }
     }
 
+    /// Check constraint node IDs and resolve alignment groups and gaps
+    fn resolve_constraints(&self, graph: &Graph) -> Result<Constraints, String> {
+        let known = |id: &Id| -> Result<Id, String> {
+            if graph.nodes.contains_key(id) {
+                Ok(id.clone())
+            } else {
+                Err(format!("Constraint refers to unknown node {}", id))
+            }
+        };
+        
+        let mut fixed = HashMap::new();
+        for constraint in &self.options.fixed_node_constraint {
+            fixed.insert(known(&constraint.node_id)?, constraint.position);
+        }
+        
+        let mut groups: [Vec<Vec<Id>>; 2] = [Vec::new(), Vec::new()];
+        let mut group_of: [HashMap<Id, usize>; 2] = [HashMap::new(), HashMap::new()];
+        let alignment = &self.options.alignment_constraint;
+        for (axis, axis_groups) in [&alignment.vertical, &alignment.horizontal].into_iter().enumerate() {
+            for group in axis_groups {
+                let members = group.iter().map(known).collect::<Result<Vec<Id>, String>>()?;
+                for member in &members {
+                    if group_of[axis].insert(member.clone(), groups[axis].len()).is_some() {
+                        return Err(format!("Node {} is in more than one alignment group", member));
+                    }
+                }
+                groups[axis].push(members);
+            }
+        }
+        
+        let mut relative = Vec::new();
+        for constraint in &self.options.relative_placement_constraint {
+            let gap = constraint.gap.unwrap_or(self.options.ideal_edge_length);
+            match (&constraint.left, &constraint.right, &constraint.top, &constraint.bottom) {
+                (Some(left), Some(right), None, None) => relative.push((known(left)?, known(right)?, 0, gap)),
+                (None, None, Some(top), Some(bottom)) => relative.push((known(top)?, known(bottom)?, 1, gap)),
+                _ => return Err("Relative placement constraints need either left and right, or top and bottom".to_string()),
+            }
+        }
+        
+        Ok(Constraints { fixed, groups, group_of, relative })
+    }
+    
+    /// Project positions onto the constraints: fixed nodes are placed, aligned groups
+    /// share a coordinate and relative placements are pushed apart until they hold.
+    /// Aligned groups move as a unit, and a group containing a fixed node stays put.
+    fn enforce_constraints(&self, graph: &mut Graph, hierarchy: &Hierarchy, constraints: &Constraints) {
+        let position = |graph: &Graph, id: &Id| graph.nodes[id].position.unwrap_or((0.0, 0.0));
+        let coordinate = |pos: (f64, f64), axis: usize| if axis == 0 { pos.0 } else { pos.1 };
+        let shift = |graph: &mut Graph, id: &Id, axis: usize, delta: f64| {
+            let (dx, dy) = if axis == 0 { (delta, 0.0) } else { (0.0, delta) };
+            hierarchy.translate(graph, id, dx, dy);
+        };
+        let is_fixed = |members: &[Id]| members.iter().any(|id| constraints.fixed.contains_key(id));
+        
+        let max_passes = 50;
+        for _ in 0..max_passes {
+            // Fixed nodes first, so that aligned groups follow them
+            for (id, &target) in &constraints.fixed {
+                hierarchy.move_to(graph, id, target);
+            }
+            
+            // Align each group on the fixed member's coordinate, or on the group mean
+            for axis in 0..2 {
+                for group in &constraints.groups[axis] {
+                    let target = match group.iter().find(|id| constraints.fixed.contains_key(*id)) {
+                        Some(id) => coordinate(constraints.fixed[id], axis),
+                        None => group.iter().map(|id| coordinate(position(graph, id), axis)).sum::<f64>() / group.len() as f64,
+                    };
+                    for id in group {
+                        let delta = target - coordinate(position(graph, id), axis);
+                        shift(graph, id, axis, delta);
+                    }
+                }
+            }
+            
+            // Push relative placements apart, measuring the gap between node borders
+            let mut violated = false;
+            for (before, after, axis, gap) in &constraints.relative {
+                let extent = |graph: &Graph, id: &Id| coordinate(graph.nodes[id].size_or(DEFAULT_NODE_SIZE), *axis) / 2.0;
+                let min_distance = gap + extent(graph, before) + extent(graph, after);
+                let distance = coordinate(position(graph, after), *axis) - coordinate(position(graph, before), *axis);
+                if distance >= min_distance - 1e-9 {
+                    continue;
+                }
+                violated = true;
+                
+                let deficit = min_distance - distance;
+                let (before_members, after_members) = (constraints.members(before, *axis), constraints.members(after, *axis));
+                let (before_share, after_share) = match (is_fixed(&before_members), is_fixed(&after_members)) {
+                    (true, true) => (0.0, 0.0), // Cannot be satisfied
+                    (true, false) => (0.0, deficit),
+                    (false, true) => (deficit, 0.0),
+                    (false, false) => (deficit / 2.0, deficit / 2.0),
+                };
+                for id in &before_members {
+                    shift(graph, id, *axis, -before_share);
+                }
+                for id in &after_members {
+                    shift(graph, id, *axis, after_share);
+                }
+            }
+            
+            if !violated {
+                break;
+            }
+        }
+    }
+    
+    /// Seed nodes without a position near their already positioned neighbours, so that
+    /// new nodes appear next to the part of the graph they were added to. Nodes whose
+    /// neighbours are all new are placed in later rounds; isolated ones near the centroid.
+    fn seed_new_nodes(&self, graph: &mut Graph, rng: &mut impl Rng) {
+        let spread = self.options.ideal_edge_length / 2.0;
+        
+        let mut neighbours: HashMap<Id, Vec<Id>> = HashMap::new();
+        for edge in graph.sorted_edges() {
+            neighbours.entry(edge.source.clone()).or_default().push(edge.target.clone());
+            neighbours.entry(edge.target.clone()).or_default().push(edge.source.clone());
+        }
+        
+        let mut pending: Vec<Id> = graph.sorted_nodes()
+            .into_iter()
+            .filter(|(_, node)| node.position.is_none())
+            .map(|(id, _)| id.clone())
+            .collect();
+        
+        while !pending.is_empty() {
+            // Place every pending node that has a positioned neighbour at their mean
+            let mut placed = Vec::new();
+            for id in &pending {
+                let anchors: Vec<(f64, f64)> = neighbours.get(id).into_iter()
+                    .flatten()
+                    .filter_map(|neighbour| graph.nodes.get(neighbour).and_then(|node| node.position))
+                    .collect();
+                if !anchors.is_empty() {
+                    let n = anchors.len() as f64;
+                    let mean = anchors.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0 / n, acc.1 + p.1 / n));
+                    placed.push((id.clone(), mean));
+                }
+            }
+            
+            // Otherwise fall back to the centroid of the positioned nodes
+            if placed.is_empty() {
+                let positioned: Vec<(f64, f64)> = graph.sorted_nodes().into_iter().filter_map(|(_, node)| node.position).collect();
+                let n = positioned.len().max(1) as f64;
+                let centroid = positioned.iter().fold((0.0, 0.0), |acc, p| (acc.0 + p.0 / n, acc.1 + p.1 / n));
+                placed.push((pending[0].clone(), centroid));
+            }
+            
+            // Offset each new node slightly so that siblings don't coincide
+            for (id, (x, y)) in &placed {
+                let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+                if let Some(node) = graph.nodes.get_mut(id) {
+                    node.position = Some((x + spread * angle.cos(), y + spread * angle.sin()));
+                }
+            }
+            pending.retain(|id| placed.iter().all(|(placed_id, _)| placed_id != id));
+        }
+    }
+    
     /// Initialize random positions for nodes that don't have positions
-    fn initialize_positions(&self, graph: &mut Graph) {
+    fn initialize_positions(&self, graph: &mut Graph, rng: &mut impl Rng) {
         let radius = 100.0;
-        let mut rng = rand::thread_rng();
         
-        for node in graph.nodes.values_mut() {
+        for (_, node) in graph.sorted_nodes_mut() {
             if node.position.is_none() {
                 // Generate random angle and distance from center
                 let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
@@ -53,16 +290,35 @@ impl FcoseLayoutEngine {
         }
     }
     
-    /// Remove node overlaps as a post-processing step
-    fn remove_overlaps(&self, graph: &mut Graph) -> Result<(), String> {
+    /// Remove node overlaps as a post-processing step.
+    ///
+    /// Siblings are separated inside the deepest compound nodes first; each compound
+    /// is then refitted and moved as a whole when separating it from its own siblings.
+    fn remove_overlaps(&self, graph: &mut Graph, rng: &mut impl Rng) -> Result<(), String> {
+        let hierarchy = Hierarchy::new(graph)?;
+        let padding = self.options.base.compound_padding;
+        
+        for (parent, siblings) in hierarchy.sibling_groups(graph) {
+            self.separate_siblings(graph, &hierarchy, &siblings, rng);
+            if let Some(parent) = parent {
+                hierarchy.fit_compound(graph, &parent, DEFAULT_NODE_SIZE, padding);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Push overlapping nodes of one sibling group apart, moving compound nodes with their contents
+    fn separate_siblings(&self, graph: &mut Graph, hierarchy: &Hierarchy, siblings: &[String], rng: &mut impl Rng) {
         let node_overlap = self.options.node_overlap;
-        let node_size = 10.0; // Assume all nodes have the same size for simplicity
-        let min_distance = node_size * 2.0 * (1.0 - node_overlap / 100.0);
-        let mut rng = rand::thread_rng();
+        let spacing = 1.0 - node_overlap / 100.0;
+        let node_count = siblings.len();
         
-        // Get node positions
-        let mut nodes: Vec<(&String, &mut crate::types::Node)> = graph.nodes.iter_mut().collect();
-        let node_count = nodes.len();
+        // Compound nodes are boxes sized to fit their children
+        let sizes: Vec<(f64, f64)> = siblings.iter().map(|id| graph.nodes[id].size_or(DEFAULT_NODE_SIZE)).collect();
+        let shapes: Vec<NodeShape> = siblings.iter()
+            .map(|id| if hierarchy.is_compound(id) { NodeShape::Rectangle } else { graph.nodes[id].shape })
+            .collect();
         
         // Iterate until no more overlaps are detected or max iterations reached
         let max_iterations = 50;
@@ -74,46 +330,100 @@ impl FcoseLayoutEngine {
             
             // Check all pairs of nodes for overlaps
             for i in 0..node_count {
-                let pos_i = nodes[i].1.position.unwrap_or((0.0, 0.0));
-                
                 for j in i+1..node_count {
-                    let pos_j = nodes[j].1.position.unwrap_or((0.0, 0.0));
+                    let pos_i = graph.nodes[&siblings[i]].position.unwrap_or((0.0, 0.0));
+                    let pos_j = graph.nodes[&siblings[j]].position.unwrap_or((0.0, 0.0));
+                    
+                    // Separate coincident nodes in a random direction
+                    let mut dx = pos_j.0 - pos_i.0;
+                    let mut dy = pos_j.1 - pos_i.1;
+                    if dx.abs() < 0.1 && dy.abs() < 0.1 {
+                        dx = rng.gen::<f64>() * 2.0 - 1.0;
+                        dy = rng.gen::<f64>() * 2.0 - 1.0;
+                    }
                     
-                    // Calculate distance between nodes
-                    let dx = pos_j.0 - pos_i.0;
-                    let dy = pos_j.1 - pos_i.1;
-                    let distance = (dx * dx + dy * dy).sqrt();
+                    let push = if !shapes[i].is_round() && !shapes[j].is_round() {
+                        // Boxes: push apart along the axis with the smaller overlap
+                        let overlap_x = (sizes[i].0 + sizes[j].0) / 2.0 * spacing - dx.abs();
+                        let overlap_y = (sizes[i].1 + sizes[j].1) / 2.0 * spacing - dy.abs();
+                        if overlap_x <= 0.0 || overlap_y <= 0.0 {
+                            None
+                        } else if overlap_x < overlap_y {
+                            Some((overlap_x.copysign(dx), 0.0))
+                        } else {
+                            Some((0.0, overlap_y.copysign(dy)))
+                        }
+                    } else {
+                        // Round shapes: compare the distance with both boundaries along the centre line
+                        let distance = (dx * dx + dy * dy).sqrt();
+                        let direction = (dx / distance, dy / distance);
+                        let min_distance = (shapes[i].boundary_distance(sizes[i], direction)
+                            + shapes[j].boundary_distance(sizes[j], direction)) * spacing;
+                        if distance < min_distance {
+                            let force = min_distance - distance;
+                            Some((force * direction.0, force * direction.1))
+                        } else {
+                            None
+                        }
+                    };
                     
-                    // Check if nodes overlap
-                    if distance < min_distance {
+                    // Move nodes (and anything nested in them) apart
+                    if let Some((force_x, force_y)) = push {
                         overlaps_exist = true;
-                        
-                        // Calculate repulsion vector
-                        let force = min_distance - distance;
-                        let force_x = if distance > 0.1 { force * dx / distance } else { rng.gen::<f64>() * 2.0 - 1.0 };
-                        let force_y = if distance > 0.1 { force * dy / distance } else { rng.gen::<f64>() * 2.0 - 1.0 };
-                        
-                        // Move nodes apart
-                        let pos_i = nodes[i].1.position.unwrap_or((0.0, 0.0));
-                        let pos_j = nodes[j].1.position.unwrap_or((0.0, 0.0));
-                        
-                        nodes[i].1.position = Some((pos_i.0 - force_x / 2.0, pos_i.1 - force_y / 2.0));
-                        nodes[j].1.position = Some((pos_j.0 + force_x / 2.0, pos_j.1 + force_y / 2.0));
+                        hierarchy.translate(graph, &siblings[i], -force_x / 2.0, -force_y / 2.0);
+                        hierarchy.translate(graph, &siblings[j], force_x / 2.0, force_y / 2.0);
                     }
                 }
             }
             
             iteration += 1;
         }
-        
-        Ok(())
     }
 }
 
-impl LayoutEngine for FcoseLayoutEngine {
-    fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
+/// Scale down the forces selected by `limited` that are longer than `max_length`,
+/// keeping their direction
+fn limit_displacement(forces: &mut [(f64, f64)], limited: &[bool], max_length: f64) {
+    for (force, _) in forces.iter_mut().zip(limited).filter(|(_, &limited)| limited) {
+        let length = (force.0 * force.0 + force.1 * force.1).sqrt();
+        if length > max_length {
+            *force = (force.0 * max_length / length, force.1 * max_length / length);
+        }
+    }
+}
+
+impl FcoseLayoutEngine {
+    /// Set up a layout run: initial positions, constraints and merged edges
+    pub fn start(&self, graph: &mut Graph) -> Result<FcoseSimulation, String> {
+        let mut rng = self.options.base.rng();
+        let hierarchy = Hierarchy::new(graph)?;
+        let mut constraints = self.resolve_constraints(graph)?;
+        let padding = self.options.base.compound_padding;
+        let incremental = !self.options.randomize;
+        graph.clear_bend_points();
+        let parallel = take_parallel_edges(graph, self.options.base.merge_edges);
+        
+        // Nodes placed by the caller, which incremental runs move only a little
+        let existing: Vec<bool> = graph.sorted_nodes().iter().map(|(_, node)| node.position.is_some()).collect();
+        
         // Initialize node positions if not already set
-        self.initialize_positions(graph);
+        if incremental {
+            self.seed_new_nodes(graph, &mut rng);
+        } else {
+            self.initialize_positions(graph, &mut rng);
+        }
+        
+        // Locked nodes are held where they start, like fixed node constraints
+        for (id, node) in graph.sorted_nodes() {
+            if node.locked {
+                let position = node.position.unwrap_or((0.0, 0.0));
+                constraints.fixed.entry(id.clone()).or_insert(position);
+            }
+        }
+        if !constraints.is_empty() {
+            self.enforce_constraints(graph, &hierarchy, &constraints);
+        }
+        hierarchy.fit_bounds(graph, DEFAULT_NODE_SIZE, padding);
         
         // Run the force-directed algorithm for a fixed number of iterations
         let max_iterations = match self.options.quality.as_str() {
@@ -122,37 +432,126 @@ impl LayoutEngine for FcoseLayoutEngine {
             _ => 50, // default
         };
         
-        let mut _temperature = 1.0; // For simulated annealing
+        // Incremental runs are a short, low-energy refinement: fewer iterations, and steps
+        // of existing nodes are capped so that in total they move at most one ideal edge length
+        let (max_iterations, temperature, cooling) = if incremental {
+            (max_iterations / 2, 0.1, 0.9)
+        } else {
+            (max_iterations, 1.0, 0.95)
+        };
         
-        for _i in 0..max_iterations {
-            // Calculate repulsive forces between all pairs of nodes
-            let repulsion_forces = self.calculate_repulsion(graph);
-            
-            // Calculate attractive forces along edges
-            let attraction_forces = self.calculate_attraction(graph);
-            
-            // Combine forces
-            let mut combined_forces = vec![(0.0, 0.0); graph.nodes.len()];
-            for i in 0..graph.nodes.len() {
-                combined_forces[i] = (
-                    repulsion_forces[i].0 + attraction_forces[i].0,
-                    repulsion_forces[i].1 + attraction_forces[i].1
-                );
-            }
-            
-            // Apply forces to update node positions
-            self.apply_forces(graph, &combined_forces)?;
-            
-            // Cool down temperature for simulated annealing
-            _temperature *= 0.95;
+        Ok(FcoseSimulation {
+            engine: FcoseLayoutEngine::new(self.options.clone()),
+            rng,
+            hierarchy,
+            constraints,
+            parallel,
+            existing,
+            iteration: 0,
+            max_iterations,
+            temperature,
+            cooling,
+        })
+    }
+}
+
+/// An fCoSE layout in progress, see `FcoseLayoutEngine::start`
+pub struct FcoseSimulation {
+    engine: FcoseLayoutEngine,
+    rng: StdRng,
+    hierarchy: Hierarchy,
+    constraints: Constraints,
+    parallel: ParallelEdges,
+    existing: Vec<bool>,
+    iteration: usize,
+    max_iterations: usize,
+    temperature: f64,
+    cooling: f64,
+}
+
+impl LayoutSimulation for FcoseSimulation {
+    fn tick(&mut self, graph: &mut Graph) -> Result<LayoutTick, String> {
+        let engine = &self.engine;
+        let hierarchy = &self.hierarchy;
+        
+        // Calculate repulsive forces between all pairs of nodes
+        let repulsion_forces = engine.calculate_repulsion(graph);
+        
+        // Calculate attractive forces along edges
+        let attraction_forces = engine.calculate_attraction(graph);
+        
+        // Combine forces
+        let mut combined_forces = vec![(0.0, 0.0); graph.nodes.len()];
+        for i in 0..graph.nodes.len() {
+            combined_forces[i] = (
+                repulsion_forces[i].0 + attraction_forces[i].0,
+                repulsion_forces[i].1 + attraction_forces[i].1
+            );
         }
         
-        // Apply overlap removal as a post-processing step
-        self.remove_overlaps(graph)?;
+        // Move compound nodes through their children and keep children near their parents
+        if hierarchy.has_compounds() {
+            hierarchy.apply_compound_forces(graph, &mut combined_forces, engine.options.compound_gravity);
+        }
+        let energy = combined_forces.iter().map(|(x, y)| x * x + y * y).sum();
+        
+        if !engine.options.randomize {
+            let max_step = engine.options.ideal_edge_length * self.temperature / APPLY_DAMPING;
+            limit_displacement(&mut combined_forces, &self.existing, max_step);
+        }
+        let max_force = combined_forces.iter().map(|(x, y)| (x * x + y * y).sqrt()).fold(0.0, f64::max);
+        
+        // Apply forces to update node positions, then restore the constraints
+        engine.apply_forces(graph, &combined_forces)?;
+        if !self.constraints.is_empty() {
+            engine.enforce_constraints(graph, hierarchy, &self.constraints);
+        }
+        hierarchy.fit_bounds(graph, DEFAULT_NODE_SIZE, engine.options.base.compound_padding);
+        
+        let tick = LayoutTick {
+            iteration: self.iteration + 1,
+            energy,
+            temperature: self.temperature,
+            max_displacement: max_force * APPLY_DAMPING,
+        };
+        
+        // Cool down temperature for simulated annealing
+        self.temperature *= self.cooling;
+        self.iteration += 1;
+        Ok(tick)
+    }
+    
+    fn is_converged(&self) -> bool {
+        self.iteration >= self.max_iterations
+    }
+    
+    fn finish(mut self: Box<Self>, graph: &mut Graph) -> Result<(), String> {
+        let engine = &self.engine;
+        
+        // Apply overlap removal as a post-processing step; constraints take precedence
+        engine.remove_overlaps(graph, &mut self.rng)?;
+        if !self.constraints.is_empty() {
+            engine.enforce_constraints(graph, &self.hierarchy, &self.constraints);
+            self.hierarchy.fit_bounds(graph, DEFAULT_NODE_SIZE, engine.options.base.compound_padding);
+        }
+        
+        // Edge paths for drawing, with merged edges drawn like the edge they were merged into
+        restore_parallel_edges(graph, self.parallel);
+        route_edges(graph, &engine.options.base, "SPLINES");
         
         Ok(())
     }
     
+    fn cancel(self: Box<Self>, graph: &mut Graph) {
+        restore_parallel_edges(graph, self.parallel);
+    }
+}
+
+impl LayoutEngine for FcoseLayoutEngine {
+    fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
+        run_to_completion(Box::new(self.start(graph)?), graph)
+    }
+    
     fn name(&self) -> &'static str {
         "Force-Directed (fCoSE)"
     }
@@ -164,47 +563,8 @@ impl LayoutEngine for FcoseLayoutEngine {
 
 impl ForceDirectedLayout for FcoseLayoutEngine {
     fn calculate_repulsion(&self, graph: &Graph) -> Vec<(f64, f64)> {
-        let node_count = graph.nodes.len();
-        let mut forces = vec![(0.0, 0.0); node_count];
-        let node_repulsion = self.options.node_repulsion;
-        
-        // Get node positions as a vector for easier indexing
-        let nodes: Vec<(&String, &crate::types::Node)> = graph.nodes.iter().collect();
-        
-        // Calculate repulsive forces between all pairs of nodes
-        for i in 0..node_count {
-            let (_, node_i) = nodes[i];
-            let pos_i = node_i.position.unwrap_or((0.0, 0.0));
-            
-            for j in 0..node_count {
-                if i == j { continue; }
-                
-                let (_, node_j) = nodes[j];
-                let pos_j = node_j.position.unwrap_or((0.0, 0.0));
-                
-                // Calculate distance between nodes
-                let dx = pos_i.0 - pos_j.0;
-                let dy = pos_i.1 - pos_j.1;
-                let distance_squared = dx * dx + dy * dy;
-                
-                // Avoid division by zero
-                if distance_squared < 0.1 {
-                    continue;
-                }
-                
-                // Calculate repulsive force (inverse square law)
-                let force = node_repulsion / distance_squared;
-                
-                // Calculate force components
-                let force_x = force * dx / distance_squared.sqrt();
-                let force_y = force * dy / distance_squared.sqrt();
-                
-                // Add to total forces for node i
-                forces[i] = (forces[i].0 + force_x, forces[i].1 + force_y);
-            }
-        }
-        
-        forces
+        // Repulsion between leaf nodes, in ascending ID order
+        leaf_repulsion(graph, self.options.theta, self.options.node_repulsion)
     }
     
     fn calculate_attraction(&self, graph: &Graph) -> Vec<(f64, f64)> {
@@ -213,14 +573,14 @@ impl ForceDirectedLayout for FcoseLayoutEngine {
         let ideal_edge_length = self.options.ideal_edge_length;
         
         // Get node positions and create a map from ID to index
-        let nodes: Vec<(&String, &crate::types::Node)> = graph.nodes.iter().collect();
+        let nodes: Vec<(&String, &crate::types::Node)> = graph.sorted_nodes();
         let mut id_to_index = std::collections::HashMap::new();
         for (i, (id, _)) in nodes.iter().enumerate() {
             id_to_index.insert(*id, i);
         }
         
         // Calculate attractive forces along edges
-        for edge in graph.edges.values() {
+        for edge in graph.sorted_edges() {
             if let (Some(&source_idx), Some(&target_idx)) = (id_to_index.get(&edge.source), id_to_index.get(&edge.target)) {
                 let source_pos = nodes[source_idx].1.position.unwrap_or((0.0, 0.0));
                 let target_pos = nodes[target_idx].1.position.unwrap_or((0.0, 0.0));
@@ -253,7 +613,7 @@ impl ForceDirectedLayout for FcoseLayoutEngine {
     
     fn apply_forces(&self, graph: &mut Graph, forces: &[(f64, f64)]) -> Result<(), String> {
         // Get mutable references to nodes
-        let mut nodes: Vec<(&String, &mut crate::types::Node)> = graph.nodes.iter_mut().collect();
+        let mut nodes: Vec<(&String, &mut crate::types::Node)> = graph.sorted_nodes_mut();
         
         // Apply forces to update positions
         for (i, (_, node)) in nodes.iter_mut().enumerate() {
@@ -265,9 +625,8 @@ impl ForceDirectedLayout for FcoseLayoutEngine {
             let current_pos = node.position.unwrap_or((0.0, 0.0));
             
             // Update position with damping
-            let damping = 0.1;
-            let new_x = current_pos.0 + force_x * damping;
-            let new_y = current_pos.1 + force_y * damping;
+            let new_x = current_pos.0 + force_x * APPLY_DAMPING;
+            let new_y = current_pos.1 + force_y * APPLY_DAMPING;
             
             node.position = Some((new_x, new_y));
         }
@@ -288,26 +647,21 @@ mod tests {
     use super::*;
     use crate::types::{Graph, Node, Edge};
     use std::collections::HashMap;
+    use crate::layout::compound::test_support::{assert_compounds_contain_children, compound_graph};
 
     fn create_test_graph() -> Graph {
         let mut nodes = HashMap::new();
         let mut edges = HashMap::new();
 
         // Add two nodes with fixed positions
-        let mut node1 = Node::default();
-        node1.position = Some((0.0, 0.0));
+        let node1 = Node::new("1").with_position(0.0, 0.0);
         nodes.insert("1".to_string(), node1);
 
-        let mut node2 = Node::default();
-        node2.position = Some((100.0, 0.0));
+        let node2 = Node::new("2").with_position(100.0, 0.0);
         nodes.insert("2".to_string(), node2);
 
         // Add an edge between them
-        let edge = Edge {
-            source: "1".to_string(),
-            target: "2".to_string(),
-            weight: 1.0,
-        };
+        let edge = Edge::new("1-2", "1", "2");
         edges.insert("1-2".to_string(), edge);
 
         Graph { nodes, edges }
@@ -364,19 +718,17 @@ mod tests {
         };
 
         // Create two overlapping nodes
-        let mut node1 = Node::default();
-        node1.position = Some((0.0, 0.0));
+        let node1 = Node::new("1").with_position(0.0, 0.0);
         graph.nodes.insert("1".to_string(), node1);
 
-        let mut node2 = Node::default();
-        node2.position = Some((5.0, 0.0));  // Very close to node1
+        let node2 = Node::new("2").with_position(5.0, 0.0);  // Very close to node1
         graph.nodes.insert("2".to_string(), node2);
 
         let options = FcoseOptions::default();
         let engine = FcoseLayoutEngine::new(options);
         
         // Remove overlaps
-        engine.remove_overlaps(&mut graph).unwrap();
+        engine.remove_overlaps(&mut graph, &mut rand::thread_rng()).unwrap();
 
         // Get final positions
         let pos1 = graph.nodes.get("1").unwrap().position.unwrap();
@@ -391,4 +743,134 @@ mod tests {
         let min_distance = 20.0 * (1.0 - 10.0/100.0);
         assert!(final_distance >= min_distance);
     }
+
+    #[test]
+    fn test_overlap_removal_uses_node_size() {
+        let mut graph = Graph::new();
+        
+        // Two wide labels side by side, 60 units apart but 100 units wide
+        graph.add_node(Node::new("1").with_position(0.0, 0.0).with_size(100.0, 20.0).with_shape(NodeShape::Rectangle))
+             .add_node(Node::new("2").with_position(60.0, 0.0).with_size(100.0, 20.0).with_shape(NodeShape::Rectangle));
+        
+        let options = FcoseOptions { node_overlap: 0.0, ..FcoseOptions::default() };
+        let engine = FcoseLayoutEngine::new(options);
+        engine.remove_overlaps(&mut graph, &mut rand::thread_rng()).unwrap();
+        
+        let pos1 = graph.nodes.get("1").unwrap().position.unwrap();
+        let pos2 = graph.nodes.get("2").unwrap().position.unwrap();
+        
+        // 60 units apart is enough for 20-unit nodes, but the boxes overlapped and were
+        // separated along the axis of least overlap (vertically)
+        assert_eq!(pos2.0 - pos1.0, 60.0);
+        assert!((pos2.1 - pos1.1).abs() >= 20.0 - 1e-9);
+    }
+
+    #[test]
+    fn test_compound_nodes_fit_children() {
+        let mut graph = compound_graph();
+        let options = FcoseOptions {
+            base: LayoutOptions { seed: Some(7), ..LayoutOptions::default() },
+            node_overlap: 0.0,
+            ..FcoseOptions::default()
+        };
+        apply_layout(&mut graph, &options).unwrap();
+        
+        assert_compounds_contain_children(&graph, DEFAULT_NODE_SIZE);
+    }
+
+    fn sample_graph(name: &str) -> Graph {
+        let path = format!("{}/docs/sample/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let content = std::fs::read_to_string(path).unwrap();
+        let file: crate::types::GraphFile = serde_json::from_str(&content).unwrap();
+        file.into()
+    }
+
+    fn position(graph: &Graph, id: &str) -> (f64, f64) {
+        graph.nodes[id].position.unwrap()
+    }
+
+    #[test]
+    fn test_constraints_hold_on_sample_graphs() {
+        for name in ["business_graph.json", "programming_graph.json"] {
+            let mut graph = sample_graph(name);
+            let options = FcoseOptions {
+                base: LayoutOptions { seed: Some(3), ..Default::default() },
+                fixed_node_constraint: vec![FixedNodeConstraint { node_id: "n1".into(), position: (0.0, 0.0) }],
+                alignment_constraint: AlignmentConstraint {
+                    vertical: vec![vec!["n1".into(), "n2".into(), "n3".into()]],
+                    horizontal: vec![vec!["n4".into(), "n5".into()]],
+                },
+                relative_placement_constraint: vec![
+                    RelativePlacementConstraint { left: Some("n4".into()), right: Some("n5".into()), gap: Some(40.0), ..Default::default() },
+                    RelativePlacementConstraint { top: Some("n2".into()), bottom: Some("n3".into()), ..Default::default() },
+                ],
+                ..Default::default()
+            };
+            FcoseLayoutEngine::new(options.clone()).apply_layout(&mut graph).unwrap();
+
+            let epsilon = 1e-6;
+            assert_eq!(position(&graph, "n1"), (0.0, 0.0), "{}", name);
+            assert!(position(&graph, "n2").0.abs() < epsilon && position(&graph, "n3").0.abs() < epsilon, "{}", name);
+            assert!((position(&graph, "n4").1 - position(&graph, "n5").1).abs() < epsilon, "{}", name);
+            // Gaps are measured between the borders of default-sized nodes
+            assert!(position(&graph, "n5").0 - position(&graph, "n4").0 >= 40.0 + DEFAULT_NODE_SIZE - epsilon, "{}", name);
+            assert!(position(&graph, "n3").1 - position(&graph, "n2").1 >= options.ideal_edge_length + DEFAULT_NODE_SIZE - epsilon, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_invalid_constraints_are_rejected() {
+        let mut graph = create_test_graph();
+        let unknown = FcoseOptions {
+            fixed_node_constraint: vec![FixedNodeConstraint { node_id: "missing".into(), position: (0.0, 0.0) }],
+            ..Default::default()
+        };
+        assert!(FcoseLayoutEngine::new(unknown).apply_layout(&mut graph).is_err());
+
+        let mixed = FcoseOptions {
+            relative_placement_constraint: vec![RelativePlacementConstraint { left: Some("1".into()), bottom: Some("2".into()), ..Default::default() }],
+            ..Default::default()
+        };
+        assert!(FcoseLayoutEngine::new(mixed).apply_layout(&mut graph).is_err());
+    }
+
+    #[test]
+    fn test_incremental_layout_keeps_existing_positions() {
+        let mut graph = sample_graph("business_graph.json");
+        let seeded = FcoseOptions { base: LayoutOptions { seed: Some(5), ..Default::default() }, ..Default::default() };
+        FcoseLayoutEngine::new(seeded.clone()).apply_layout(&mut graph).unwrap();
+        let before: HashMap<String, (f64, f64)> = graph.nodes.iter().map(|(id, node)| (id.clone(), node.position.unwrap())).collect();
+
+        // Expand the neighbourhood of n1 and refine incrementally
+        graph.add_node(Node::new("new"));
+        graph.add_edge(Edge::new("n1-new", "n1", "new"));
+        let incremental = FcoseOptions { randomize: false, ..seeded };
+        FcoseLayoutEngine::new(incremental.clone()).apply_layout(&mut graph).unwrap();
+
+        for (id, &(x, y)) in &before {
+            let (nx, ny) = position(&graph, id);
+            let moved = ((nx - x).powi(2) + (ny - y).powi(2)).sqrt();
+            assert!(moved < incremental.ideal_edge_length, "{} moved {}", id, moved);
+        }
+        let (n1, new) = (position(&graph, "n1"), position(&graph, "new"));
+        assert!(((n1.0 - new.0).powi(2) + (n1.1 - new.1).powi(2)).sqrt() < 2.0 * incremental.ideal_edge_length);
+    }
+
+    #[test]
+    fn test_locked_nodes_stay_in_place() {
+        let mut graph = sample_graph("programming_graph.json");
+        for id in ["n1", "n2"] {
+            let node = graph.nodes.get_mut(id).unwrap();
+            node.position = Some((500.0, -500.0));
+            node.locked = true;
+        }
+        graph.nodes.get_mut("n2").unwrap().position = Some((600.0, -500.0));
+
+        for randomize in [true, false] {
+            let options = FcoseOptions { randomize, base: LayoutOptions { seed: Some(1), ..Default::default() }, ..Default::default() };
+            FcoseLayoutEngine::new(options).apply_layout(&mut graph).unwrap();
+            assert_eq!(position(&graph, "n1"), (500.0, -500.0));
+            assert_eq!(position(&graph, "n2"), (600.0, -500.0));
+        }
+    }
 }
@@ -1,6 +1,12 @@
 use std::collections::HashSet;
 use crate::types::{Graph, KlayLayeredLayoutOptions};
 use crate::layout::traits::{LayoutEngine, LayeredLayout};
+use crate::layout::compound::Hierarchy;
+use crate::layout::ordering::{count_crossings, interactive_order, layer_sweep};
+use crate::layout::long_edges::{dummy_ids, edge_sources, join_long_edges, restore_edge_directions, split_long_edges};
+use crate::layout::placement::{place_nodes, NodePlacement};
+use crate::layout::parallel_edges::{restore_parallel_edges, take_parallel_edges};
+use crate::layout::routing::{route_edges, self_loop_room};
 
 /// KLay Layered layout engine implementation
 pub struct KlayLayoutEngine {
@@ -16,17 +22,19 @@ impl KlayLayoutEngine {
 
 impl LayoutEngine for KlayLayoutEngine {
     fn apply_layout(&self, graph: &mut Graph) -> Result<(), String> {
-        // Step 1: Assign nodes to layers
-        let mut layers = self.assign_layers(graph)?;
+        let hierarchy = Hierarchy::new(graph)?;
+        graph.clear_bend_points();
+        let parallel = take_parallel_edges(graph, self.options.base.merge_edges);
         
-        // Step 2: Break cycles if needed
-        self.break_cycles(graph, &mut layers)?;
+        // Lay out the inside of each compound node first, then place it as a single
+        // node among its siblings; the top level comes last
+        hierarchy.layout_levels(graph, self.options.base.compound_padding, |level| self.layout_level(level))?;
         
-        // Step 3: Order nodes within layers to minimize crossings
-        self.minimize_crossings(&mut layers, graph)?;
+        // Edge paths for drawing, with merged edges drawn like the edge they were merged into
+        restore_parallel_edges(graph, parallel);
+        route_edges(graph, &self.options.base, "ORTHOGONAL");
         
-        // Step 4: Assign coordinates
-        self.assign_coordinates(graph, &layers)
+        Ok(())
     }
     
     fn name(&self) -> &'static str {
@@ -43,9 +51,11 @@ impl LayeredLayout for KlayLayoutEngine {
         let mut layers: Vec<Vec<String>> = Vec::new();
         let mut assigned = HashSet::new();
         let mut current_layer = Vec::new();
+        let node_ids = graph.sorted_node_ids();
+        let edges = graph.sorted_edges();
         
         // Find root nodes (nodes with no incoming edges)
-        for node_id in graph.nodes.keys() {
+        for &node_id in &node_ids {
             let has_incoming = graph.edges.values().any(|e| e.target == *node_id);
             if !has_incoming {
                 current_layer.push(node_id.clone());
@@ -55,7 +65,7 @@ impl LayeredLayout for KlayLayoutEngine {
         
         // If no root nodes found, start with any node
         if current_layer.is_empty() && !graph.nodes.is_empty() {
-            let first_node = graph.nodes.keys().next().unwrap().clone();
+            let first_node = node_ids[0].clone();
             current_layer.push(first_node.clone());
             assigned.insert(first_node);
         }
@@ -67,7 +77,7 @@ impl LayeredLayout for KlayLayoutEngine {
             
             for node_id in &current_layer {
                 // Find all unassigned nodes that this node points to
-                for edge in graph.edges.values() {
+                for edge in &edges {
                     if edge.source == *node_id && !assigned.contains(&edge.target) {
                         next_layer.push(edge.target.clone());
                         assigned.insert(edge.target.clone());
@@ -79,7 +89,7 @@ impl LayeredLayout for KlayLayoutEngine {
         }
         
         // Handle any remaining nodes (disconnected or in cycles)
-        for node_id in graph.nodes.keys() {
+        for node_id in node_ids {
             if !assigned.contains(node_id) {
                 if let Some(last_layer) = layers.last_mut() {
                     last_layer.push(node_id.clone());
@@ -119,90 +129,85 @@ impl LayeredLayout for KlayLayoutEngine {
     }
     
     fn minimize_crossings(&self, layers: &mut Vec<Vec<String>>, graph: &Graph) -> Result<(), String> {
-        // For each pair of adjacent layers
-        for i in 0..layers.len().saturating_sub(1) {
-            let mut improved = true;
-            
-            // Keep trying to improve until no more improvements can be made
-            while improved {
-                improved = false;
-                
-                // Clone the current layer for comparison
-                let current_layer = layers[i].clone();
-                
-                // Get mutable reference to the next layer
-                let next_layer = &mut layers[i + 1];
-                
-                // Count crossings between current positions
-                let mut best_crossings = self.count_crossings(&current_layer, next_layer, graph);
-                
-                // Try swapping adjacent nodes in the next layer
-                for j in 0..next_layer.len().saturating_sub(1) {
-                    next_layer.swap(j, j + 1);
-                    
-                    let new_crossings = self.count_crossings(&current_layer, next_layer, graph);
-                    if new_crossings < best_crossings {
-                        best_crossings = new_crossings;
-                        improved = true;
-                    } else {
-                        // Swap back if no improvement
-                        next_layer.swap(j, j + 1);
-                    }
-                }
-            }
+        match self.options.cross_minimization.as_str() {
+            "INTERACTIVE" => interactive_order(layers, graph, 0), // Keep the current left-to-right order
+            _ => layer_sweep(layers, graph), // "LAYER_SWEEP"
         }
         
         Ok(())
     }
     
     fn count_crossings(&self, layer1: &[String], layer2: &[String], graph: &Graph) -> usize {
-        let mut crossings = 0;
-        
-        // For each pair of edges between the layers
-        for (i1, n1) in layer1.iter().enumerate() {
-            for (i2, n2) in layer1.iter().enumerate().skip(i1 + 1) {
-                for edge1 in graph.edges.values() {
-                    if edge1.source != *n1 { continue; }
-                    
-                    for edge2 in graph.edges.values() {
-                        if edge2.source != *n2 { continue; }
-                        
-                        let j1 = layer2.iter().position(|n| *n == edge1.target);
-                        let j2 = layer2.iter().position(|n| *n == edge2.target);
-                        
-                        if let (Some(j1), Some(j2)) = (j1, j2) {
-                            // Check if edges cross
-                            if (i1 < i2 && j1 > j2) || (i1 > i2 && j1 < j2) {
-                                crossings += 1;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        
-        crossings
+        count_crossings(layer1, layer2, graph)
     }
 }
 
 impl KlayLayoutEngine {
-    fn assign_coordinates(&self, graph: &mut Graph, layers: &[Vec<String>]) -> Result<(), String> {
-        let layer_height = self.options.layer_spacing;
+    /// Lay out a graph without compound nodes
+    fn layout_level(&self, graph: &mut Graph) -> Result<(), String> {
+        let sources = edge_sources(graph);
+        
+        // Step 1: Assign nodes to layers
+        let mut layers = self.assign_layers(graph)?;
+        
+        // Step 2: Break cycles if needed
+        self.break_cycles(graph, &mut layers)?;
+        
+        // Step 3: Route edges spanning several layers through a dummy node per layer
+        let long_edges = split_long_edges(graph, &mut layers);
+        
+        // Step 4: Order nodes within layers to minimize crossings
+        self.minimize_crossings(&mut layers, graph)?;
+        
+        // Step 5: Assign coordinates
+        self.assign_coordinates(graph, &layers, &dummy_ids(&long_edges))?;
+        
+        // Dummy positions become bend points of the original edges
+        join_long_edges(graph, &mut layers, long_edges);
+        restore_edge_directions(graph, &sources);
+        Ok(())
+    }
+    
+    fn assign_coordinates(&self, graph: &mut Graph, layers: &[Vec<String>], dummies: &HashSet<String>) -> Result<(), String> {
+        let layer_spacing = self.options.layer_spacing;
         let node_spacing = self.options.node_spacing;
         
-        // Assign y-coordinates based on layer
-        for (layer_idx, layer) in layers.iter().enumerate() {
-            let y = layer_idx as f64 * layer_height;
-            
-            // Assign x-coordinates within layer
-            let layer_width = (layer.len() - 1) as f64 * node_spacing;
-            let start_x = -layer_width / 2.0;
+        // Node sizes, with unsized nodes treated as points. Self-loops stick out to the
+        // right, so nodes with loops are widened on both sides
+        let loop_room = self_loop_room(graph, self.options.base.merge_edges);
+        let size = |node_id: &String| {
+            let (width, height) = graph.nodes.get(node_id).map(|n| n.size_or(0.0)).unwrap_or((0.0, 0.0));
+            (width + 2.0 * loop_room.get(node_id).copied().unwrap_or(0.0), height)
+        };
+        
+        // x-coordinates within layers, keeping node_spacing between borders
+        let placement = match self.options.node_placement.as_str() {
+            "SIMPLE" => NodePlacement::Simple,
+            "LINEAR_SEGMENTS" => NodePlacement::LinearSegments,
+            _ => NodePlacement::BrandesKoepf, // "BRANDES_KOEPF"
+        };
+        let xs = place_nodes(layers, graph, dummies, |id| size(id).0, node_spacing, placement);
+        
+        let mut positions = Vec::new();
+        let mut y = 0.0;
+        let mut previous_height: Option<f64> = None;
+        
+        for layer in layers {
+            // Keep layer_spacing between the tallest nodes of consecutive layers
+            let height = layer.iter().map(|id| size(id).1).fold(0.0, f64::max);
+            if let Some(previous) = previous_height {
+                y += previous / 2.0 + layer_spacing + height / 2.0;
+            }
+            previous_height = Some(height);
             
-            for (node_idx, node_id) in layer.iter().enumerate() {
-                if let Some(node) = graph.nodes.get_mut(node_id) {
-                    let x = start_x + node_idx as f64 * node_spacing;
-                    node.position = Some((x, y));
-                }
+            for node_id in layer {
+                positions.push((node_id.clone(), (xs[node_id], y)));
+            }
+        }
+        
+        for (node_id, position) in positions {
+            if let Some(node) = graph.nodes.get_mut(&node_id) {
+                node.position = Some(position);
             }
         }
         
@@ -220,6 +225,7 @@ pub fn apply_layout(graph: &mut Graph, options: &KlayLayeredLayoutOptions) -> Re
 mod tests {
     use super::*;
     use crate::types::{Node, Edge};
+    use crate::layout::compound::test_support::{assert_compounds_contain_children, compound_graph};
 
     #[test]
     fn test_simple_chain() {
@@ -284,4 +290,124 @@ mod tests {
         assert_eq!(forward_count + backward_count, 2);
         assert!(forward_count == 2 || backward_count == 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_wide_nodes_do_not_overlap() {
+        let mut graph = Graph::new();
+        
+        // Three wide labels in the same rank below a common root
+        graph.add_node(Node::new("root"));
+        for id in ["A", "B", "C"] {
+            graph.add_node(Node::new(id).with_size(120.0, 30.0));
+            graph.add_edge(Edge::new(format!("root-{}", id), "root", id));
+        }
+        
+        let options = KlayLayeredLayoutOptions::default();
+        let separation = options.node_spacing;
+        let engine = KlayLayoutEngine::new(options);
+        engine.apply_layout(&mut graph).unwrap();
+        
+        let mut xs: Vec<f64> = ["A", "B", "C"].iter()
+            .map(|id| graph.nodes[*id].position.unwrap().0)
+            .collect();
+        xs.sort_by(f64::total_cmp);
+        
+        // Neighbouring centres are a full width plus the separation apart
+        for pair in xs.windows(2) {
+            assert!((pair[1] - pair[0] - (120.0 + separation)).abs() < 1e-9);
+        }
+    }
+    
+    #[test]
+    fn test_layer_sweep_uncrosses_edges() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d"] {
+            graph.add_node(Node::new(id));
+        }
+        graph.add_edge(Edge::new("e1", "a", "d")).add_edge(Edge::new("e2", "b", "c"));
+        
+        KlayLayoutEngine::new(KlayLayeredLayoutOptions::default()).apply_layout(&mut graph).unwrap();
+        
+        let x = |id: &str| graph.nodes[id].position.unwrap().0;
+        assert_eq!(x("a") < x("b"), x("d") < x("c"));
+    }
+    
+    #[test]
+    fn test_long_edges_get_bend_points() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d"] {
+            graph.add_node(Node::new(id));
+        }
+        graph.add_edge(Edge::new("ab", "a", "b"))
+             .add_edge(Edge::new("bc", "b", "c"))
+             .add_edge(Edge::new("cd", "c", "d"))
+             .add_edge(Edge::new("da", "d", "a"));
+        
+        KlayLayoutEngine::new(KlayLayeredLayoutOptions::default()).apply_layout(&mut graph).unwrap();
+        
+        // The edge back up passes beside c and b, one bend point per layer crossed
+        let position = |id: &str| graph.nodes[id].position.unwrap();
+        let bends = &graph.edges["da"].bend_points;
+        assert_eq!(bends.len(), 2);
+        assert_eq!(bends[0].1, position("c").1);
+        assert_eq!(bends[1].1, position("b").1);
+        assert!(bends[0].0 != position("c").0 && bends[1].0 != position("b").0);
+        assert!(graph.edges["ab"].bend_points.is_empty());
+        assert_eq!(graph.nodes.len(), 4);
+    }
+    
+    #[test]
+    fn test_long_edges_run_straight() {
+        for placement in ["BRANDES_KOEPF", "LINEAR_SEGMENTS"] {
+            let mut graph = Graph::new();
+            for id in ["a", "b", "c", "d", "e", "f"] {
+                graph.add_node(Node::new(id).with_size(30.0, 30.0));
+            }
+            graph.add_edge(Edge::new("ab", "a", "b"))
+                 .add_edge(Edge::new("bc", "b", "c"))
+                 .add_edge(Edge::new("cd", "c", "d"))
+                 .add_edge(Edge::new("ae", "a", "e"))
+                 .add_edge(Edge::new("ef", "e", "f"))
+                 .add_edge(Edge::new("fd", "f", "d"))
+                 .add_edge(Edge::new("da", "d", "a"));
+            
+            let options = KlayLayeredLayoutOptions { node_placement: placement.to_string(), ..Default::default() };
+            KlayLayoutEngine::new(options).apply_layout(&mut graph).unwrap();
+            
+            let bends = &graph.edges["da"].bend_points;
+            assert_eq!(bends.len(), 2);
+            assert!((bends[0].0 - bends[1].0).abs() < 1e-9, "{} bends the long edge", placement);
+        }
+    }
+    
+    #[test]
+    fn test_edges_are_routed_orthogonally() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d"] {
+            graph.add_node(Node::new(id).with_size(30.0, 20.0));
+        }
+        graph.add_edge(Edge::new("ab", "a", "b"))
+             .add_edge(Edge::new("ac", "a", "c"))
+             .add_edge(Edge::new("bd", "b", "d"))
+             .add_edge(Edge::new("ad", "a", "d"));
+        
+        KlayLayoutEngine::new(KlayLayeredLayoutOptions::default()).apply_layout(&mut graph).unwrap();
+        
+        for edge in graph.edges.values() {
+            let route = edge.route.as_ref().unwrap();
+            assert_eq!(route.style, crate::types::RouteStyle::Orthogonal);
+            for pair in route.points.windows(2) {
+                assert!(pair[0].0 == pair[1].0 || pair[0].1 == pair[1].1, "{} has a diagonal segment", edge.id);
+            }
+        }
+    }
+    
+    #[test]
+    fn test_compound_nodes_fit_children() {
+        let mut graph = compound_graph();
+        KlayLayoutEngine::new(KlayLayeredLayoutOptions::default()).apply_layout(&mut graph).unwrap();
+        
+        assert_compounds_contain_children(&graph, 0.0);
+        assert_eq!(graph.edges["between"].source, "g1-0");
+    }
+}
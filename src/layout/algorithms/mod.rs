@@ -1 +1,7 @@
 pub mod fcose;
+pub mod cose_bilkent;
+pub mod cise;
+pub mod concentric;
+pub mod klay;
+pub mod dagre;
+pub mod nforce;
@@ -0,0 +1,448 @@
+//! Compound (nested) node support shared by the layout engines.
+//!
+//! A node becomes a compound node when other nodes name it as their `parent`.
+//! Compound nodes are never simulated themselves. Force-directed engines move the
+//! leaves (compound forces are handed down to them), while layered engines lay out
+//! each compound's children first and then treat the compound as a single node one
+//! level up. Either way every compound is sized to the bounding box of its children
+//! plus padding, so its `position`, `width` and `height` describe that box.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::types::{Graph, Id};
+use crate::layout::quadtree::inverse_square_repulsion;
+
+/// Validated parent/child structure of a graph
+#[derive(Debug, Clone, Default)]
+pub struct Hierarchy {
+    parents: HashMap<Id, Id>,
+    /// Children of every compound node, in ascending ID order
+    children: BTreeMap<Id, Vec<Id>>,
+    /// Nesting depth of every node; top-level nodes have depth 0
+    depths: HashMap<Id, usize>,
+}
+
+impl Hierarchy {
+    /// Build the hierarchy of a graph, checking that every parent exists and that
+    /// the parent links form a forest
+    pub fn new(graph: &Graph) -> Result<Self, String> {
+        let mut parents = HashMap::new();
+        let mut children: BTreeMap<Id, Vec<Id>> = BTreeMap::new();
+
+        for id in graph.sorted_node_ids() {
+            if let Some(parent) = &graph.nodes[id].parent {
+                if !graph.nodes.contains_key(parent) {
+                    return Err(format!("Node {} has unknown parent {}", id, parent));
+                }
+                parents.insert(id.clone(), parent.clone());
+                children.entry(parent.clone()).or_default().push(id.clone());
+            }
+        }
+
+        // Walk up from every node, reusing depths already known; revisiting a node on
+        // the current path means the parent links contain a cycle
+        let mut depths: HashMap<Id, usize> = HashMap::new();
+        for id in graph.sorted_node_ids() {
+            let mut path: Vec<&Id> = Vec::new();
+            let mut on_path: HashSet<&Id> = HashSet::new();
+            let mut current = id;
+
+            let mut depth = loop {
+                if let Some(&known) = depths.get(current) {
+                    break known + 1;
+                }
+                if !on_path.insert(current) {
+                    return Err(format!("Parent hierarchy contains a cycle through node {}", current));
+                }
+                path.push(current);
+                match parents.get(current) {
+                    Some(parent) => current = parent,
+                    None => break 0,
+                }
+            };
+
+            for node in path.into_iter().rev() {
+                depths.insert(node.clone(), depth);
+                depth += 1;
+            }
+        }
+
+        Ok(Self { parents, children, depths })
+    }
+
+    /// Whether any node has children
+    pub fn has_compounds(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    pub fn is_compound(&self, id: &str) -> bool {
+        self.children.contains_key(id)
+    }
+
+    /// Direct children of a node, in ascending ID order
+    pub fn children(&self, id: &str) -> &[Id] {
+        self.children.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Nesting depth of a node; top-level nodes have depth 0
+    pub fn depth(&self, id: &str) -> usize {
+        self.depths.get(id).copied().unwrap_or(0)
+    }
+
+    /// Leaf nodes nested (at any depth) inside `id`, or `id` itself for a leaf
+    pub fn leaves(&self, id: &str) -> Vec<Id> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![id.to_string()];
+        while let Some(current) = stack.pop() {
+            match self.children.get(&current) {
+                Some(children) => stack.extend(children.iter().rev().cloned()),
+                None => leaves.push(current),
+            }
+        }
+        leaves
+    }
+
+    /// Compound nodes, deepest first
+    pub fn compounds_bottom_up(&self) -> Vec<Id> {
+        let mut compounds: Vec<Id> = self.children.keys().cloned().collect();
+        compounds.sort_by_key(|id| std::cmp::Reverse(self.depth(id)));
+        compounds
+    }
+
+    /// Groups of sibling nodes with their common parent, deepest compounds first
+    /// and the top-level nodes (parent `None`) last
+    pub fn sibling_groups(&self, graph: &Graph) -> Vec<(Option<Id>, Vec<Id>)> {
+        let mut groups: Vec<(Option<Id>, Vec<Id>)> = self.compounds_bottom_up().into_iter()
+            .map(|compound| {
+                let children = self.children(&compound).to_vec();
+                (Some(compound), children)
+            })
+            .collect();
+
+        let roots = graph.sorted_node_ids().into_iter()
+            .filter(|id| !self.parents.contains_key(*id))
+            .cloned()
+            .collect();
+        groups.push((None, roots));
+        groups
+    }
+
+    /// Move a node together with everything nested inside it, including the bend
+    /// points of edges that run between its descendants
+    pub fn translate(&self, graph: &mut Graph, id: &str, dx: f64, dy: f64) {
+        let mut moved = HashSet::new();
+        let mut stack = vec![id.to_string()];
+        while let Some(current) = stack.pop() {
+            if let Some(node) = graph.nodes.get_mut(&current) {
+                if let Some((x, y)) = node.position {
+                    node.position = Some((x + dx, y + dy));
+                }
+            }
+            stack.extend(self.children(&current).iter().cloned());
+            moved.insert(current);
+        }
+
+        if self.is_compound(id) {
+            for edge in graph.edges.values_mut() {
+                if moved.contains(&edge.source) && moved.contains(&edge.target) {
+                    for point in &mut edge.bend_points {
+                        *point = (point.0 + dx, point.1 + dy);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Size every compound node to the bounding box of its children plus `padding`,
+    /// innermost compounds first. Leaves without a size count as `default_size` squares.
+    pub fn fit_bounds(&self, graph: &mut Graph, default_size: f64, padding: f64) {
+        for compound in self.compounds_bottom_up() {
+            self.fit_compound(graph, &compound, default_size, padding);
+        }
+    }
+
+    /// Size one compound node to fit its (already sized) children
+    pub fn fit_compound(&self, graph: &mut Graph, id: &str, default_size: f64, padding: f64) {
+        let mut min = (f64::INFINITY, f64::INFINITY);
+        let mut max = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+
+        for child in self.children(id) {
+            let node = &graph.nodes[child];
+            if let Some((x, y)) = node.position {
+                let (width, height) = node.size_or(default_size);
+                min = (min.0.min(x - width / 2.0), min.1.min(y - height / 2.0));
+                max = (max.0.max(x + width / 2.0), max.1.max(y + height / 2.0));
+            }
+        }
+
+        if min.0 > max.0 {
+            return; // No positioned children
+        }
+
+        if let Some(node) = graph.nodes.get_mut(id) {
+            node.position = Some(((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0));
+            node.width = Some(max.0 - min.0 + 2.0 * padding);
+            node.height = Some(max.1 - min.1 + 2.0 * padding);
+        }
+    }
+
+    /// Adjust forces (indexed like `graph.sorted_nodes()`) for compound nodes: a
+    /// compound's own force is shared between its leaves, and every leaf is pulled
+    /// towards the centre of each of its ancestors with strength `gravity`.
+    pub fn apply_compound_forces(&self, graph: &Graph, forces: &mut [(f64, f64)], gravity: f64) {
+        let index: HashMap<&Id, usize> = graph.sorted_node_ids().into_iter()
+            .enumerate()
+            .map(|(i, id)| (id, i))
+            .collect();
+
+        for compound in self.children.keys() {
+            let Some(&compound_idx) = index.get(compound) else { continue };
+            let force = std::mem::replace(&mut forces[compound_idx], (0.0, 0.0));
+            let leaves = self.leaves(compound);
+            let share = 1.0 / leaves.len() as f64;
+            for leaf in leaves {
+                if let Some(&leaf_idx) = index.get(&leaf) {
+                    forces[leaf_idx].0 += force.0 * share;
+                    forces[leaf_idx].1 += force.1 * share;
+                }
+            }
+        }
+
+        for (id, &idx) in &index {
+            if self.is_compound(id) {
+                continue;
+            }
+            let Some(pos) = graph.nodes[*id].position else { continue };
+            let mut current = self.parents.get(*id);
+            while let Some(parent) = current {
+                if let Some(center) = graph.nodes[parent].position {
+                    forces[idx].0 += gravity * (center.0 - pos.0);
+                    forces[idx].1 += gravity * (center.1 - pos.1);
+                }
+                current = self.parents.get(parent);
+            }
+        }
+    }
+
+    /// Parents whose children are laid out together, innermost compounds first and
+    /// the top level (`None`) last, for engines that lay out one level at a time
+    pub fn levels_bottom_up(&self) -> Vec<Option<Id>> {
+        let mut levels: Vec<Option<Id>> = self.compounds_bottom_up().into_iter().map(Some).collect();
+        levels.push(None);
+        levels
+    }
+
+    /// The children of `parent` (the top-level nodes for `None`) as a flat graph.
+    /// Compound children keep their current size, and every edge between two
+    /// different children's subtrees is lifted to connect those children.
+    pub fn level_graph(&self, graph: &Graph, parent: Option<&str>) -> Graph {
+        // The ancestor-or-self of a node that sits directly in this level
+        let level_node = |id: &Id| -> Option<Id> {
+            let mut current = id;
+            loop {
+                let current_parent = self.parents.get(current).map(String::as_str);
+                if current_parent == parent {
+                    return Some(current.clone());
+                }
+                current = self.parents.get(current)?;
+            }
+        };
+
+        let mut level = Graph::new();
+        for (id, node) in &graph.nodes {
+            if self.parents.get(id).map(String::as_str) == parent {
+                level.add_node(node.clone());
+            }
+        }
+        for edge in graph.edges.values() {
+            if let (Some(source), Some(target)) = (level_node(&edge.source), level_node(&edge.target)) {
+                let lifted = source != edge.source || target != edge.target;
+                if lifted && source == target {
+                    continue; // Both ends inside the same child
+                }
+                let mut edge = edge.clone();
+                edge.source = source;
+                edge.target = target;
+                level.add_edge(edge);
+            }
+        }
+        level
+    }
+
+    /// Lay out the inside of each compound node with `layout_level`, then place it as
+    /// a single node among its siblings, the top level coming last. Positions and bend
+    /// points are copied back and every compound is fitted to its children.
+    pub fn layout_levels<F>(&self, graph: &mut Graph, padding: f64, mut layout_level: F) -> Result<(), String>
+    where
+        F: FnMut(&mut Graph) -> Result<(), String>,
+    {
+        for parent in self.levels_bottom_up() {
+            let mut level = self.level_graph(graph, parent.as_deref());
+            layout_level(&mut level)?;
+
+            for (id, node) in &level.nodes {
+                if let Some(position) = node.position {
+                    self.move_to(graph, id, position);
+                }
+            }
+            for (id, edge) in &level.edges {
+                if let Some(original) = graph.edges.get_mut(id) {
+                    original.bend_points = edge.bend_points.clone();
+                }
+            }
+            if let Some(parent) = parent {
+                self.fit_compound(graph, &parent, 0.0, padding);
+            }
+        }
+        Ok(())
+    }
+
+    /// Place a node at `position`, moving the contents of a compound node along with it
+    pub fn move_to(&self, graph: &mut Graph, id: &str, position: (f64, f64)) {
+        match graph.nodes.get(id).and_then(|node| node.position) {
+            Some(current) if self.is_compound(id) => {
+                self.translate(graph, id, position.0 - current.0, position.1 - current.1);
+            }
+            _ => {
+                if let Some(node) = graph.nodes.get_mut(id) {
+                    node.position = Some(position);
+                }
+            }
+        }
+    }
+}
+
+/// Inverse-square repulsion between the leaf nodes of a graph, indexed like
+/// `graph.sorted_nodes()`. Compound nodes neither exert nor receive repulsion.
+pub fn leaf_repulsion(graph: &Graph, theta: f64, strength: f64) -> Vec<(f64, f64)> {
+    let compounds: HashSet<&Id> = graph.nodes.values().filter_map(|node| node.parent.as_ref()).collect();
+    let nodes = graph.sorted_nodes();
+
+    let leaves: Vec<usize> = (0..nodes.len()).filter(|&i| !compounds.contains(nodes[i].0)).collect();
+    let positions: Vec<(f64, f64)> = leaves.iter()
+        .map(|&i| nodes[i].1.position.unwrap_or((0.0, 0.0)))
+        .collect();
+
+    // Approximate all-pairs repulsion with a Barnes-Hut quadtree
+    let mut forces = vec![(0.0, 0.0); nodes.len()];
+    for (&i, force) in leaves.iter().zip(inverse_square_repulsion(&positions, theta, strength)) {
+        forces[i] = force;
+    }
+    forces
+}
+
+/// Graphs and checks shared by the compound node tests of the layout engines
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::types::{Edge, Graph, Node};
+
+    /// Two compound nodes with three children each, connected by one edge
+    pub(crate) fn compound_graph() -> Graph {
+        let mut graph = Graph::new();
+        for group in ["g1", "g2"] {
+            graph.add_node(Node::new(group));
+            for i in 0..3 {
+                graph.add_node(Node::new(format!("{}-{}", group, i)).with_parent(group));
+            }
+            graph.add_edge(Edge::new(format!("{}-a", group), format!("{}-0", group), format!("{}-1", group)))
+                 .add_edge(Edge::new(format!("{}-b", group), format!("{}-0", group), format!("{}-2", group)));
+        }
+        graph.add_edge(Edge::new("between", "g1-0", "g2-0"));
+        graph
+    }
+
+    /// (min_x, min_y, max_x, max_y) of a node's box
+    pub(crate) fn bounds(graph: &Graph, id: &str, default_size: f64) -> (f64, f64, f64, f64) {
+        let node = &graph.nodes[id];
+        let (x, y) = node.position.unwrap();
+        let (w, h) = node.size_or(default_size);
+        (x - w / 2.0, y - h / 2.0, x + w / 2.0, y + h / 2.0)
+    }
+
+    /// Check that the compounds of `compound_graph` contain their children and do not overlap
+    pub(crate) fn assert_compounds_contain_children(graph: &Graph, default_size: f64) {
+        for group in ["g1", "g2"] {
+            let outer = bounds(graph, group, default_size);
+            for i in 0..3 {
+                let inner = bounds(graph, &format!("{}-{}", group, i), default_size);
+                assert!(inner.0 >= outer.0 - 1e-9 && inner.1 >= outer.1 - 1e-9, "{} escapes {}", i, group);
+                assert!(inner.2 <= outer.2 + 1e-9 && inner.3 <= outer.3 + 1e-9, "{} escapes {}", i, group);
+            }
+        }
+
+        // The two compound boxes do not overlap
+        let (a, b) = (bounds(graph, "g1", default_size), bounds(graph, "g2", default_size));
+        assert!(a.2 <= b.0 + 1e-6 || b.2 <= a.0 + 1e-6 || a.3 <= b.1 + 1e-6 || b.3 <= a.1 + 1e-6);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Edge, Node};
+
+    fn nested_graph() -> Graph {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("outer"))
+             .add_node(Node::new("inner").with_parent("outer"))
+             .add_node(Node::new("a").with_parent("inner").with_position(0.0, 0.0))
+             .add_node(Node::new("b").with_parent("inner").with_position(40.0, 20.0))
+             .add_node(Node::new("c").with_parent("outer").with_position(100.0, 0.0))
+             .add_node(Node::new("d").with_position(300.0, 0.0));
+        graph.add_edge(Edge::new("e1", "a", "b"))
+             .add_edge(Edge::new("e2", "outer", "d"))
+             .add_edge(Edge::new("e3", "a", "inner"));
+        graph
+    }
+
+    #[test]
+    fn test_rejects_cycles_and_unknown_parents() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_parent("b"))
+             .add_node(Node::new("b").with_parent("a"));
+        assert!(Hierarchy::new(&graph).unwrap_err().contains("cycle"));
+
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_parent("missing"));
+        assert!(Hierarchy::new(&graph).unwrap_err().contains("unknown parent"));
+
+        let hierarchy = Hierarchy::new(&nested_graph()).unwrap();
+        assert_eq!(hierarchy.depth("a"), 2);
+        assert_eq!(hierarchy.leaves("outer"), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_fit_bounds_nests_boxes() {
+        let mut graph = nested_graph();
+        let hierarchy = Hierarchy::new(&graph).unwrap();
+        hierarchy.fit_bounds(&mut graph, 10.0, 5.0);
+
+        // inner spans a (-5..5, -5..5) and b (35..45, 15..25) plus 5 padding
+        let inner = &graph.nodes["inner"];
+        assert_eq!(inner.position, Some((20.0, 10.0)));
+        assert_eq!((inner.width, inner.height), (Some(60.0), Some(40.0)));
+
+        // outer contains inner's padded box and c
+        let outer = &graph.nodes["outer"];
+        assert_eq!(outer.position, Some((47.5, 10.0)));
+        assert_eq!((outer.width, outer.height), (Some(125.0), Some(50.0)));
+    }
+
+    #[test]
+    fn test_level_graph_lifts_edges() {
+        let graph = nested_graph();
+        let hierarchy = Hierarchy::new(&graph).unwrap();
+
+        let top = hierarchy.level_graph(&graph, None);
+        assert_eq!(top.sorted_node_ids(), vec!["d", "outer"]);
+        assert_eq!(top.edges.len(), 1);
+        assert_eq!((top.edges["e2"].source.as_str(), top.edges["e2"].target.as_str()), ("outer", "d"));
+
+        // a -> inner stays inside `inner`, so it only appears at the innermost level
+        let outer = hierarchy.level_graph(&graph, Some("outer"));
+        assert_eq!(outer.sorted_node_ids(), vec!["c", "inner"]);
+        assert!(outer.edges.is_empty());
+        let inner = hierarchy.level_graph(&graph, Some("inner"));
+        assert_eq!(inner.edges.len(), 1);
+        assert_eq!(hierarchy.levels_bottom_up(), vec![Some("inner".to_string()), Some("outer".to_string()), None]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Dummy nodes for edges that span several layers.
+//!
+//! After ranking, every edge from layer `i` to layer `j > i + 1` is replaced by a
+//! chain of size-less dummy nodes, one per layer in between, so that ordering sees
+//! long edges (and counts their crossings) and coordinate assignment reserves room
+//! for them. Joining the chains back turns the dummy positions into bend points.
+
+use std::collections::{HashMap, HashSet};
+use crate::types::{Edge, Graph, Id, Node};
+
+/// A long edge that was replaced by a chain of dummy nodes
+pub struct LongEdge {
+    edge: Edge,
+    /// Dummy nodes from the upper end of the edge to the lower end
+    dummies: Vec<Id>,
+    /// Whether the edge points up the layers
+    upward: bool,
+    /// Edges of the chain
+    segments: Vec<Id>,
+}
+
+/// Replace every edge spanning more than one layer by a chain through dummy nodes,
+/// which are appended to the layers they cross
+pub fn split_long_edges(graph: &mut Graph, layers: &mut [Vec<Id>]) -> Vec<LongEdge> {
+    let mut layer_of = HashMap::new();
+    for (layer_idx, layer) in layers.iter().enumerate() {
+        for id in layer {
+            layer_of.insert(id.clone(), layer_idx);
+        }
+    }
+
+    let long: Vec<(Edge, usize, usize)> = graph.sorted_edges()
+        .into_iter()
+        .filter_map(|edge| {
+            let (source, target) = (*layer_of.get(&edge.source)?, *layer_of.get(&edge.target)?);
+            (source.abs_diff(target) > 1).then(|| (edge.clone(), source, target))
+        })
+        .collect();
+
+    let mut chains = Vec::with_capacity(long.len());
+    for (edge, source_layer, target_layer) in long {
+        graph.remove_edge(&edge.id);
+
+        // Build the chain downwards, whichever way the edge points
+        let upward = source_layer > target_layer;
+        let (upper, lower) = if upward { (&edge.target, &edge.source) } else { (&edge.source, &edge.target) };
+        let mut dummies = Vec::new();
+        let mut segments = Vec::new();
+        let mut previous = upper.clone();
+        for (k, layer) in (source_layer.min(target_layer) + 1..source_layer.max(target_layer)).enumerate() {
+            let dummy = unused_id(&graph.nodes, &format!("{}::dummy{}", edge.id, k));
+            graph.add_node(Node::new(dummy.clone()).with_size(0.0, 0.0));
+            layers[layer].push(dummy.clone());
+            let segment = Edge::new(unused_id(&graph.edges, &format!("{}::segment{}", edge.id, k)), previous, dummy.clone());
+            segments.push(segment.id.clone());
+            graph.add_edge(segment);
+            previous = dummy.clone();
+            dummies.push(dummy);
+        }
+        let last = unused_id(&graph.edges, &format!("{}::segment{}", edge.id, dummies.len()));
+        let segment = Edge::new(last, previous, lower.clone());
+        segments.push(segment.id.clone());
+        graph.add_edge(segment);
+
+        chains.push(LongEdge { edge, dummies, upward, segments });
+    }
+    chains
+}
+
+/// Ids of the dummy nodes of all chains
+pub fn dummy_ids(chains: &[LongEdge]) -> HashSet<Id> {
+    chains.iter().flat_map(|chain| chain.dummies.iter().cloned()).collect()
+}
+
+/// Remove the dummy nodes again and restore the original edges, with the dummies'
+/// positions as bend points
+pub fn join_long_edges(graph: &mut Graph, layers: &mut [Vec<Id>], chains: Vec<LongEdge>) {
+    let mut removed = HashSet::new();
+    for LongEdge { mut edge, dummies, upward, segments } in chains {
+        for segment in &segments {
+            graph.remove_edge(segment);
+        }
+        edge.bend_points = dummies.iter()
+            .filter_map(|dummy| graph.nodes.remove(dummy).and_then(|node| node.position))
+            .collect();
+        if upward {
+            edge.bend_points.reverse();
+        }
+        graph.add_edge(edge);
+        removed.extend(dummies);
+    }
+    for layer in layers.iter_mut() {
+        layer.retain(|id| !removed.contains(id));
+    }
+}
+
+/// Edge sources, to undo the reversals of cycle breaking with `restore_edge_directions`
+pub fn edge_sources(graph: &Graph) -> HashMap<Id, Id> {
+    graph.edges.values().map(|edge| (edge.id.clone(), edge.source.clone())).collect()
+}
+
+/// Turn reversed edges back around, along with their bend points
+pub fn restore_edge_directions(graph: &mut Graph, sources: &HashMap<Id, Id>) {
+    for edge in graph.edges.values_mut() {
+        if sources.get(&edge.id).is_some_and(|source| *source != edge.source) {
+            std::mem::swap(&mut edge.source, &mut edge.target);
+            edge.bend_points.reverse();
+        }
+    }
+}
+
+/// `candidate`, or a variant of it that is not taken yet
+fn unused_id<V>(taken: &HashMap<Id, V>, candidate: &str) -> Id {
+    let mut id = candidate.to_string();
+    while taken.contains_key(&id) {
+        id.push('_');
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_join_round_trip() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d"] {
+            graph.add_node(Node::new(id));
+        }
+        graph.add_edge(Edge::new("ab", "a", "b")).add_edge(Edge::new("ad", "a", "d")).add_edge(Edge::new("ca", "c", "a"));
+        let mut layers: Vec<Vec<Id>> = [["a"], ["b"], ["c"], ["d"]].iter().map(|l| vec![l[0].to_string()]).collect();
+
+        let chains = split_long_edges(&mut graph, &mut layers);
+        assert_eq!(graph.nodes.len(), 7);
+        assert_eq!(layers[1].len(), 3);
+        assert_eq!(layers[2].len(), 2);
+        assert!(!graph.edges.contains_key("ad"));
+
+        // Pretend coordinate assignment ran
+        for (i, layer) in layers.iter().enumerate() {
+            for (j, id) in layer.iter().enumerate() {
+                graph.nodes.get_mut(id).unwrap().position = Some((j as f64, i as f64));
+            }
+        }
+
+        join_long_edges(&mut graph, &mut layers, chains);
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.edges.len(), 3);
+        assert_eq!(layers.iter().map(Vec::len).sum::<usize>(), 4);
+        assert_eq!(graph.edges["ad"].bend_points, vec![(1.0, 1.0), (1.0, 2.0)]);
+        // Bend points of upward edges still run from source to target
+        assert_eq!(graph.edges["ca"].bend_points, vec![(2.0, 1.0)]);
+        assert!(graph.edges["ab"].bend_points.is_empty());
+    }
+}
@@ -1,43 +1,185 @@
-use crate::types::{Graph, LayoutAlgorithm};
+use serde::Serialize;
+use crate::types::{
+    Graph, LayoutAlgorithm, DagreLayoutOptions, KlayLayeredLayoutOptions, CiseLayoutOptions,
+    ConcentricLayoutOptions, CoseBilkentLayoutOptions, MultilevelLayoutOptions,
+};
+use algorithms::fcose::FcoseOptions;
 
 pub mod traits;
 pub mod algorithms;
+pub mod quadtree;
+pub mod compound;
+pub mod ranking;
+pub mod ordering;
+pub mod long_edges;
+pub mod placement;
+pub mod routing;
+pub mod parallel_edges;
+pub mod session;
 
 pub use traits::*;
-pub use algorithms::*;
-
-/// Common trait for all layout algorithms
-pub trait LayoutEngine {
-    /// Apply the layout algorithm to a graph
-    fn apply_layout(&self, graph: &mut Graph) -> Result<(), String>;
-    
-    /// Get the name of the layout algorithm
-    fn name(&self) -> &'static str;
-    
-    /// Get a description of the layout algorithm
-    fn description(&self) -> &'static str;
-}
 
 /// Apply a layout algorithm to a graph
 pub fn apply_layout(graph: &mut Graph, layout: &LayoutAlgorithm) -> Result<(), String> {
     match layout {
-        // fCoSE layout (compound)
-        // fCoSE layout (constraint)
-        // Circle layout
-        // AVSDF layout
-        // Grid layout
-        // CoSE layout
-        // CoSE Bilkent layout
-        // CoSE Bilkent layout (compound)
-        // Cola layout
-        // Cola layout (compound)
-        // Euler layout
-        // Spread layout
         LayoutAlgorithm::Fcose(options) => algorithms::fcose::apply_layout(graph, options),
         LayoutAlgorithm::CoseBilkent(options) => algorithms::cose_bilkent::apply_layout(graph, options),
         LayoutAlgorithm::Cise(options) => algorithms::cise::apply_layout(graph, options),
         LayoutAlgorithm::Concentric(options) => algorithms::concentric::apply_layout(graph, options),
         LayoutAlgorithm::KlayLayered(options) => algorithms::klay::apply_layout(graph, options),
         LayoutAlgorithm::Dagre(options) => algorithms::dagre::apply_layout(graph, options),
+        LayoutAlgorithm::Multilevel(options) => algorithms::nforce::apply_layout(graph, options),
     }
-}
\ No newline at end of file
+}
+
+/// Create the engine for a layout algorithm
+pub fn create_engine(layout: &LayoutAlgorithm) -> Box<dyn LayoutEngine> {
+    match layout {
+        LayoutAlgorithm::Fcose(options) => Box::new(algorithms::fcose::FcoseLayoutEngine::new(options.clone())),
+        LayoutAlgorithm::CoseBilkent(options) => Box::new(algorithms::cose_bilkent::CoseBilkentLayoutEngine::new(options.clone())),
+        LayoutAlgorithm::Cise(options) => Box::new(algorithms::cise::CiseLayoutEngine::new(options.clone())),
+        LayoutAlgorithm::Concentric(options) => Box::new(algorithms::concentric::ConcentricLayoutEngine::new(options.clone())),
+        LayoutAlgorithm::KlayLayered(options) => Box::new(algorithms::klay::KlayLayoutEngine::new(options.clone())),
+        LayoutAlgorithm::Dagre(options) => Box::new(algorithms::dagre::DagreLayoutEngine::new(options.clone())),
+        LayoutAlgorithm::Multilevel(options) => Box::new(algorithms::nforce::MultilevelLayoutEngine::new(options.clone())),
+    }
+}
+
+/// Every registered layout algorithm with its default options
+pub fn available_layouts() -> Vec<LayoutAlgorithm> {
+    vec![
+        LayoutAlgorithm::Fcose(FcoseOptions::default()),
+        LayoutAlgorithm::CoseBilkent(CoseBilkentLayoutOptions::default()),
+        LayoutAlgorithm::Cise(CiseLayoutOptions::default()),
+        LayoutAlgorithm::Concentric(ConcentricLayoutOptions::default()),
+        LayoutAlgorithm::KlayLayered(KlayLayeredLayoutOptions::default()),
+        LayoutAlgorithm::Dagre(DagreLayoutOptions::default()),
+        LayoutAlgorithm::Multilevel(MultilevelLayoutOptions::default()),
+    ]
+}
+
+/// Description of a registered layout algorithm, e.g. for building a layout picker
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutInfo {
+    /// The `name` tag accepted by `LayoutAlgorithm`
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub defaults: LayoutAlgorithm,
+}
+
+/// Describe every registered layout algorithm
+pub fn layout_catalog() -> Vec<LayoutInfo> {
+    available_layouts()
+        .into_iter()
+        .map(|layout| {
+            let engine = create_engine(&layout);
+            LayoutInfo {
+                id: layout.id(),
+                name: engine.name(),
+                description: engine.description(),
+                defaults: layout,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Node, Edge};
+
+    #[test]
+    fn test_catalog_round_trips_defaults() {
+        let catalog = layout_catalog();
+        assert_eq!(catalog.len(), available_layouts().len());
+        
+        for info in catalog {
+            // The serialized defaults carry the same tag and parse back
+            let json = serde_json::to_value(&info.defaults).unwrap();
+            assert_eq!(json["name"], info.id);
+            let parsed: LayoutAlgorithm = serde_json::from_value(json).unwrap();
+            assert_eq!(parsed.id(), info.id);
+            assert!(!info.name.is_empty() && !info.description.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_tagged_algorithm_dispatch() {
+        let names = ["fcose", "cose-bilkent", "cise", "concentric", "klay-layered", "dagre", "multilevel"];
+        
+        for name in names {
+            let mut graph = Graph::new();
+            graph.add_node(Node::new("A"))
+                 .add_node(Node::new("B"))
+                 .add_node(Node::new("C"));
+            graph.add_edge(Edge::new("e1", "A", "B"))
+                 .add_edge(Edge::new("e2", "B", "C"));
+            
+            let layout: LayoutAlgorithm = serde_json::from_str(&format!(r#"{{"name": "{}"}}"#, name))
+                .unwrap_or_else(|e| panic!("failed to parse {}: {}", name, e));
+            apply_layout(&mut graph, &layout).unwrap();
+            
+            assert!(graph.nodes.values().all(|n| n.position.is_some()), "{} left nodes unpositioned", name);
+        }
+    }
+
+    #[test]
+    fn test_seeded_layouts_are_reproducible() {
+        let names = ["fcose", "cose-bilkent", "cise", "concentric", "klay-layered", "dagre", "multilevel"];
+        
+        // A fresh graph each time, so `HashMap` iteration order differs between runs
+        let build = || {
+            let mut graph = Graph::new();
+            for i in 0..40 {
+                graph.add_node(Node::new(format!("n{}", i)));
+            }
+            for i in 0..40 {
+                graph.add_edge(Edge::new(format!("e{}", i), format!("n{}", i), format!("n{}", (i * 7 + 3) % 40)));
+            }
+            graph
+        };
+        
+        for name in names {
+            let layout: LayoutAlgorithm = serde_json::from_str(&format!(r#"{{"name": "{}", "base": {{"seed": 42}}}}"#, name)).unwrap();
+            
+            let mut first = build();
+            apply_layout(&mut first, &layout).unwrap();
+            let mut second = build();
+            apply_layout(&mut second, &layout).unwrap();
+            
+            assert_eq!(
+                serde_json::to_string(&first).unwrap(),
+                serde_json::to_string(&second).unwrap(),
+                "{} is not reproducible with a fixed seed", name
+            );
+        }
+    }
+    
+    #[test]
+    fn test_self_loops_and_parallel_edges() {
+        for merge_edges in [false, true] {
+            for name in ["fcose", "cose-bilkent", "cise", "concentric", "klay-layered", "dagre", "multilevel"] {
+                let mut graph = Graph::new();
+                for id in ["a", "b", "c"] {
+                    graph.add_node(Node::new(id).with_size(30.0, 20.0));
+                }
+                graph.add_edge(Edge::new("aa", "a", "a"))
+                     .add_edge(Edge::new("ab1", "a", "b"))
+                     .add_edge(Edge::new("ab2", "a", "b"))
+                     .add_edge(Edge::new("ba", "b", "a"))
+                     .add_edge(Edge::new("bc", "b", "c"));
+                
+                let layout: LayoutAlgorithm = serde_json::from_str(&format!(
+                    r#"{{"name": "{}", "base": {{"seed": 3, "merge_edges": {}}}}}"#, name, merge_edges
+                )).unwrap();
+                apply_layout(&mut graph, &layout).unwrap();
+                
+                assert!(graph.nodes.values().all(|n| n.position.is_some_and(|(x, y)| x.is_finite() && y.is_finite())), "{}", name);
+                assert_eq!(graph.edges.len(), 5, "{} lost edges", name);
+                assert!(graph.edges.values().all(|e| e.route.is_some()), "{} left edges unrouted", name);
+                assert_eq!(graph.edges["ab1"].route == graph.edges["ab2"].route, merge_edges, "{}", name);
+            }
+        }
+    }
+}
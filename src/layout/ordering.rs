@@ -0,0 +1,361 @@
+//! Node ordering within layers for layered layouts.
+//!
+//! Layer sweeps reorder each layer by the median or barycenter of its neighbours in
+//! the layer before it (top-down, then bottom-up), and transpose refinement swaps
+//! adjacent nodes while that removes crossings. Crossings between two layers are
+//! counted with the accumulator tree of Barth, Jünger and Mutzel in O(E log V).
+
+use std::collections::HashMap;
+use crate::types::{Graph, Id};
+
+/// Sweeps without improvement before giving up
+const MAX_STALE_SWEEPS: usize = 4;
+
+/// Upper bound on the number of sweeps
+const MAX_SWEEPS: usize = 24;
+
+/// Value a layer is sorted by during a sweep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Heuristic {
+    Median,
+    Barycenter,
+}
+
+/// Layers as node indices, with the neighbours of each node in the adjacent layers
+#[derive(Clone)]
+struct LayerGraph {
+    layers: Vec<Vec<usize>>,
+    /// Neighbours in the layer above, per node (with multiplicity for parallel edges)
+    up: Vec<Vec<usize>>,
+    /// Neighbours in the layer below, per node
+    down: Vec<Vec<usize>>,
+    /// Position of each node within its layer
+    positions: Vec<usize>,
+}
+
+impl LayerGraph {
+    fn new(layers: &[Vec<Id>], graph: &Graph) -> (Self, Vec<Id>) {
+        let ids: Vec<Id> = layers.iter().flatten().cloned().collect();
+        let mut index = HashMap::new();
+        let mut layer_of = Vec::with_capacity(ids.len());
+        let mut indexed = Vec::with_capacity(layers.len());
+        for (layer_idx, layer) in layers.iter().enumerate() {
+            let start = index.len();
+            for id in layer {
+                index.insert(id, index.len());
+                layer_of.push(layer_idx);
+            }
+            indexed.push((start..start + layer.len()).collect::<Vec<usize>>());
+        }
+
+        let mut up = vec![Vec::new(); ids.len()];
+        let mut down = vec![Vec::new(); ids.len()];
+        for edge in graph.sorted_edges() {
+            if let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) {
+                // Only edges between adjacent layers count, whichever way they point
+                let (upper, lower) = if layer_of[source] < layer_of[target] { (source, target) } else { (target, source) };
+                if layer_of[lower] == layer_of[upper] + 1 {
+                    down[upper].push(lower);
+                    up[lower].push(upper);
+                }
+            }
+        }
+
+        let mut layer_graph = Self { layers: indexed, up, down, positions: vec![0; ids.len()] };
+        layer_graph.update_positions();
+        (layer_graph, ids)
+    }
+
+    fn update_positions(&mut self) {
+        for layer in &self.layers {
+            for (pos, &node) in layer.iter().enumerate() {
+                self.positions[node] = pos;
+            }
+        }
+    }
+
+    /// Crossings between layer `upper` and the layer below it
+    fn crossings_below(&self, upper: usize) -> usize {
+        let lower_len = self.layers[upper + 1].len();
+        let mut sequence = Vec::new();
+        for &node in &self.layers[upper] {
+            let mut targets: Vec<usize> = self.down[node].iter().map(|&w| self.positions[w]).collect();
+            targets.sort_unstable();
+            sequence.extend(targets);
+        }
+        count_inversions(&sequence, lower_len)
+    }
+
+    fn total_crossings(&self) -> usize {
+        (0..self.layers.len().saturating_sub(1)).map(|i| self.crossings_below(i)).sum()
+    }
+
+    /// Reorder layer `layer` by the positions of its neighbours in the adjacent layer;
+    /// nodes without such neighbours keep their place
+    fn reorder(&mut self, layer: usize, downward: bool, heuristic: Heuristic) {
+        let mut movable = Vec::new();
+        let mut slots = Vec::new();
+        for (slot, &node) in self.layers[layer].iter().enumerate() {
+            let neighbours = if downward { &self.up[node] } else { &self.down[node] };
+            let mut adjacent: Vec<f64> = neighbours.iter().map(|&w| self.positions[w] as f64).collect();
+            if adjacent.is_empty() {
+                continue;
+            }
+            adjacent.sort_by(|a, b| a.total_cmp(b));
+            let value = match heuristic {
+                Heuristic::Median => weighted_median(&adjacent),
+                Heuristic::Barycenter => adjacent.iter().sum::<f64>() / adjacent.len() as f64,
+            };
+            movable.push((value, node));
+            slots.push(slot);
+        }
+
+        movable.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (slot, (_, node)) in slots.into_iter().zip(movable) {
+            self.layers[layer][slot] = node;
+        }
+        for (pos, &node) in self.layers[layer].iter().enumerate() {
+            self.positions[node] = pos;
+        }
+    }
+
+    /// Crossings among the edges of `v` and `w` if `v` is placed left of `w`
+    fn pair_crossings(&self, v: usize, w: usize) -> usize {
+        let mut crossings = 0;
+        for neighbours in [&self.up, &self.down] {
+            for &a in &neighbours[v] {
+                for &b in &neighbours[w] {
+                    if self.positions[a] > self.positions[b] {
+                        crossings += 1;
+                    }
+                }
+            }
+        }
+        crossings
+    }
+
+    /// Swap adjacent nodes while that reduces the crossings around them
+    fn transpose(&mut self) {
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for layer in 0..self.layers.len() {
+                for j in 0..self.layers[layer].len().saturating_sub(1) {
+                    let (v, w) = (self.layers[layer][j], self.layers[layer][j + 1]);
+                    if self.pair_crossings(v, w) > self.pair_crossings(w, v) {
+                        self.layers[layer].swap(j, j + 1);
+                        self.positions[v] = j + 1;
+                        self.positions[w] = j;
+                        improved = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Alternate downward and upward sweeps, keeping the best ordering seen
+    fn sweep(&mut self, heuristic: Heuristic) -> usize {
+        self.transpose();
+        let mut best = self.layers.clone();
+        let mut best_crossings = self.total_crossings();
+        let mut stale = 0;
+
+        for iteration in 0..MAX_SWEEPS {
+            if best_crossings == 0 || stale >= MAX_STALE_SWEEPS {
+                break;
+            }
+            if iteration % 2 == 0 {
+                for layer in 1..self.layers.len() {
+                    self.reorder(layer, true, heuristic);
+                }
+            } else {
+                for layer in (0..self.layers.len().saturating_sub(1)).rev() {
+                    self.reorder(layer, false, heuristic);
+                }
+            }
+            self.transpose();
+
+            let crossings = self.total_crossings();
+            if crossings < best_crossings {
+                best = self.layers.clone();
+                best_crossings = crossings;
+                stale = 0;
+            } else {
+                stale += 1;
+            }
+        }
+
+        self.layers = best;
+        self.update_positions();
+        best_crossings
+    }
+}
+
+/// Median of sorted neighbour positions; with an even count the two middle values
+/// are weighted towards the side where the neighbours are packed more tightly
+fn weighted_median(sorted: &[f64]) -> f64 {
+    let len = sorted.len();
+    let m = len / 2;
+    if len % 2 == 1 {
+        sorted[m]
+    } else if len == 2 {
+        (sorted[0] + sorted[1]) / 2.0
+    } else {
+        let left = sorted[m - 1] - sorted[0];
+        let right = sorted[len - 1] - sorted[m];
+        if left + right == 0.0 {
+            (sorted[m - 1] + sorted[m]) / 2.0
+        } else {
+            (sorted[m - 1] * right + sorted[m] * left) / (left + right)
+        }
+    }
+}
+
+/// Number of pairs `i < j` with `sequence[i] > sequence[j]`, for values below `size`,
+/// using an accumulator tree over the values
+fn count_inversions(sequence: &[usize], size: usize) -> usize {
+    let mut first_leaf = 1;
+    while first_leaf < size {
+        first_leaf *= 2;
+    }
+    let mut tree = vec![0usize; 2 * first_leaf];
+    let mut crossings = 0;
+    for &value in sequence {
+        // Add the value, counting earlier values greater than it on the way up
+        let mut index = value + first_leaf;
+        tree[index] += 1;
+        while index > 1 {
+            if index % 2 == 0 {
+                crossings += tree[index + 1];
+            }
+            index /= 2;
+            tree[index] += 1;
+        }
+    }
+    crossings
+}
+
+/// Count the crossings between edges joining two adjacent layers
+pub fn count_crossings(upper: &[Id], lower: &[Id], graph: &Graph) -> usize {
+    let layers = [upper.to_vec(), lower.to_vec()];
+    LayerGraph::new(&layers, graph).0.crossings_below(0)
+}
+
+/// Reorder every layer with median and barycenter layer sweeps plus transpose
+/// refinement, keeping whichever ordering has fewer crossings
+pub fn layer_sweep(layers: &mut [Vec<Id>], graph: &Graph) {
+    let (mut median, ids) = LayerGraph::new(layers, graph);
+    let mut barycenter = median.clone();
+    let median_crossings = median.sweep(Heuristic::Median);
+    let barycenter_crossings = if median_crossings > 0 { barycenter.sweep(Heuristic::Barycenter) } else { usize::MAX };
+
+    let best = if barycenter_crossings < median_crossings { barycenter } else { median };
+    for (layer, indices) in layers.iter_mut().zip(&best.layers) {
+        *layer = indices.iter().map(|&i| ids[i].clone()).collect();
+    }
+}
+
+/// Order every layer by the nodes' current coordinate along `axis` (0 for x, 1 for
+/// y), keeping nodes without a position after the others in their existing order
+pub fn interactive_order(layers: &mut [Vec<Id>], graph: &Graph, axis: usize) {
+    for layer in layers.iter_mut() {
+        let coordinate = |id: &Id| {
+            graph.nodes.get(id)
+                .and_then(|node| node.position)
+                .map(|(x, y)| if axis == 0 { x } else { y })
+        };
+        layer.sort_by(|a, b| match (coordinate(a), coordinate(b)) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Edge, Node};
+    use rand::{Rng, SeedableRng};
+
+    fn layered_graph(layers: &[Vec<&str>], edges: &[(&str, &str)]) -> (Vec<Vec<Id>>, Graph) {
+        let mut graph = Graph::new();
+        for id in layers.iter().flatten() {
+            graph.add_node(Node::new(*id));
+        }
+        for (i, (source, target)) in edges.iter().enumerate() {
+            graph.add_edge(Edge::new(format!("e{}", i), *source, *target));
+        }
+        let layers = layers.iter().map(|layer| layer.iter().map(|id| id.to_string()).collect()).collect();
+        (layers, graph)
+    }
+
+    fn total_crossings(layers: &[Vec<Id>], graph: &Graph) -> usize {
+        LayerGraph::new(layers, graph).0.total_crossings()
+    }
+
+    fn brute_force(upper: &[Id], lower: &[Id], graph: &Graph) -> usize {
+        let pos = |layer: &[Id], id: &Id| layer.iter().position(|n| n == id);
+        let edges: Vec<(usize, usize)> = graph.sorted_edges().iter()
+            .filter_map(|e| Some((pos(upper, &e.source)?, pos(lower, &e.target)?)))
+            .collect();
+        let mut crossings = 0;
+        for (i, a) in edges.iter().enumerate() {
+            for b in &edges[i + 1..] {
+                if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                    crossings += 1;
+                }
+            }
+        }
+        crossings
+    }
+
+    #[test]
+    fn test_accumulator_tree_matches_brute_force() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let upper: Vec<String> = (0..rng.gen_range(1..8)).map(|i| format!("u{}", i)).collect();
+            let lower: Vec<String> = (0..rng.gen_range(1..8)).map(|i| format!("l{}", i)).collect();
+            let mut graph = Graph::new();
+            for id in upper.iter().chain(&lower) {
+                graph.add_node(Node::new(id.clone()));
+            }
+            for i in 0..rng.gen_range(0..15) {
+                let source = &upper[rng.gen_range(0..upper.len())];
+                let target = &lower[rng.gen_range(0..lower.len())];
+                graph.add_edge(Edge::new(format!("e{}", i), source.clone(), target.clone()));
+            }
+            assert_eq!(count_crossings(&upper, &lower, &graph), brute_force(&upper, &lower, &graph));
+        }
+    }
+
+    #[test]
+    fn test_layer_sweep_removes_crossings() {
+        // Two interleaved chains that can be drawn without crossings
+        let (mut layers, graph) = layered_graph(
+            &[vec!["a1", "b1"], vec!["b2", "a2"], vec!["a3", "b3"]],
+            &[("a1", "a2"), ("a2", "a3"), ("b1", "b2"), ("b2", "b3")],
+        );
+        assert_eq!(total_crossings(&layers, &graph), 2);
+        layer_sweep(&mut layers, &graph);
+        assert_eq!(total_crossings(&layers, &graph), 0);
+    }
+
+    #[test]
+    fn test_weighted_median() {
+        assert_eq!(weighted_median(&[3.0]), 3.0);
+        assert_eq!(weighted_median(&[1.0, 4.0]), 2.5);
+        // The middle values lean towards the tightly packed side
+        assert_eq!(weighted_median(&[0.0, 1.0, 2.0, 8.0]), (1.0 * 6.0 + 2.0 * 1.0) / 7.0);
+    }
+
+    #[test]
+    fn test_interactive_order_follows_positions() {
+        let (mut layers, mut graph) = layered_graph(&[vec!["a", "b", "c"]], &[]);
+        graph.nodes.get_mut("a").unwrap().position = Some((50.0, 0.0));
+        graph.nodes.get_mut("c").unwrap().position = Some((-50.0, 0.0));
+        interactive_order(&mut layers, &graph, 0);
+        assert_eq!(layers[0], vec!["c", "a", "b"]);
+    }
+}
@@ -0,0 +1,75 @@
+//! Merging of parallel edges for layout.
+//!
+//! With `merge_edges`, engines lay out only the first (by ID) of the edges that share
+//! a source and a target. The others are taken out of the graph before the layout and
+//! put back afterwards with the bend points of the edge they were merged into, so
+//! that routing draws them on top of it.
+
+use std::collections::BTreeMap;
+use crate::types::{Edge, Graph, Id};
+
+/// Edges taken out by `take_parallel_edges`, with the ID of the edge each was merged into
+pub struct ParallelEdges {
+    merged: Vec<(Id, Edge)>,
+}
+
+/// Remove every edge that repeats the source and target of an edge with a smaller ID,
+/// if `merge` is set
+pub fn take_parallel_edges(graph: &mut Graph, merge: bool) -> ParallelEdges {
+    let mut merged = Vec::new();
+    if merge {
+        let mut repeated = Vec::new();
+        let mut first: BTreeMap<(&Id, &Id), &Id> = BTreeMap::new();
+        for edge in graph.sorted_edges() {
+            match first.get(&(&edge.source, &edge.target)) {
+                Some(&kept) => repeated.push((kept.clone(), edge.id.clone())),
+                None => {
+                    first.insert((&edge.source, &edge.target), &edge.id);
+                }
+            }
+        }
+        for (kept, id) in repeated {
+            if let Some(edge) = graph.edges.remove(&id) {
+                merged.push((kept, edge));
+            }
+        }
+    }
+    ParallelEdges { merged }
+}
+
+/// Put the merged edges back, bent like the edge they were merged into
+pub fn restore_parallel_edges(graph: &mut Graph, parallel: ParallelEdges) {
+    for (kept, mut edge) in parallel.merged {
+        edge.bend_points = graph.edges.get(&kept).map(|kept| kept.bend_points.clone()).unwrap_or_default();
+        graph.add_edge(edge);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Node;
+
+    #[test]
+    fn test_take_and_restore_parallel_edges() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a")).add_node(Node::new("b"));
+        graph.add_edge(Edge::new("e1", "a", "b"))
+             .add_edge(Edge::new("e2", "a", "b"))
+             .add_edge(Edge::new("e3", "b", "a"));
+
+        let untouched = take_parallel_edges(&mut graph, false);
+        assert_eq!(graph.edges.len(), 3);
+        restore_parallel_edges(&mut graph, untouched);
+
+        // Only the repeated a -> b edge goes; b -> a points the other way
+        let parallel = take_parallel_edges(&mut graph, true);
+        assert_eq!(graph.edges.len(), 2);
+        assert!(!graph.edges.contains_key("e2"));
+
+        graph.edges.get_mut("e1").unwrap().bend_points = vec![(1.0, 2.0)];
+        restore_parallel_edges(&mut graph, parallel);
+        assert_eq!(graph.edges.len(), 3);
+        assert_eq!(graph.edges["e2"].bend_points, vec![(1.0, 2.0)]);
+    }
+}
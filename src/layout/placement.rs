@@ -0,0 +1,529 @@
+//! Coordinates within layers for layered layouts.
+//!
+//! Three strategies are offered, mirroring ELK Layered's node placers:
+//!
+//! - Brandes–Köpf ("Fast and Simple Horizontal Coordinate Assignment"): four
+//!   vertical alignments (up/down × left/right) are each compacted into blocks and
+//!   the results are balanced, which keeps most edges straight. Implemented after
+//!   dagre.js.
+//! - Linear segments: every chain of long-edge dummy nodes is kept straight and
+//!   segments are then pulled towards their neighbours.
+//! - Simple: every layer is centred with even spacing.
+//!
+//! All of them keep nodes in their layer order with `separation` between borders.
+//! Each node claims half of it on either side and dummy nodes a quarter, so two
+//! neighbouring dummies are half of it apart.
+
+use std::collections::{HashMap, HashSet};
+use crate::types::{Graph, Id};
+
+/// In-layer coordinate assignment strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodePlacement {
+    BrandesKoepf,
+    LinearSegments,
+    Simple,
+}
+
+/// Rounds of pulling linear segments towards their neighbours
+const BALANCING_ROUNDS: usize = 50;
+
+/// Layers as node indices, with each node's neighbours in the adjacent layers
+struct Layering {
+    layers: Vec<Vec<usize>>,
+    /// Position of every node within its layer
+    order: Vec<usize>,
+    up: Vec<Vec<usize>>,
+    down: Vec<Vec<usize>>,
+    breadth: Vec<f64>,
+    dummy: Vec<bool>,
+}
+
+impl Layering {
+    fn new(layers: &[Vec<Id>], graph: &Graph, dummies: &HashSet<Id>, breadth: &dyn Fn(&Id) -> f64) -> (Self, Vec<Id>) {
+        let ids: Vec<Id> = layers.iter().flatten().cloned().collect();
+        let index: HashMap<&Id, usize> = ids.iter().enumerate().map(|(i, id)| (id, i)).collect();
+        let mut layer_of = vec![0; ids.len()];
+        let mut order = vec![0; ids.len()];
+        let mut indexed = Vec::with_capacity(layers.len());
+        let mut next = 0;
+        for (layer_idx, layer) in layers.iter().enumerate() {
+            let members: Vec<usize> = (next..next + layer.len()).collect();
+            for (pos, &node) in members.iter().enumerate() {
+                layer_of[node] = layer_idx;
+                order[node] = pos;
+            }
+            next += layer.len();
+            indexed.push(members);
+        }
+
+        let mut up = vec![Vec::new(); ids.len()];
+        let mut down = vec![Vec::new(); ids.len()];
+        for edge in graph.sorted_edges() {
+            if let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) {
+                let (upper, lower) = if layer_of[source] < layer_of[target] { (source, target) } else { (target, source) };
+                if layer_of[lower] == layer_of[upper] + 1 {
+                    down[upper].push(lower);
+                    up[lower].push(upper);
+                }
+            }
+        }
+
+        let layering = Self {
+            layers: indexed,
+            order,
+            up,
+            down,
+            breadth: ids.iter().map(breadth).collect(),
+            dummy: ids.iter().map(|id| dummies.contains(id)).collect(),
+        };
+        (layering, ids)
+    }
+
+    /// Minimum distance between the centres of two neighbouring nodes
+    fn gap(&self, u: usize, v: usize, separation: f64) -> f64 {
+        let margin = |node: usize| if self.dummy[node] { separation / 4.0 } else { separation / 2.0 };
+        self.breadth[u] / 2.0 + margin(u) + margin(v) + self.breadth[v] / 2.0
+    }
+}
+
+/// Assign every node a coordinate along its layer, centred on zero overall
+pub fn place_nodes(
+    layers: &[Vec<Id>],
+    graph: &Graph,
+    dummies: &HashSet<Id>,
+    breadth: impl Fn(&Id) -> f64,
+    separation: f64,
+    placement: NodePlacement,
+) -> HashMap<Id, f64> {
+    let (layering, ids) = Layering::new(layers, graph, dummies, &breadth);
+    let xs = match placement {
+        NodePlacement::BrandesKoepf => brandes_koepf(&layering, separation),
+        NodePlacement::LinearSegments => linear_segments(&layering, separation),
+        NodePlacement::Simple => return simple(&layering, &ids, separation),
+    };
+
+    // Centre the drawing on zero
+    let min = (0..ids.len()).map(|v| xs[v] - layering.breadth[v] / 2.0).fold(f64::INFINITY, f64::min);
+    let max = (0..ids.len()).map(|v| xs[v] + layering.breadth[v] / 2.0).fold(f64::NEG_INFINITY, f64::max);
+    let shift = if ids.is_empty() { 0.0 } else { (min + max) / 2.0 };
+    ids.into_iter().enumerate().map(|(v, id)| (id, xs[v] - shift)).collect()
+}
+
+/// Centre each layer on zero independently
+fn simple(layering: &Layering, ids: &[Id], separation: f64) -> HashMap<Id, f64> {
+    let mut xs = HashMap::new();
+    for layer in &layering.layers {
+        let mut offsets = Vec::with_capacity(layer.len());
+        let mut x = 0.0;
+        for (i, &v) in layer.iter().enumerate() {
+            if i > 0 {
+                x += layering.gap(layer[i - 1], v, separation);
+            }
+            offsets.push(x);
+        }
+        let centre = match (layer.first(), layer.last()) {
+            (Some(&first), Some(&last)) => (offsets[0] - layering.breadth[first] / 2.0 + x + layering.breadth[last] / 2.0) / 2.0,
+            _ => 0.0,
+        };
+        for (&v, offset) in layer.iter().zip(offsets) {
+            xs.insert(ids[v].clone(), offset - centre);
+        }
+    }
+    xs
+}
+
+/// Segments that cross a long-edge segment between two dummy nodes. Marking them lets
+/// the alignment keep long edges straight at the expense of ordinary edges.
+fn type1_conflicts(layering: &Layering) -> HashSet<(usize, usize)> {
+    let mut conflicts = HashSet::new();
+    for pair in layering.layers.windows(2) {
+        let (previous, layer) = (&pair[0], &pair[1]);
+        let mut k0 = 0;
+        let mut scan_pos = 0;
+        for (i, &v) in layer.iter().enumerate() {
+            // The upper end of an inner segment ending in v, if any
+            let inner = if layering.dummy[v] { layering.up[v].iter().copied().find(|&u| layering.dummy[u]) } else { None };
+            if inner.is_some() || i + 1 == layer.len() {
+                let k1 = inner.map_or(previous.len(), |w| layering.order[w]);
+                for &scan_node in &layer[scan_pos..=i] {
+                    for &u in &layering.up[scan_node] {
+                        let u_pos = layering.order[u];
+                        if (u_pos < k0 || k1 < u_pos) && !(layering.dummy[u] && layering.dummy[scan_node]) {
+                            conflicts.insert((u.min(scan_node), u.max(scan_node)));
+                        }
+                    }
+                }
+                scan_pos = i + 1;
+                k0 = k1;
+            }
+        }
+    }
+    conflicts
+}
+
+/// Align every node with the median of its neighbours in the layer before it, unless
+/// that conflicts with an earlier alignment. Returns the root of every node's block
+/// and the next node in its block (cyclic).
+fn vertical_alignment(
+    layering: &Layering,
+    layers: &[Vec<usize>],
+    conflicts: &HashSet<(usize, usize)>,
+    neighbours: &[Vec<usize>],
+) -> (Vec<usize>, Vec<usize>) {
+    let n = layering.order.len();
+    let mut root: Vec<usize> = (0..n).collect();
+    let mut align: Vec<usize> = (0..n).collect();
+    let mut pos = vec![0; n];
+    for layer in layers {
+        for (order, &v) in layer.iter().enumerate() {
+            pos[v] = order;
+        }
+    }
+
+    for layer in layers {
+        let mut previous: Option<usize> = None;
+        for &v in layer {
+            let mut ws = neighbours[v].clone();
+            if ws.is_empty() {
+                continue;
+            }
+            ws.sort_by_key(|&w| pos[w]);
+            let mp = (ws.len() - 1) as f64 / 2.0;
+            for &w in &ws[mp.floor() as usize..=mp.ceil() as usize] {
+                if align[v] == v
+                    && previous.is_none_or(|p| p < pos[w])
+                    && !conflicts.contains(&(v.min(w), v.max(w)))
+                {
+                    align[w] = v;
+                    root[v] = root[w];
+                    align[v] = root[v];
+                    previous = Some(pos[w]);
+                }
+            }
+        }
+    }
+    (root, align)
+}
+
+/// Place the blocks as far left as their separation constraints allow, then pull
+/// each towards the right where that is free, so that blocks sit between neighbours
+fn horizontal_compaction(layering: &Layering, layers: &[Vec<usize>], root: &[usize], separation: f64) -> Vec<f64> {
+    let n = layering.order.len();
+
+    // Block graph: an edge from the block on the left to the block on the right of it
+    let mut successors: Vec<HashMap<usize, f64>> = vec![HashMap::new(); n];
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for layer in layers {
+        for pair in layer.windows(2) {
+            let (u, v) = (pair[0], pair[1]);
+            let gap = layering.gap(u, v, separation);
+            let (u_root, v_root) = (root[u], root[v]);
+            let entry = successors[u_root].entry(v_root).or_insert_with(|| {
+                predecessors[v_root].push(u_root);
+                0.0
+            });
+            *entry = entry.max(gap);
+        }
+    }
+    let mut blocks: Vec<usize> = (0..n).filter(|&v| root[v] == v).collect();
+    blocks.sort_unstable();
+
+    // Topological order of the blocks (the block graph is acyclic by construction)
+    let mut indegree = vec![0; n];
+    for &b in &blocks {
+        for &next in successors[b].keys() {
+            indegree[next] += 1;
+        }
+    }
+    let mut ready: Vec<usize> = blocks.iter().copied().filter(|&b| indegree[b] == 0).rev().collect();
+    let mut topological = Vec::with_capacity(blocks.len());
+    while let Some(b) = ready.pop() {
+        topological.push(b);
+        let mut next: Vec<usize> = successors[b].keys().copied().collect();
+        next.sort_unstable();
+        for w in next.into_iter().rev() {
+            indegree[w] -= 1;
+            if indegree[w] == 0 {
+                ready.push(w);
+            }
+        }
+    }
+
+    let mut xs = vec![0.0; n];
+    for &b in &topological {
+        xs[b] = predecessors[b].iter().map(|&p| xs[p] + successors[p][&b]).fold(0.0, f64::max);
+    }
+    for &b in topological.iter().rev() {
+        let max = successors[b].iter().map(|(&s, &gap)| xs[s] - gap).fold(f64::INFINITY, f64::min);
+        if max.is_finite() {
+            xs[b] = xs[b].max(max);
+        }
+    }
+
+    (0..n).map(|v| xs[root[v]]).collect()
+}
+
+fn brandes_koepf(layering: &Layering, separation: f64) -> Vec<f64> {
+    let n = layering.order.len();
+    let conflicts = type1_conflicts(layering);
+
+    let mut alignments: Vec<(bool, Vec<f64>)> = Vec::with_capacity(4);
+    for downward in [true, false] {
+        for leftmost in [true, false] {
+            let mut layers: Vec<Vec<usize>> = layering.layers.clone();
+            if !downward {
+                layers.reverse();
+            }
+            if !leftmost {
+                for layer in &mut layers {
+                    layer.reverse();
+                }
+            }
+            let neighbours = if downward { &layering.up } else { &layering.down };
+            let (root, _) = vertical_alignment(layering, &layers, &conflicts, neighbours);
+            let mut xs = horizontal_compaction(layering, &layers, &root, separation);
+            if !leftmost {
+                for x in &mut xs {
+                    *x = -*x;
+                }
+            }
+            alignments.push((leftmost, xs));
+        }
+    }
+
+    // Align all four to the narrowest one: left alignments by their left border,
+    // right alignments by their right border
+    let extent = |xs: &[f64]| -> (f64, f64) {
+        let min = (0..n).map(|v| xs[v] - layering.breadth[v] / 2.0).fold(f64::INFINITY, f64::min);
+        let max = (0..n).map(|v| xs[v] + layering.breadth[v] / 2.0).fold(f64::NEG_INFINITY, f64::max);
+        (min, max)
+    };
+    let narrowest = alignments.iter()
+        .map(|(_, xs)| extent(xs))
+        .min_by(|a, b| (a.1 - a.0).total_cmp(&(b.1 - b.0)))
+        .unwrap_or((0.0, 0.0));
+    for (leftmost, xs) in &mut alignments {
+        let (min, max) = extent(xs);
+        let shift = if *leftmost { narrowest.0 - min } else { narrowest.1 - max };
+        for x in xs.iter_mut() {
+            *x += shift;
+        }
+    }
+
+    // Balance: the mean of the two median candidates
+    (0..n)
+        .map(|v| {
+            let mut candidates: Vec<f64> = alignments.iter().map(|(_, xs)| xs[v]).collect();
+            candidates.sort_by(|a, b| a.total_cmp(b));
+            (candidates[1] + candidates[2]) / 2.0
+        })
+        .collect()
+}
+
+/// Keep every chain of dummy nodes straight, placing segments left to right in
+/// dependency order and then pulling each towards the mean of its neighbours
+fn linear_segments(layering: &Layering, separation: f64) -> Vec<f64> {
+    let n = layering.order.len();
+
+    // Chains of dummies form one segment; every other node is a segment of its own
+    let mut segment_of: Vec<usize> = (0..n).collect();
+    for layer in &layering.layers {
+        for &v in layer {
+            if layering.dummy[v] {
+                if let Some(&u) = layering.up[v].iter().find(|&&u| layering.dummy[u]) {
+                    segment_of[v] = segment_of[u];
+                }
+            }
+        }
+    }
+
+    loop {
+        // Constraint graph between segments: left neighbour -> right neighbour
+        let mut constraints: Vec<HashMap<usize, f64>> = vec![HashMap::new(); n];
+        for layer in &layering.layers {
+            for pair in layer.windows(2) {
+                let (a, b) = (segment_of[pair[0]], segment_of[pair[1]]);
+                let gap = layering.gap(pair[0], pair[1], separation);
+                let entry = constraints[a].entry(b).or_insert(0.0);
+                *entry = entry.max(gap);
+            }
+        }
+
+        match topological_segments(&constraints, &segment_of) {
+            Some(order) => return place_segments(layering, &segment_of, &constraints, &order),
+            None => {
+                // Two segments swap sides somewhere: give up on keeping one of them straight
+                let split = (0..n).rev().find(|&v| segment_of[v] != v).expect("only multi-node segments form cycles");
+                let segment = segment_of[split];
+                for (v, of) in segment_of.iter_mut().enumerate() {
+                    if *of == segment {
+                        *of = v;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Segments in an order where every constraint points forward, or `None` on a cycle
+fn topological_segments(constraints: &[HashMap<usize, f64>], segment_of: &[usize]) -> Option<Vec<usize>> {
+    let mut segments: Vec<usize> = segment_of.to_vec();
+    segments.sort_unstable();
+    segments.dedup();
+
+    let mut indegree = vec![0; constraints.len()];
+    for &s in &segments {
+        for &t in constraints[s].keys() {
+            if t != s {
+                indegree[t] += 1;
+            }
+        }
+    }
+    let mut ready: Vec<usize> = segments.iter().copied().filter(|&s| indegree[s] == 0).rev().collect();
+    let mut order = Vec::with_capacity(segments.len());
+    while let Some(s) = ready.pop() {
+        order.push(s);
+        let mut next: Vec<usize> = constraints[s].keys().copied().filter(|&t| t != s).collect();
+        next.sort_unstable();
+        for t in next.into_iter().rev() {
+            indegree[t] -= 1;
+            if indegree[t] == 0 {
+                ready.push(t);
+            }
+        }
+    }
+    (order.len() == segments.len() && segments.iter().all(|&s| !constraints[s].contains_key(&s))).then_some(order)
+}
+
+fn place_segments(layering: &Layering, segment_of: &[usize], constraints: &[HashMap<usize, f64>], order: &[usize]) -> Vec<f64> {
+    let n = segment_of.len();
+    let mut predecessors: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n];
+    for &s in order {
+        for (&t, &gap) in &constraints[s] {
+            predecessors[t].push((s, gap));
+        }
+    }
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for v in 0..n {
+        members[segment_of[v]].push(v);
+    }
+
+    // Leftmost feasible placement
+    let mut xs = vec![0.0; n];
+    for &s in order {
+        xs[s] = predecessors[s].iter().map(|&(p, gap)| xs[p] + gap).fold(0.0, f64::max);
+    }
+
+    // Pull segments towards their neighbours without violating any constraint
+    for _ in 0..BALANCING_ROUNDS {
+        let mut moved = false;
+        for &s in order {
+            let neighbours: Vec<f64> = members[s].iter()
+                .flat_map(|&v| layering.up[v].iter().chain(&layering.down[v]))
+                .filter(|&&w| segment_of[w] != s)
+                .map(|&w| xs[segment_of[w]])
+                .collect();
+            if neighbours.is_empty() {
+                continue;
+            }
+            let target = neighbours.iter().sum::<f64>() / neighbours.len() as f64;
+            let low = predecessors[s].iter().map(|&(p, gap)| xs[p] + gap).fold(f64::NEG_INFINITY, f64::max);
+            let high = constraints[s].iter().map(|(&t, &gap)| xs[t] - gap).fold(f64::INFINITY, f64::min);
+            let x = target.max(low).min(high);
+            if (x - xs[s]).abs() > 1e-6 {
+                xs[s] = x;
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    (0..n).map(|v| xs[segment_of[v]]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Edge, Node};
+    use rand::{Rng, SeedableRng};
+
+    const ALL: [NodePlacement; 3] = [NodePlacement::BrandesKoepf, NodePlacement::LinearSegments, NodePlacement::Simple];
+
+    fn layered(layers: &[&[&str]], edges: &[(&str, &str)]) -> (Vec<Vec<Id>>, Graph) {
+        let mut graph = Graph::new();
+        for id in layers.iter().flat_map(|layer| layer.iter()) {
+            graph.add_node(Node::new(*id));
+        }
+        for (i, (source, target)) in edges.iter().enumerate() {
+            graph.add_edge(Edge::new(format!("e{}", i), *source, *target));
+        }
+        (layers.iter().map(|layer| layer.iter().map(|id| id.to_string()).collect()).collect(), graph)
+    }
+
+    fn assert_separated(layers: &[Vec<Id>], xs: &HashMap<Id, f64>, breadth: &dyn Fn(&Id) -> f64, separation: f64, dummies: &HashSet<Id>) {
+        for layer in layers {
+            for pair in layer.windows(2) {
+                let margin = |id: &Id| if dummies.contains(id) { separation / 4.0 } else { separation / 2.0 };
+                let needed = breadth(&pair[0]) / 2.0 + margin(&pair[0]) + margin(&pair[1]) + breadth(&pair[1]) / 2.0;
+                assert!(xs[&pair[1]] - xs[&pair[0]] >= needed - 1e-6, "{:?}: {} vs {}", pair, xs[&pair[1]] - xs[&pair[0]], needed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_brandes_koepf_straightens_edges() {
+        // Simple centring would put c halfway between a and b
+        let (layers, graph) = layered(&[&["a", "b"], &["c"], &["d"]], &[("a", "c"), ("c", "d")]);
+        let xs = place_nodes(&layers, &graph, &HashSet::new(), |_| 10.0, 20.0, NodePlacement::BrandesKoepf);
+        assert_eq!(xs["a"], xs["c"]);
+        assert_eq!(xs["c"], xs["d"]);
+        assert!((xs["b"] - xs["a"] - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_dummy_chains_stay_straight() {
+        let (layers, graph) = layered(
+            &[&["a", "b"], &["x1", "c"], &["x2", "d"], &["e"]],
+            &[("a", "x1"), ("x1", "x2"), ("x2", "e"), ("b", "c"), ("c", "d"), ("d", "e"), ("a", "c")],
+        );
+        let dummies: HashSet<Id> = ["x1", "x2"].iter().map(|id| id.to_string()).collect();
+        for placement in [NodePlacement::BrandesKoepf, NodePlacement::LinearSegments] {
+            let xs = place_nodes(&layers, &graph, &dummies, |id| if dummies.contains(id) { 0.0 } else { 10.0 }, 20.0, placement);
+            assert!((xs["x1"] - xs["x2"]).abs() < 1e-9, "{:?}", placement);
+        }
+    }
+
+    #[test]
+    fn test_separation_is_respected() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(8);
+        for _ in 0..20 {
+            let layer_count = rng.gen_range(2..5);
+            let layers: Vec<Vec<Id>> = (0..layer_count)
+                .map(|l| (0..rng.gen_range(1..6)).map(|i| format!("n{}_{}", l, i)).collect())
+                .collect();
+            let mut graph = Graph::new();
+            for id in layers.iter().flatten() {
+                graph.add_node(Node::new(id.clone()));
+            }
+            for l in 0..layer_count - 1 {
+                for (i, source) in layers[l].iter().enumerate() {
+                    for (j, target) in layers[l + 1].iter().enumerate() {
+                        if rng.gen_bool(0.4) {
+                            graph.add_edge(Edge::new(format!("e{}_{}_{}", l, i, j), source.clone(), target.clone()));
+                        }
+                    }
+                }
+            }
+            let dummies: HashSet<Id> = layers.iter().flatten().filter(|_| rng.gen_bool(0.3)).cloned().collect();
+            let widths: HashMap<Id, f64> = layers.iter().flatten().map(|id| (id.clone(), rng.gen_range(0.0..60.0))).collect();
+            let breadth = |id: &Id| widths[id];
+            for placement in ALL {
+                let xs = place_nodes(&layers, &graph, &dummies, breadth, 15.0, placement);
+                assert_separated(&layers, &xs, &breadth, 15.0, &dummies);
+            }
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Barnes–Hut quadtree for approximating all-pairs repulsion in O(n log n).
+//!
+//! The tree stores weighted points. Distant cells are treated as a single body
+//! at their centre of mass when `cell_size / distance < theta`; a `theta` of 0
+//! disables the approximation and yields the exact all-pairs result.
+
+/// Maximum subdivision depth; points that still share a cell at this depth
+/// (e.g. coincident points) are kept together in one leaf.
+const MAX_DEPTH: usize = 32;
+
+/// End marker for the per-leaf body lists
+const NONE: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct Cell {
+    center: (f64, f64),
+    half_size: f64,
+    mass: f64,
+    center_of_mass: (f64, f64),
+    /// Indices of the four child cells, or `None` for a leaf
+    children: Option<[usize; 4]>,
+    /// First point stored in a leaf (`NONE` if empty); further points follow `QuadTree::next`
+    first_body: usize,
+}
+
+impl Cell {
+    fn new(center: (f64, f64), half_size: f64) -> Self {
+        Self {
+            center,
+            half_size,
+            mass: 0.0,
+            center_of_mass: (0.0, 0.0),
+            children: None,
+            first_body: NONE,
+        }
+    }
+
+    fn quadrant(&self, pos: (f64, f64)) -> usize {
+        let east = pos.0 >= self.center.0;
+        let south = pos.1 >= self.center.1;
+        (east as usize) | ((south as usize) << 1)
+    }
+
+    fn contains(&self, pos: (f64, f64)) -> bool {
+        (pos.0 - self.center.0).abs() <= self.half_size && (pos.1 - self.center.1).abs() <= self.half_size
+    }
+}
+
+/// Quadtree over a set of weighted 2D points
+#[derive(Debug, Clone)]
+pub struct QuadTree {
+    cells: Vec<Cell>,
+    positions: Vec<(f64, f64)>,
+    weights: Vec<f64>,
+    /// Next point in the same leaf, for leaves at `MAX_DEPTH`
+    next: Vec<usize>,
+}
+
+impl QuadTree {
+    /// Build a tree from point positions and their masses
+    pub fn build(positions: &[(f64, f64)], weights: &[f64]) -> Self {
+        let mut min = (f64::INFINITY, f64::INFINITY);
+        let mut max = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for pos in positions {
+            min = (min.0.min(pos.0), min.1.min(pos.1));
+            max = (max.0.max(pos.0), max.1.max(pos.1));
+        }
+
+        let (center, half_size) = if positions.is_empty() {
+            ((0.0, 0.0), 1.0)
+        } else {
+            let half = ((max.0 - min.0).max(max.1 - min.1) / 2.0).max(1e-6);
+            (((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0), half * 1.0001)
+        };
+
+        let mut tree = Self {
+            cells: vec![Cell::new(center, half_size)],
+            positions: positions.to_vec(),
+            weights: weights.to_vec(),
+            next: vec![NONE; positions.len()],
+        };
+
+        for i in 0..positions.len() {
+            tree.insert(i);
+        }
+
+        tree
+    }
+
+    /// Number of points in the tree
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Whether the tree holds no points
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    fn insert(&mut self, body: usize) {
+        let pos = self.positions[body];
+        let weight = self.weights.get(body).copied().unwrap_or(1.0);
+        let mut cell_idx = 0;
+        let mut depth = 0;
+
+        loop {
+            // Every cell on the path accumulates the body's mass
+            let cell = &mut self.cells[cell_idx];
+            let total = cell.mass + weight;
+            if total > 0.0 {
+                cell.center_of_mass = (
+                    (cell.center_of_mass.0 * cell.mass + pos.0 * weight) / total,
+                    (cell.center_of_mass.1 * cell.mass + pos.1 * weight) / total,
+                );
+            }
+            cell.mass = total;
+
+            match cell.children {
+                Some(children) => {
+                    cell_idx = children[cell.quadrant(pos)];
+                    depth += 1;
+                }
+                None if cell.first_body == NONE || depth >= MAX_DEPTH => {
+                    self.next[body] = cell.first_body;
+                    cell.first_body = body;
+                    return;
+                }
+                None => {
+                    // Split the leaf and push its single body one level down
+                    let other = std::mem::replace(&mut cell.first_body, NONE);
+                    let children = self.subdivide(cell_idx);
+                    {
+                        let other_pos = self.positions[other];
+                        let other_weight = self.weights.get(other).copied().unwrap_or(1.0);
+                        let child_idx = children[self.cells[cell_idx].quadrant(other_pos)];
+                        let child = &mut self.cells[child_idx];
+                        let total = child.mass + other_weight;
+                        if total > 0.0 {
+                            child.center_of_mass = (
+                                (child.center_of_mass.0 * child.mass + other_pos.0 * other_weight) / total,
+                                (child.center_of_mass.1 * child.mass + other_pos.1 * other_weight) / total,
+                            );
+                        }
+                        child.mass = total;
+                        child.first_body = other;
+                    }
+                    cell_idx = children[self.cells[cell_idx].quadrant(pos)];
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    fn subdivide(&mut self, cell_idx: usize) -> [usize; 4] {
+        let center = self.cells[cell_idx].center;
+        let quarter = self.cells[cell_idx].half_size / 2.0;
+        let first = self.cells.len();
+
+        for quadrant in 0..4 {
+            let dx = if quadrant & 1 == 1 { quarter } else { -quarter };
+            let dy = if quadrant & 2 == 2 { quarter } else { -quarter };
+            self.cells.push(Cell::new((center.0 + dx, center.1 + dy), quarter));
+        }
+
+        let children = [first, first + 1, first + 2, first + 3];
+        self.cells[cell_idx].children = Some(children);
+        children
+    }
+
+    /// Accumulate the repulsive force on point `body` from every other point.
+    ///
+    /// `magnitude(distance_squared, mass)` returns the force magnitude exerted by a
+    /// body (or cell) of the given mass; the force pushes `body` directly away from it.
+    pub fn repulsion<F>(&self, body: usize, theta: f64, magnitude: F) -> (f64, f64)
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        let pos = self.positions[body];
+        let mut force = (0.0, 0.0);
+        let mut stack = Vec::with_capacity(4 * MAX_DEPTH);
+        stack.push(0);
+
+        let push_away = |from: (f64, f64), mass: f64, force: &mut (f64, f64)| {
+            let dx = pos.0 - from.0;
+            let dy = pos.1 - from.1;
+            let distance_squared = dx * dx + dy * dy;
+            if distance_squared <= 0.0 {
+                return;
+            }
+            let f = magnitude(distance_squared, mass);
+            let distance = distance_squared.sqrt();
+            force.0 += f * dx / distance;
+            force.1 += f * dy / distance;
+        };
+
+        while let Some(cell_idx) = stack.pop() {
+            let cell = &self.cells[cell_idx];
+            if cell.mass <= 0.0 {
+                continue;
+            }
+
+            match cell.children {
+                None => {
+                    let mut other = cell.first_body;
+                    while other != NONE {
+                        if other != body {
+                            let weight = self.weights.get(other).copied().unwrap_or(1.0);
+                            push_away(self.positions[other], weight, &mut force);
+                        }
+                        other = self.next[other];
+                    }
+                }
+                Some(children) => {
+                    let dx = pos.0 - cell.center_of_mass.0;
+                    let dy = pos.1 - cell.center_of_mass.1;
+                    let distance = (dx * dx + dy * dy).sqrt();
+                    let size = cell.half_size * 2.0;
+
+                    if !cell.contains(pos) && distance > 0.0 && size / distance < theta {
+                        push_away(cell.center_of_mass, cell.mass, &mut force);
+                    } else {
+                        stack.extend_from_slice(&children);
+                    }
+                }
+            }
+        }
+
+        force
+    }
+}
+
+/// Inverse-square repulsion (`strength / d²`) between unit-mass points, as used by
+/// the force-directed engines. Pairs closer than `sqrt(0.1)` are skipped to avoid
+/// division by zero.
+pub fn inverse_square_repulsion(positions: &[(f64, f64)], theta: f64, strength: f64) -> Vec<(f64, f64)> {
+    let weights = vec![1.0; positions.len()];
+    let tree = QuadTree::build(positions, &weights);
+
+    (0..positions.len())
+        .map(|i| {
+            tree.repulsion(i, theta, |distance_squared, mass| {
+                if distance_squared < 0.1 {
+                    0.0
+                } else {
+                    strength * mass / distance_squared
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inverse_square(distance_squared: f64, mass: f64) -> f64 {
+        mass / distance_squared
+    }
+
+    fn exact(positions: &[(f64, f64)], body: usize) -> (f64, f64) {
+        let mut force = (0.0, 0.0);
+        for (j, other) in positions.iter().enumerate() {
+            if j == body { continue; }
+            let dx = positions[body].0 - other.0;
+            let dy = positions[body].1 - other.1;
+            let d2 = dx * dx + dy * dy;
+            let d = d2.sqrt();
+            force.0 += dx / d / d2;
+            force.1 += dy / d / d2;
+        }
+        force
+    }
+
+    fn grid(n: usize) -> Vec<(f64, f64)> {
+        (0..n * n).map(|i| ((i % n) as f64 * 10.0 + (i as f64 * 0.37).sin(), (i / n) as f64 * 10.0)).collect()
+    }
+
+    #[test]
+    fn test_theta_zero_matches_exact() {
+        let positions = grid(8);
+        let weights = vec![1.0; positions.len()];
+        let tree = QuadTree::build(&positions, &weights);
+
+        for body in 0..positions.len() {
+            let approx = tree.repulsion(body, 0.0, inverse_square);
+            let expected = exact(&positions, body);
+            assert!((approx.0 - expected.0).abs() < 1e-9);
+            assert!((approx.1 - expected.1).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_approximation_is_close() {
+        let positions = grid(12);
+        let weights = vec![1.0; positions.len()];
+        let tree = QuadTree::build(&positions, &weights);
+
+        for body in [0, 17, 70, 143] {
+            let approx = tree.repulsion(body, 0.5, inverse_square);
+            let expected = exact(&positions, body);
+            let error = ((approx.0 - expected.0).powi(2) + (approx.1 - expected.1).powi(2)).sqrt();
+            let norm = (expected.0.powi(2) + expected.1.powi(2)).sqrt();
+            assert!(error <= 0.05 * norm.max(1e-3), "body {}: error {} vs norm {}", body, error, norm);
+        }
+    }
+
+    #[test]
+    fn test_coincident_points() {
+        let positions = vec![(1.0, 1.0); 5];
+        let weights = vec![1.0; 5];
+        let tree = QuadTree::build(&positions, &weights);
+
+        assert_eq!(tree.len(), 5);
+        // Coincident points exert no (undefined-direction) force on each other
+        assert_eq!(tree.repulsion(0, 0.8, inverse_square), (0.0, 0.0));
+    }
+}
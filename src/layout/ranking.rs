@@ -0,0 +1,485 @@
+//! Rank assignment for layered layouts, following Gansner et al., "A Technique for
+//! Drawing Directed Graphs" as implemented by dagre.js.
+//!
+//! Every edge `tail -> head` must satisfy `rank(head) - rank(tail) >= minlen`. The
+//! longest-path ranker finds any such assignment; the tight-tree ranker then pulls
+//! nodes together until a spanning tree of edges is tight (`slack == 0`), and network
+//! simplex exchanges tree edges until the total `weight * length` is minimal.
+
+use std::collections::BTreeMap;
+use crate::types::{Graph, Id};
+
+/// Rank assignment strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranker {
+    LongestPath,
+    TightTree,
+    NetworkSimplex,
+}
+
+/// Tolerance for negative cut values, which are sums of edge weights
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone)]
+struct RankEdge {
+    tail: usize,
+    head: usize,
+    minlen: i64,
+    weight: f64,
+}
+
+/// The graph as a DAG over node indices, with parallel edges merged
+struct Problem {
+    ids: Vec<Id>,
+    edges: Vec<RankEdge>,
+    /// Incident edge indices per node
+    incident: Vec<Vec<usize>>,
+}
+
+impl Problem {
+    fn new(graph: &Graph) -> Self {
+        let ids: Vec<Id> = graph.sorted_node_ids().into_iter().cloned().collect();
+        let index: std::collections::HashMap<&Id, usize> = ids.iter().enumerate().map(|(i, id)| (id, i)).collect();
+
+        let mut out: Vec<Vec<(usize, u32, f64)>> = vec![Vec::new(); ids.len()];
+        for edge in graph.sorted_edges() {
+            if let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) {
+                if source != target {
+                    out[source].push((target, edge.minlen, edge.weight));
+                }
+            }
+        }
+
+        // Reverse the back edges of a depth-first search so that the graph is acyclic,
+        // then merge parallel edges: weights add up and the longest minlen wins
+        let mut merged: BTreeMap<(usize, usize), (i64, f64)> = BTreeMap::new();
+        let mut state = vec![0u8; ids.len()]; // 0: unvisited, 1: on the stack, 2: done
+        for start in 0..ids.len() {
+            if state[start] != 0 {
+                continue;
+            }
+            let mut stack = vec![(start, 0)];
+            state[start] = 1;
+            while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+                if let Some(&(target, minlen, weight)) = out[node].get(*next) {
+                    *next += 1;
+                    let key = if state[target] == 1 { (target, node) } else { (node, target) };
+                    let entry = merged.entry(key).or_insert((0, 0.0));
+                    entry.0 = entry.0.max(minlen as i64);
+                    entry.1 += weight;
+                    if state[target] == 0 {
+                        state[target] = 1;
+                        stack.push((target, 0));
+                    }
+                } else {
+                    state[node] = 2;
+                    stack.pop();
+                }
+            }
+        }
+
+        let edges: Vec<RankEdge> = merged
+            .into_iter()
+            .map(|((tail, head), (minlen, weight))| RankEdge { tail, head, minlen, weight })
+            .collect();
+        let mut incident = vec![Vec::new(); ids.len()];
+        for (i, edge) in edges.iter().enumerate() {
+            incident[edge.tail].push(i);
+            incident[edge.head].push(i);
+        }
+
+        Self { ids, edges, incident }
+    }
+
+    fn slack(&self, ranks: &[i64], edge: usize) -> i64 {
+        let edge = &self.edges[edge];
+        ranks[edge.head] - ranks[edge.tail] - edge.minlen
+    }
+
+    /// Connected components, each listed in ascending node order
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut component = vec![usize::MAX; self.ids.len()];
+        let mut components = Vec::new();
+        for start in 0..self.ids.len() {
+            if component[start] != usize::MAX {
+                continue;
+            }
+            let mut members = vec![start];
+            component[start] = components.len();
+            let mut i = 0;
+            while i < members.len() {
+                for &edge in &self.incident[members[i]] {
+                    let other = self.other_end(edge, members[i]);
+                    if component[other] == usize::MAX {
+                        component[other] = components.len();
+                        members.push(other);
+                    }
+                }
+                i += 1;
+            }
+            members.sort_unstable();
+            components.push(members);
+        }
+        components
+    }
+
+    fn other_end(&self, edge: usize, node: usize) -> usize {
+        let edge = &self.edges[edge];
+        if edge.tail == node { edge.head } else { edge.tail }
+    }
+
+    /// Rank every node as high as its successors allow; sinks get rank 0
+    fn longest_path(&self) -> Vec<i64> {
+        let n = self.ids.len();
+        let mut outstanding: Vec<usize> = vec![0; n];
+        for edge in &self.edges {
+            outstanding[edge.tail] += 1;
+        }
+        let mut ranks = vec![0; n];
+        let mut ready: Vec<usize> = (0..n).filter(|&v| outstanding[v] == 0).collect();
+        while let Some(node) = ready.pop() {
+            let successors = self.incident[node].iter().filter(|&&e| self.edges[e].tail == node);
+            ranks[node] = successors.map(|&e| ranks[self.edges[e].head] - self.edges[e].minlen).min().unwrap_or(0);
+            for &edge in &self.incident[node] {
+                let tail = self.edges[edge].tail;
+                if tail != node {
+                    outstanding[tail] -= 1;
+                    if outstanding[tail] == 0 {
+                        ready.push(tail);
+                    }
+                }
+            }
+        }
+        ranks
+    }
+}
+
+/// A spanning tree of one component, with the bookkeeping network simplex needs.
+/// Components are disjoint, so the per-node and per-edge arrays are shared by all.
+struct SpanningTree<'a> {
+    problem: &'a Problem,
+    root: usize,
+    members: &'a [usize],
+    /// Edges of the component
+    edges: Vec<usize>,
+    in_tree_node: Vec<bool>,
+    in_tree: Vec<bool>,
+    tree_adjacent: Vec<Vec<usize>>,
+    /// Tree edge towards the root, per node
+    parent_edge: Vec<Option<usize>>,
+    low: Vec<usize>,
+    lim: Vec<usize>,
+    cut_values: Vec<f64>,
+    /// Nodes in depth-first preorder from the root
+    preorder: Vec<usize>,
+}
+
+impl<'a> SpanningTree<'a> {
+    fn new(problem: &'a Problem) -> Self {
+        let n = problem.ids.len();
+        Self {
+            problem,
+            root: 0,
+            members: &[],
+            edges: Vec::new(),
+            in_tree_node: vec![false; n],
+            in_tree: vec![false; problem.edges.len()],
+            tree_adjacent: vec![Vec::new(); n],
+            parent_edge: vec![None; n],
+            low: vec![0; n],
+            lim: vec![0; n],
+            cut_values: vec![0.0; problem.edges.len()],
+            preorder: Vec::new(),
+        }
+    }
+
+    /// Grow a tree of tight edges over a component, shifting the tree's ranks
+    /// towards the nearest outside node whenever no tight edge leaves the tree
+    fn feasible(&mut self, members: &'a [usize], ranks: &mut [i64]) {
+        let problem = self.problem;
+        let root = members[0];
+        self.root = root;
+        self.members = members;
+        self.edges = members.iter().flat_map(|&v| problem.incident[v].iter().copied()).collect();
+        self.edges.sort_unstable();
+        self.edges.dedup();
+
+        self.in_tree_node[root] = true;
+        let mut size = 1;
+        let mut frontier = vec![root];
+        loop {
+            // Extend the tree along tight edges
+            while let Some(node) = frontier.pop() {
+                for &edge in &problem.incident[node] {
+                    let other = problem.other_end(edge, node);
+                    if !self.in_tree_node[other] && problem.slack(ranks, edge) == 0 {
+                        self.in_tree_node[other] = true;
+                        self.add_edge(edge);
+                        size += 1;
+                        frontier.push(other);
+                    }
+                }
+            }
+            if size == members.len() {
+                break;
+            }
+
+            // Make the edge with the least slack between the tree and the rest tight
+            let edge = self.edges.iter()
+                .copied()
+                .filter(|&e| self.in_tree_node[problem.edges[e].tail] != self.in_tree_node[problem.edges[e].head])
+                .min_by_key(|&e| problem.slack(ranks, e))
+                .expect("component is connected");
+            let slack = problem.slack(ranks, edge);
+            let delta = if self.in_tree_node[problem.edges[edge].tail] { slack } else { -slack };
+            for &v in members {
+                if self.in_tree_node[v] {
+                    ranks[v] += delta;
+                }
+            }
+            frontier = members.iter().copied().filter(|&v| self.in_tree_node[v]).collect();
+        }
+
+        self.init_low_lim();
+        self.init_cut_values();
+    }
+
+    fn add_edge(&mut self, edge: usize) {
+        self.in_tree[edge] = true;
+        self.tree_adjacent[self.problem.edges[edge].tail].push(edge);
+        self.tree_adjacent[self.problem.edges[edge].head].push(edge);
+    }
+
+    fn remove_edge(&mut self, edge: usize) {
+        self.in_tree[edge] = false;
+        self.tree_adjacent[self.problem.edges[edge].tail].retain(|&e| e != edge);
+        self.tree_adjacent[self.problem.edges[edge].head].retain(|&e| e != edge);
+    }
+
+    /// Number the tree in postorder: `lim` is a node's own number and `low` the
+    /// smallest number in its subtree, so `v` is below `u` iff `low[u] <= lim[v] <= lim[u]`
+    fn init_low_lim(&mut self) {
+        self.preorder.clear();
+        self.parent_edge[self.root] = None;
+        let mut next_lim = 1;
+        let mut stack = vec![(self.root, 0, next_lim)];
+        self.preorder.push(self.root);
+        while let Some(&mut (node, ref mut next, low)) = stack.last_mut() {
+            if let Some(&edge) = self.tree_adjacent[node].get(*next) {
+                *next += 1;
+                if Some(edge) == self.parent_edge[node] {
+                    continue;
+                }
+                let child = self.problem.other_end(edge, node);
+                self.parent_edge[child] = Some(edge);
+                self.preorder.push(child);
+                stack.push((child, 0, next_lim));
+            } else {
+                self.low[node] = low;
+                self.lim[node] = next_lim;
+                next_lim += 1;
+                stack.pop();
+            }
+        }
+    }
+
+    fn is_descendant(&self, node: usize, ancestor: usize) -> bool {
+        self.low[ancestor] <= self.lim[node] && self.lim[node] <= self.lim[ancestor]
+    }
+
+    /// Cut value of every tree edge: the weight of the edges crossing from the tail
+    /// side of the tree to the head side, minus those crossing back. Children are
+    /// handled before their parents so that each value builds on the ones below it.
+    fn init_cut_values(&mut self) {
+        for i in (1..self.preorder.len()).rev() {
+            let child = self.preorder[i];
+            let parent_edge = self.parent_edge[child].expect("non-root nodes have a parent");
+            let parent = self.problem.other_end(parent_edge, child);
+            let child_is_tail = self.problem.edges[parent_edge].tail == child;
+
+            let mut cut_value = self.problem.edges[parent_edge].weight;
+            for &edge in &self.problem.incident[child] {
+                let is_out_edge = self.problem.edges[edge].tail == child;
+                let other = self.problem.other_end(edge, child);
+                if other == parent {
+                    continue;
+                }
+                let points_to_head = is_out_edge == child_is_tail;
+                let weight = self.problem.edges[edge].weight;
+                cut_value += if points_to_head { weight } else { -weight };
+                if self.in_tree[edge] {
+                    let other_cut_value = self.cut_values[edge];
+                    cut_value += if points_to_head { -other_cut_value } else { other_cut_value };
+                }
+            }
+            self.cut_values[parent_edge] = cut_value;
+        }
+    }
+
+    /// A tree edge with a negative cut value, whose removal would shorten the layout
+    fn leave_edge(&self) -> Option<usize> {
+        self.edges.iter().copied().find(|&e| self.in_tree[e] && self.cut_values[e] < -EPSILON)
+    }
+
+    /// The non-tree edge with the least slack that reconnects the two halves of the
+    /// tree left after removing `edge`, crossing in the opposite direction
+    fn enter_edge(&self, edge: usize, ranks: &[i64]) -> usize {
+        let RankEdge { tail, head, .. } = self.problem.edges[edge];
+        let (subtree, flip) = if self.lim[tail] > self.lim[head] { (head, true) } else { (tail, false) };
+        self.edges.iter()
+            .copied()
+            .filter(|&e| {
+                let candidate = &self.problem.edges[e];
+                flip == self.is_descendant(candidate.tail, subtree) && flip != self.is_descendant(candidate.head, subtree)
+            })
+            .min_by_key(|&e| self.problem.slack(ranks, e))
+            .expect("a cut of a connected component is crossed both ways")
+    }
+
+    fn exchange(&mut self, leaving: usize, entering: usize, ranks: &mut [i64]) {
+        self.remove_edge(leaving);
+        self.add_edge(entering);
+        self.init_low_lim();
+        self.init_cut_values();
+
+        // Re-derive ranks from the root so that every tree edge is tight
+        for i in 1..self.preorder.len() {
+            let node = self.preorder[i];
+            let edge = &self.problem.edges[self.parent_edge[node].expect("non-root nodes have a parent")];
+            ranks[node] = if edge.tail == node { ranks[edge.head] - edge.minlen } else { ranks[edge.tail] + edge.minlen };
+        }
+    }
+
+    fn network_simplex(&mut self, ranks: &mut [i64]) {
+        // Degenerate pivots can cycle in theory; bound the work like dagre's users expect
+        let max_exchanges = 10 * (self.edges.len() + self.members.len()) + 100;
+        for _ in 0..max_exchanges {
+            let Some(leaving) = self.leave_edge() else {
+                break;
+            };
+            let entering = self.enter_edge(leaving, ranks);
+            self.exchange(leaving, entering, ranks);
+        }
+    }
+}
+
+/// Assign a rank to every node; each connected component starts at rank 0
+pub fn rank(graph: &Graph, ranker: Ranker) -> BTreeMap<Id, usize> {
+    let problem = Problem::new(graph);
+    let mut ranks = problem.longest_path();
+
+    let components = problem.components();
+    let mut tree = SpanningTree::new(&problem);
+    for members in &components {
+        if ranker != Ranker::LongestPath {
+            tree.feasible(members, &mut ranks);
+            if ranker == Ranker::NetworkSimplex {
+                tree.network_simplex(&mut ranks);
+            }
+        }
+    }
+
+    let mut result = BTreeMap::new();
+    for members in &components {
+        let min = members.iter().map(|&v| ranks[v]).min().unwrap_or(0);
+        for &v in members {
+            result.insert(problem.ids[v].clone(), (ranks[v] - min) as usize);
+        }
+    }
+    result
+}
+
+/// Nodes grouped by rank, in ascending ID order within each rank. Ranks that only
+/// long edges pass through are kept as empty layers.
+pub fn rank_layers(graph: &Graph, ranker: Ranker) -> Vec<Vec<Id>> {
+    let ranks = rank(graph, ranker);
+    let mut layers = vec![Vec::new(); ranks.values().max().map_or(0, |&max| max + 1)];
+    for (id, rank) in ranks {
+        layers[rank].push(id);
+    }
+    layers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Edge, Node};
+    use rand::{Rng, SeedableRng};
+
+    fn graph(nodes: usize, edges: &[(usize, usize, u32, f64)]) -> Graph {
+        let mut graph = Graph::new();
+        for i in 0..nodes {
+            graph.add_node(Node::new(format!("n{}", i)));
+        }
+        for (i, &(source, target, minlen, weight)) in edges.iter().enumerate() {
+            let edge = Edge::new(format!("e{}", i), format!("n{}", source), format!("n{}", target));
+            graph.add_edge(edge.with_minlen(minlen).with_weight(weight));
+        }
+        graph
+    }
+
+    fn cost(ranks: &BTreeMap<Id, usize>, edges: &[(usize, usize, u32, f64)]) -> f64 {
+        edges.iter()
+            .map(|&(s, t, _, w)| w * (ranks[&format!("n{}", t)] as f64 - ranks[&format!("n{}", s)] as f64).abs())
+            .sum()
+    }
+
+    #[test]
+    fn test_minlen_is_honoured() {
+        let edges = [(0, 1, 2, 1.0), (1, 2, 1, 1.0), (0, 2, 1, 1.0)];
+        for ranker in [Ranker::LongestPath, Ranker::TightTree, Ranker::NetworkSimplex] {
+            let ranks = rank(&graph(3, &edges), ranker);
+            assert_eq!((ranks["n0"], ranks["n1"], ranks["n2"]), (0, 2, 3), "{:?}", ranker);
+        }
+    }
+
+    #[test]
+    fn test_tight_tree_pulls_sources_down() {
+        // Longest path leaves n3 at the top; a tight tree puts it right above n2
+        let edges = [(0, 1, 1, 1.0), (1, 2, 1, 1.0), (3, 2, 1, 1.0)];
+        assert_eq!(rank(&graph(4, &edges), Ranker::LongestPath)["n3"], 1);
+        let ranks = rank(&graph(4, &edges), Ranker::TightTree);
+        assert_eq!(ranks["n2"] - ranks["n3"], 1);
+    }
+
+    #[test]
+    fn test_network_simplex_is_optimal() {
+        // Compare against brute force over all rank assignments of small random DAGs
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        for _ in 0..20 {
+            let n = 5;
+            let mut edges = Vec::new();
+            for s in 0..n {
+                for t in s + 1..n {
+                    if rng.gen_bool(0.5) {
+                        edges.push((s, t, rng.gen_range(1..=2), rng.gen_range(1..=4) as f64));
+                    }
+                }
+            }
+            let ranks = rank(&graph(n, &edges), Ranker::NetworkSimplex);
+            for &(s, t, minlen, _) in &edges {
+                assert!(ranks[&format!("n{}", t)] >= ranks[&format!("n{}", s)] + minlen as usize);
+            }
+
+            let levels = 2 * n;
+            let mut best = f64::INFINITY;
+            for code in 0..levels.pow(n as u32) {
+                let assignment: Vec<usize> = (0..n).map(|i| code / levels.pow(i as u32) % levels).collect();
+                if edges.iter().all(|&(s, t, minlen, _)| assignment[t] >= assignment[s] + minlen as usize) {
+                    let candidate: BTreeMap<Id, usize> = assignment.iter().enumerate().map(|(i, &r)| (format!("n{}", i), r)).collect();
+                    best = best.min(cost(&candidate, &edges));
+                }
+            }
+            assert!((cost(&ranks, &edges) - best).abs() < 1e-9, "{:?}: {} vs optimum {}", edges, cost(&ranks, &edges), best);
+        }
+    }
+
+    #[test]
+    fn test_cycles_and_components() {
+        let edges = [(0, 1, 1, 1.0), (1, 2, 1, 1.0), (2, 0, 1, 1.0), (3, 4, 1, 1.0), (4, 4, 1, 1.0)];
+        let layers = rank_layers(&graph(6, &edges), Ranker::NetworkSimplex);
+        assert_eq!(layers[0], vec!["n0", "n3", "n5"]);
+        assert_eq!(layers[1], vec!["n1", "n4"]);
+        assert_eq!(layers[2], vec!["n2"]);
+    }
+}
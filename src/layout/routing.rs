@@ -0,0 +1,402 @@
+//! Drawable edge routes.
+//!
+//! Once a layout has placed the nodes, every edge gets an `EdgeRoute` running from
+//! the boundary of its source to the boundary of its target:
+//!
+//! - `"STRAIGHT"`: a single line, ignoring bend points.
+//! - `"POLYLINE"`: straight segments through the bend points.
+//! - `"ORTHOGONAL"`: horizontal and vertical segments through the bend points, turning
+//!   halfway between consecutive points along the main direction of the edge.
+//! - `"SPLINES"`: a smooth curve through the bend points. Edges without bend points
+//!   curve towards the centre of the layout, so that edges in the same region run
+//!   together rather than crossing at wide angles.
+//!
+//! Self-loops are drawn as loops on the right of their node, nested when a node has
+//! several. Edges between the same two nodes without bend points are fanned out side
+//! by side, unless `merge_edges` draws the ones with the same direction as one.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::types::{EdgeRoute, Graph, Id, LayoutOptions, Node, NodeShape, RouteStyle};
+
+/// How far a spline without bend points bows out, relative to its length
+const CURVATURE: f64 = 0.2;
+
+/// How far each self-loop reaches beyond the previous one
+const LOOP_SIZE: f64 = 15.0;
+
+/// Distance between neighbouring parallel edges
+const PARALLEL_SPACING: f64 = 8.0;
+
+type Point = (f64, f64);
+
+/// Two node IDs
+type Pair<'a> = (&'a Id, &'a Id);
+
+/// Replace the route of every edge whose endpoints are placed, using `edge_routing`
+/// from the options or else `default_routing`
+pub fn route_edges(graph: &mut Graph, options: &LayoutOptions, default_routing: &str) {
+    let routing = options.edge_routing.as_deref().unwrap_or(default_routing);
+    let parents: HashSet<String> = graph.nodes.values().filter_map(|node| node.parent.clone()).collect();
+    let positions: Vec<Point> = graph.nodes.values().filter_map(|node| node.position).collect();
+    let centre = bounding_centre(&positions);
+    let lanes = lanes(graph, options.merge_edges);
+
+    let mut routes = Vec::with_capacity(graph.edges.len());
+    for edge in graph.edges.values() {
+        let (Some(source), Some(target)) = (graph.nodes.get(&edge.source), graph.nodes.get(&edge.target)) else {
+            routes.push((edge.id.clone(), None));
+            continue;
+        };
+        let (Some(from), Some(to)) = (source.position, target.position) else {
+            routes.push((edge.id.clone(), None));
+            continue;
+        };
+        let lane = lanes.get(&edge.id).copied().unwrap_or(0.0);
+        if edge.source == edge.target {
+            routes.push((edge.id.clone(), Some(self_loop(source, from, lane, routing, &parents))));
+            continue;
+        }
+
+        // Parallel edges run beside each other: across the flow for orthogonal routes,
+        // perpendicular to the line between the nodes otherwise
+        let shift = if routing == "ORTHOGONAL" {
+            if (to.1 - from.1).abs() >= (to.0 - from.0).abs() { (lane, 0.0) } else { (0.0, lane) }
+        } else {
+            // The same side for both directions, so that a -> b and b -> a do not overlap
+            let (first, second) = if edge.source < edge.target { (from, to) } else { (to, from) };
+            let (dx, dy) = (second.0 - first.0, second.1 - first.1);
+            let length = (dx * dx + dy * dy).sqrt().max(1e-9);
+            (-dy / length * lane, dx / length * lane)
+        };
+        let (from, to) = ((from.0 + shift.0, from.1 + shift.1), (to.0 + shift.0, to.1 + shift.1));
+
+        let ends = Ends { source, target, from, to, parents: &parents };
+        let route = match routing {
+            "STRAIGHT" => straight(&ends),
+            "POLYLINE" => polyline(&ends, &edge.bend_points),
+            "ORTHOGONAL" => orthogonal(&ends, &edge.bend_points),
+            _ => spline(&ends, &edge.bend_points, centre), // "SPLINES"
+        };
+        routes.push((edge.id.clone(), Some(route)));
+    }
+
+    for (id, route) in routes {
+        if let Some(edge) = graph.edges.get_mut(&id) {
+            edge.route = route;
+        }
+    }
+}
+
+/// How far self-loops of a node reach beyond its right border, to reserve room for them
+pub fn self_loop_room(graph: &Graph, merge: bool) -> HashMap<Id, f64> {
+    let mut room = HashMap::new();
+    for edge in graph.edges.values().filter(|edge| edge.source == edge.target) {
+        let loops = room.entry(edge.source.clone()).or_insert(0.0);
+        *loops = if merge { LOOP_SIZE } else { *loops + LOOP_SIZE };
+    }
+    for loops in room.values_mut() {
+        *loops += LOOP_SIZE / 2.0;
+    }
+    room
+}
+
+/// Offset of every edge from the line between its nodes: self-loops count up from
+/// zero per node, other edges without bend points are centred around zero per pair
+/// of nodes. Merged edges share the lane of the first edge with their direction.
+fn lanes(graph: &Graph, merge: bool) -> HashMap<Id, f64> {
+    let mut groups: BTreeMap<Pair, Vec<(&Id, Pair)>> = BTreeMap::new();
+    for edge in graph.sorted_edges() {
+        if edge.source == edge.target || edge.bend_points.is_empty() {
+            let pair = if edge.source <= edge.target { (&edge.source, &edge.target) } else { (&edge.target, &edge.source) };
+            // Merged edges are told apart by their direction only
+            let key = if merge { (&edge.source, &edge.target) } else { (&edge.id, &edge.id) };
+            groups.entry(pair).or_default().push((&edge.id, key));
+        }
+    }
+
+    let mut lanes = HashMap::new();
+    for ((first, second), edges) in groups {
+        // Distinct lanes, in order of first use
+        let mut keys = Vec::new();
+        let mut lane_of = Vec::with_capacity(edges.len());
+        for &(_, key) in &edges {
+            let lane = keys.iter().position(|k| *k == key).unwrap_or_else(|| {
+                keys.push(key);
+                keys.len() - 1
+            });
+            lane_of.push(lane);
+        }
+
+        for (&(id, _), lane) in edges.iter().zip(lane_of) {
+            let offset = if first == second {
+                lane as f64
+            } else {
+                (lane as f64 - (keys.len() - 1) as f64 / 2.0) * PARALLEL_SPACING
+            };
+            lanes.insert(id.clone(), offset);
+        }
+    }
+    lanes
+}
+
+/// Loop on the right of a node; the `index`-th loop of a node reaches furthest out
+fn self_loop(node: &Node, centre: Point, index: f64, routing: &str, parents: &HashSet<String>) -> EdgeRoute {
+    let reach = LOOP_SIZE * (index + 1.0);
+    let angle = std::f64::consts::FRAC_PI_6;
+    let (width, height) = node.size_or(0.0);
+    // Far enough away that clipping ends on the boundary
+    let far = width + height + 1.0;
+    let mut start = clip(node, centre, (centre.0 + far * angle.cos(), centre.1 - far * angle.sin()), parents);
+    let mut end = clip(node, centre, (centre.0 + far * angle.cos(), centre.1 + far * angle.sin()), parents);
+    // Keep loops on nodes without a size open
+    start.1 = start.1.min(centre.1 - reach / 4.0);
+    end.1 = end.1.max(centre.1 + reach / 4.0);
+    let outside = centre.0 + width / 2.0 + reach;
+
+    let corners = vec![start, (outside, start.1), (outside, end.1), end];
+    match routing {
+        "STRAIGHT" | "POLYLINE" => EdgeRoute { style: RouteStyle::Polyline, points: corners },
+        "ORTHOGONAL" => EdgeRoute { style: RouteStyle::Orthogonal, points: corners },
+        _ => EdgeRoute {
+            style: RouteStyle::Spline,
+            points: vec![start, (outside, start.1 - reach / 2.0), (outside, end.1 + reach / 2.0), end],
+        },
+    }
+}
+
+/// The two nodes of an edge with their positions
+struct Ends<'a> {
+    source: &'a Node,
+    target: &'a Node,
+    from: Point,
+    to: Point,
+    parents: &'a HashSet<String>,
+}
+
+impl Ends<'_> {
+    /// Where a line from the source centre towards `toward` leaves the source
+    fn leave(&self, toward: Point) -> Point {
+        clip(self.source, self.from, toward, self.parents)
+    }
+
+    /// Where a line from `from` towards the target centre enters the target
+    fn enter(&self, from: Point) -> Point {
+        clip(self.target, self.to, from, self.parents)
+    }
+}
+
+/// Point on the boundary of `node` (centred at `centre`) in the direction of `toward`
+fn clip(node: &Node, centre: Point, toward: Point, parents: &HashSet<String>) -> Point {
+    let (dx, dy) = (toward.0 - centre.0, toward.1 - centre.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length < 1e-9 {
+        return centre;
+    }
+    // Compound nodes are drawn as boxes around their children
+    let shape = if parents.contains(&node.id) { NodeShape::Rectangle } else { node.shape };
+    let distance = shape.boundary_distance(node.size_or(0.0), (dx / length, dy / length)).min(length);
+    (centre.0 + dx / length * distance, centre.1 + dy / length * distance)
+}
+
+fn bounding_centre(points: &[Point]) -> Point {
+    if points.is_empty() {
+        return (0.0, 0.0);
+    }
+    let (mut min, mut max) = (points[0], points[0]);
+    for &(x, y) in points {
+        min = (min.0.min(x), min.1.min(y));
+        max = (max.0.max(x), max.1.max(y));
+    }
+    ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0)
+}
+
+fn straight(ends: &Ends) -> EdgeRoute {
+    EdgeRoute { style: RouteStyle::Straight, points: vec![ends.leave(ends.to), ends.enter(ends.from)] }
+}
+
+/// Source boundary, bend points, target boundary
+fn through_bends(ends: &Ends, bends: &[Point]) -> Vec<Point> {
+    let first = bends.first().copied().unwrap_or(ends.to);
+    let last = bends.last().copied().unwrap_or(ends.from);
+    let mut points = Vec::with_capacity(bends.len() + 2);
+    points.push(ends.leave(first));
+    points.extend_from_slice(bends);
+    points.push(ends.enter(last));
+    points
+}
+
+fn polyline(ends: &Ends, bends: &[Point]) -> EdgeRoute {
+    EdgeRoute { style: RouteStyle::Polyline, points: through_bends(ends, bends) }
+}
+
+fn orthogonal(ends: &Ends, bends: &[Point]) -> EdgeRoute {
+    // Turn between ranks: vertically flowing edges run vertically out of and into nodes
+    let vertical = (ends.to.1 - ends.from.1).abs() >= (ends.to.0 - ends.from.0).abs();
+
+    let mut centres = Vec::with_capacity(bends.len() + 2);
+    centres.push(ends.from);
+    centres.extend_from_slice(bends);
+    centres.push(ends.to);
+
+    let mut path = vec![ends.from];
+    for pair in centres.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if vertical && (a.0 - b.0).abs() > 1e-9 {
+            let middle = (a.1 + b.1) / 2.0;
+            path.extend([(a.0, middle), (b.0, middle)]);
+        } else if !vertical && (a.1 - b.1).abs() > 1e-9 {
+            let middle = (a.0 + b.0) / 2.0;
+            path.extend([(middle, a.1), (middle, b.1)]);
+        }
+        path.push(b);
+    }
+
+    // Clip the first and last segments to the node outlines
+    let last = path.len() - 1;
+    path[0] = ends.leave(path[1]);
+    path[last] = ends.enter(path[last - 1]);
+
+    EdgeRoute { style: RouteStyle::Orthogonal, points: simplify(path) }
+}
+
+/// Drop repeated points and points in the middle of a straight run
+fn simplify(path: Vec<Point>) -> Vec<Point> {
+    let mut points: Vec<Point> = Vec::with_capacity(path.len());
+    for point in path {
+        if points.last().is_some_and(|last| (last.0 - point.0).abs() < 1e-9 && (last.1 - point.1).abs() < 1e-9) {
+            continue;
+        }
+        if let [.., a, b] = points[..] {
+            let cross = (b.0 - a.0) * (point.1 - a.1) - (b.1 - a.1) * (point.0 - a.0);
+            if cross.abs() < 1e-9 {
+                points.pop();
+            }
+        }
+        points.push(point);
+    }
+    points
+}
+
+fn spline(ends: &Ends, bends: &[Point], centre: Point) -> EdgeRoute {
+    if !bends.is_empty() {
+        return EdgeRoute { style: RouteStyle::Spline, points: catmull_rom(&through_bends(ends, bends)) };
+    }
+
+    // Bow out towards the centre of the layout, perpendicular to the edge
+    let (dx, dy) = (ends.to.0 - ends.from.0, ends.to.1 - ends.from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    let normal = if length > 1e-9 { (-dy / length, dx / length) } else { (0.0, 0.0) };
+    let middle = ((ends.from.0 + ends.to.0) / 2.0, (ends.from.1 + ends.to.1) / 2.0);
+    let side = (centre.0 - middle.0) * normal.0 + (centre.1 - middle.1) * normal.1;
+    let bow = if side.abs() < 1e-9 { 0.0 } else { side.signum() * CURVATURE * length };
+    let control = |t: f64| (ends.from.0 + dx * t + normal.0 * bow, ends.from.1 + dy * t + normal.1 * bow);
+    let (c1, c2) = (control(1.0 / 3.0), control(2.0 / 3.0));
+
+    EdgeRoute { style: RouteStyle::Spline, points: vec![ends.leave(c1), c1, c2, ends.enter(c2)] }
+}
+
+/// Cubic Bézier segments of the Catmull-Rom spline through `points`
+fn catmull_rom(points: &[Point]) -> Vec<Point> {
+    let mut curve = vec![points[0]];
+    for i in 0..points.len() - 1 {
+        let before = points[i.saturating_sub(1)];
+        let (start, end) = (points[i], points[i + 1]);
+        let after = points[(i + 2).min(points.len() - 1)];
+        curve.push((start.0 + (end.0 - before.0) / 6.0, start.1 + (end.1 - before.1) / 6.0));
+        curve.push((end.0 - (after.0 - start.0) / 6.0, end.1 - (after.1 - start.1) / 6.0));
+        curve.push(end);
+    }
+    curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Edge;
+
+    fn boxes() -> Graph {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_position(0.0, 0.0).with_size(40.0, 20.0).with_shape(NodeShape::Rectangle))
+             .add_node(Node::new("b").with_position(100.0, 200.0).with_size(40.0, 20.0).with_shape(NodeShape::Rectangle));
+        let mut edge = Edge::new("ab", "a", "b");
+        edge.bend_points = vec![(60.0, 100.0)];
+        graph.add_edge(edge);
+        graph
+    }
+
+    #[test]
+    fn test_routes_end_on_node_boundaries() {
+        for routing in ["STRAIGHT", "POLYLINE", "ORTHOGONAL", "SPLINES"] {
+            let mut graph = boxes();
+            route_edges(&mut graph, &LayoutOptions::default(), routing);
+            let route = graph.edges["ab"].route.clone().unwrap();
+            let (first, last) = (route.points[0], *route.points.last().unwrap());
+            assert!((first.1 - 10.0).abs() < 1e-9, "{} leaves a at {:?}", routing, first);
+            assert!((last.1 - 190.0).abs() < 1e-9, "{} enters b at {:?}", routing, last);
+        }
+    }
+
+    #[test]
+    fn test_orthogonal_routes_only_turn_at_right_angles() {
+        let mut graph = boxes();
+        route_edges(&mut graph, &LayoutOptions::default(), "ORTHOGONAL");
+        let route = graph.edges["ab"].route.clone().unwrap();
+        assert_eq!(route.style, RouteStyle::Orthogonal);
+        assert_eq!(route.points, vec![(0.0, 10.0), (0.0, 50.0), (60.0, 50.0), (60.0, 150.0), (100.0, 150.0), (100.0, 190.0)]);
+    }
+
+    #[test]
+    fn test_splines_pass_through_bend_points() {
+        let mut graph = boxes();
+        route_edges(&mut graph, &LayoutOptions::default(), "SPLINES");
+        let route = graph.edges["ab"].route.clone().unwrap();
+        // Start point plus one (control, control, end) triple per piece
+        assert_eq!(route.points.len(), 7);
+        assert_eq!(route.points[3], (60.0, 100.0));
+    }
+
+    #[test]
+    fn test_self_loops_are_nested_loops() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_position(0.0, 0.0).with_size(40.0, 20.0).with_shape(NodeShape::Rectangle));
+        graph.add_edge(Edge::new("l1", "a", "a")).add_edge(Edge::new("l2", "a", "a"));
+
+        for routing in ["POLYLINE", "ORTHOGONAL", "SPLINES"] {
+            route_edges(&mut graph, &LayoutOptions::default(), routing);
+            let (inner, outer) = (graph.edges["l1"].route.clone().unwrap(), graph.edges["l2"].route.clone().unwrap());
+            // Both leave and re-enter the right side of the node
+            for route in [&inner, &outer] {
+                assert_eq!(route.points.len(), 4);
+                assert!((route.points[0].0 - 17.32).abs() < 0.01 && route.points[0].1 < 0.0, "{:?}", route.points);
+                assert!(route.points[3].1 > 0.0);
+            }
+            assert!(outer.points[1].0 > inner.points[1].0, "{} loops overlap", routing);
+        }
+
+        // Merged loops coincide
+        let options = LayoutOptions { merge_edges: true, ..LayoutOptions::default() };
+        route_edges(&mut graph, &options, "SPLINES");
+        assert_eq!(graph.edges["l1"].route, graph.edges["l2"].route);
+    }
+
+    #[test]
+    fn test_parallel_edges_fan_out() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_position(0.0, 0.0))
+             .add_node(Node::new("b").with_position(100.0, 0.0));
+        graph.add_edge(Edge::new("e1", "a", "b"))
+             .add_edge(Edge::new("e2", "a", "b"))
+             .add_edge(Edge::new("e3", "b", "a"));
+
+        route_edges(&mut graph, &LayoutOptions::default(), "STRAIGHT");
+        let mut offsets: Vec<f64> = graph.edges.values().map(|edge| edge.route.as_ref().unwrap().points[0].1).collect();
+        offsets.sort_by(f64::total_cmp);
+        assert_eq!(offsets, vec![-PARALLEL_SPACING, 0.0, PARALLEL_SPACING]);
+
+        // Merging keeps the two directions apart but draws e1 and e2 as one
+        let options = LayoutOptions { merge_edges: true, ..LayoutOptions::default() };
+        route_edges(&mut graph, &options, "STRAIGHT");
+        assert_eq!(graph.edges["e1"].route, graph.edges["e2"].route);
+        assert_ne!(graph.edges["e1"].route.as_ref().unwrap().points[0], graph.edges["e3"].route.as_ref().unwrap().points[1]);
+    }
+}
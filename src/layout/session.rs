@@ -0,0 +1,166 @@
+//! Step-by-step runs of the force-directed layouts.
+//!
+//! `start_layout` does a layout's setup (initial positions, merged edges) and returns
+//! its simulation, which `LayoutSession` advances a few iterations at a time, e.g.
+//! once per animation frame. Once converged the session runs the layout's
+//! post-processing, so the final positions match `apply_layout` with the same seed.
+//! The graph should not be changed while a session is running.
+
+use crate::types::{Graph, LayoutAlgorithm, LayoutTick};
+use crate::layout::traits::LayoutSimulation;
+use crate::layout::algorithms::{cose_bilkent::CoseBilkentLayoutEngine, fcose::FcoseLayoutEngine, nforce::MultilevelLayoutEngine};
+
+/// Set up a force-directed layout to be run one iteration at a time
+pub fn start_layout(graph: &mut Graph, layout: &LayoutAlgorithm) -> Result<Box<dyn LayoutSimulation>, String> {
+    match layout {
+        LayoutAlgorithm::Fcose(options) => Ok(Box::new(FcoseLayoutEngine::new(options.clone()).start(graph)?)),
+        LayoutAlgorithm::CoseBilkent(options) => Ok(Box::new(CoseBilkentLayoutEngine::new(options.clone()).start(graph)?)),
+        LayoutAlgorithm::Multilevel(options) => Ok(Box::new(MultilevelLayoutEngine::new(options.clone()).start(graph)?)),
+        other => Err(format!("The {} layout cannot be run step by step", other.id())),
+    }
+}
+
+/// Run a simulation to the end, as `apply_layout` does
+pub(crate) fn run_to_completion(mut simulation: Box<dyn LayoutSimulation>, graph: &mut Graph) -> Result<(), String> {
+    while !simulation.is_converged() {
+        simulation.tick(graph)?;
+    }
+    simulation.finish(graph)
+}
+
+enum State {
+    Running(Box<dyn LayoutSimulation>),
+    Converged,
+    Cancelled,
+}
+
+/// A layout run that the caller advances, inspects and may cancel
+pub struct LayoutSession {
+    state: State,
+    last_tick: Option<LayoutTick>,
+}
+
+impl LayoutSession {
+    /// Start a force-directed layout of the graph
+    pub fn start(graph: &mut Graph, layout: &LayoutAlgorithm) -> Result<Self, String> {
+        Ok(Self { state: State::Running(start_layout(graph, layout)?), last_tick: None })
+    }
+
+    /// Run up to `iterations` iterations, stopping at convergence, and return their
+    /// progress. The layout is finished as soon as it converges.
+    pub fn step(&mut self, graph: &mut Graph, iterations: usize) -> Result<Vec<LayoutTick>, String> {
+        let mut ticks = Vec::new();
+        if let State::Running(simulation) = &mut self.state {
+            while ticks.len() < iterations && !simulation.is_converged() {
+                ticks.push(simulation.tick(graph)?);
+            }
+            if simulation.is_converged() {
+                if let State::Running(simulation) = std::mem::replace(&mut self.state, State::Converged) {
+                    simulation.finish(graph)?;
+                }
+            }
+        }
+        self.last_tick = ticks.last().copied().or(self.last_tick);
+        Ok(ticks)
+    }
+
+    /// Whether the layout ran to the end and was finished
+    pub fn is_converged(&self) -> bool {
+        matches!(self.state, State::Converged)
+    }
+
+    /// Whether the layout can still be stepped
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, State::Running(_))
+    }
+
+    /// Progress of the latest iteration, if any has run
+    pub fn last_tick(&self) -> Option<LayoutTick> {
+        self.last_tick
+    }
+
+    /// Stop the layout, leaving nodes where the last iteration put them
+    pub fn cancel(&mut self, graph: &mut Graph) {
+        if let State::Running(simulation) = std::mem::replace(&mut self.state, State::Cancelled) {
+            simulation.cancel(graph);
+        }
+    }
+
+    /// Run a layout to the end, calling `progress` after every iteration; returning
+    /// `false` from it cancels the layout. Returns whether the layout finished.
+    pub fn run(graph: &mut Graph, layout: &LayoutAlgorithm, mut progress: impl FnMut(&LayoutTick) -> bool) -> Result<bool, String> {
+        let mut session = Self::start(graph, layout)?;
+        while session.is_running() {
+            for tick in session.step(graph, 1)? {
+                if !progress(&tick) {
+                    session.cancel(graph);
+                }
+            }
+        }
+        Ok(session.is_converged())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{apply_layout, available_layouts};
+    use crate::file_parsers::parse_graph_file;
+
+    fn sample_graph() -> Graph {
+        let path = format!("{}/docs/sample/dagre_layout.json", env!("CARGO_MANIFEST_DIR"));
+        parse_graph_file(&std::fs::read_to_string(path).unwrap(), "json").unwrap()
+    }
+
+    fn seeded(mut layout: LayoutAlgorithm) -> LayoutAlgorithm {
+        match &mut layout {
+            LayoutAlgorithm::Fcose(options) => options.base.seed = Some(7),
+            LayoutAlgorithm::CoseBilkent(options) => options.base.seed = Some(7),
+            LayoutAlgorithm::Multilevel(options) => options.base.seed = Some(7),
+            _ => {}
+        }
+        layout
+    }
+
+    #[test]
+    fn test_stepping_matches_apply_layout() {
+        for layout in available_layouts().into_iter().map(seeded) {
+            let mut stepped = sample_graph();
+            let Ok(mut session) = LayoutSession::start(&mut stepped, &layout) else {
+                assert!(matches!(layout, LayoutAlgorithm::Dagre(_) | LayoutAlgorithm::KlayLayered(_) | LayoutAlgorithm::Cise(_) | LayoutAlgorithm::Concentric(_)));
+                continue;
+            };
+            let mut ticks = Vec::new();
+            while session.is_running() {
+                ticks.extend(session.step(&mut stepped, 7).unwrap());
+            }
+            assert!(session.is_converged() && !ticks.is_empty(), "{}", layout.id());
+            assert!(ticks.iter().enumerate().all(|(i, tick)| tick.iteration == i + 1 && tick.energy.is_finite()));
+            assert_eq!(session.last_tick(), ticks.last().copied());
+
+            let mut applied = sample_graph();
+            apply_layout(&mut applied, &layout).unwrap();
+            for (id, node) in &applied.nodes {
+                assert_eq!(stepped.nodes[id].position, node.position, "{} {}", layout.id(), id);
+            }
+            assert!(session.step(&mut stepped, 5).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_progress_callback_cancels() {
+        let mut graph = sample_graph();
+        let edges = graph.edges.len();
+        let layout: LayoutAlgorithm = serde_json::from_str(r#"{"name": "fcose", "base": {"merge_edges": true}}"#).unwrap();
+        let mut seen = 0;
+        let finished = LayoutSession::run(&mut graph, &layout, |tick| {
+            seen = tick.iteration;
+            tick.iteration < 3
+        }).unwrap();
+
+        assert!(!finished);
+        assert_eq!(seen, 3);
+        assert_eq!(graph.edges.len(), edges);
+        assert!(graph.nodes.values().all(|node| node.position.is_some()));
+    }
+}
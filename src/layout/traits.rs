@@ -0,0 +1,74 @@
+use crate::types::{Graph, LayoutTick};
+
+/// Common trait for all layout algorithms
+pub trait LayoutEngine {
+    /// Apply the layout algorithm to a graph
+    fn apply_layout(&self, graph: &mut Graph) -> Result<(), String>;
+
+    /// Get the name of the layout algorithm
+    fn name(&self) -> &'static str;
+
+    /// Get a description of the layout algorithm
+    fn description(&self) -> &'static str;
+}
+
+/// Layouts that simulate physical forces between nodes
+pub trait ForceDirectedLayout: LayoutEngine {
+    /// Calculate repulsive forces between nodes, indexed like `graph.nodes`
+    fn calculate_repulsion(&self, graph: &Graph) -> Vec<(f64, f64)>;
+
+    /// Calculate attractive forces along edges, indexed like `graph.nodes`
+    fn calculate_attraction(&self, graph: &Graph) -> Vec<(f64, f64)>;
+
+    /// Move nodes according to the combined forces
+    fn apply_forces(&self, graph: &mut Graph, forces: &[(f64, f64)]) -> Result<(), String>;
+}
+
+/// Layouts that place nodes on one or more circles
+pub trait CircularLayout: LayoutEngine {
+    /// Place all nodes on a single circle of the given radius
+    fn arrange_circle(&self, graph: &mut Graph, radius: f64) -> Result<(), String>;
+
+    /// Reorder nodes on their circles to reduce edge crossings
+    fn optimize_ordering(&self, graph: &mut Graph) -> Result<(), String>;
+}
+
+/// Layouts that assign nodes to layers (ranks) and order them within each layer
+pub trait LayeredLayout: LayoutEngine {
+    /// Assign nodes to layers, returned top to bottom
+    fn assign_layers(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String>;
+
+    /// Reverse edges so that the graph becomes acyclic with respect to the layers
+    fn break_cycles(&self, graph: &mut Graph, layers: &mut Vec<Vec<String>>) -> Result<(), String>;
+
+    /// Order nodes within each layer to minimize edge crossings
+    fn minimize_crossings(&self, layers: &mut Vec<Vec<String>>, graph: &Graph) -> Result<(), String>;
+
+    /// Count edge crossings between two adjacent layers
+    fn count_crossings(&self, layer1: &[String], layer2: &[String], graph: &Graph) -> usize;
+}
+
+/// Layouts that group nodes into levels and position each level as a unit
+pub trait HierarchicalLayout: LayoutEngine {
+    /// Group nodes into levels, innermost (or topmost) first
+    fn assign_levels(&self, graph: &Graph) -> Result<Vec<Vec<String>>, String>;
+
+    /// Position nodes according to their level
+    fn position_nodes(&self, graph: &mut Graph, levels: &[Vec<String>]) -> Result<(), String>;
+}
+
+/// A force-directed layout in progress, advanced one iteration at a time
+/// (see `layout::session`)
+pub trait LayoutSimulation {
+    /// Run one iteration, moving the nodes of the graph
+    fn tick(&mut self, graph: &mut Graph) -> Result<LayoutTick, String>;
+
+    /// Whether every iteration has run, so that `finish` should be called
+    fn is_converged(&self) -> bool;
+
+    /// Post-process after the last iteration, e.g. remove overlaps and route edges
+    fn finish(self: Box<Self>, graph: &mut Graph) -> Result<(), String>;
+
+    /// Stop early, putting back edges set aside for the run; nodes stay where they are
+    fn cancel(self: Box<Self>, graph: &mut Graph);
+}
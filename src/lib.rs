@@ -4,13 +4,39 @@ mod types;
 mod layout;
 mod benchmark;
 mod file_parsers;
+mod metrics;
+mod stats;
 mod utils;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+mod cli;
 
-use layout::LayoutEngine;
-pub use types::{Graph, Node, Edge, Id, MetadataValue, LayoutOptions};
-pub use layout::algorithms::fcose::{FcoseLayoutEngine, FcoseOptions};
-pub use benchmark::{run_benchmark, run_all_benchmarks};
-use file_parsers::parse_graph_file;
+pub use types::{
+    Graph, Node, NodeShape, Edge, EdgeRoute, RouteStyle, Id, MetadataValue, LayoutOptions, LayoutAlgorithm,
+    DagreLayoutOptions, KlayLayeredLayoutOptions, CiseLayoutOptions,
+    ConcentricLayoutOptions, CoseBilkentLayoutOptions, MultilevelLayoutOptions, SvgOptions, SvgNodeStyle, LayoutTick,
+};
+pub use layout::{
+    apply_layout, create_engine, available_layouts, layout_catalog, LayoutInfo, LayoutEngine, ForceDirectedLayout, CircularLayout,
+    LayeredLayout, HierarchicalLayout, LayoutSimulation,
+};
+pub use layout::session::{start_layout, LayoutSession};
+pub use layout::algorithms::fcose::{
+    FcoseLayoutEngine, FcoseOptions, FixedNodeConstraint, AlignmentConstraint, RelativePlacementConstraint,
+};
+pub use layout::quadtree::QuadTree;
+pub use layout::algorithms::cose_bilkent::CoseBilkentLayoutEngine;
+pub use layout::algorithms::cise::CiseLayoutEngine;
+pub use layout::algorithms::concentric::ConcentricLayoutEngine;
+pub use layout::algorithms::klay::KlayLayoutEngine;
+pub use layout::algorithms::dagre::DagreLayoutEngine;
+pub use layout::algorithms::nforce::{self, MultilevelLayoutEngine};
+pub use benchmark::{
+    run_benchmark, run_benchmarks, run_all_benchmarks, load_benchmark_config, BenchmarkConfig, BenchmarkFailure, BenchmarkResult, LayoutSweep,
+    compare_benchmark_csv, compare_benchmark_files, BenchmarkComparison, CompareThresholds, MetricDelta,
+};
+pub use file_parsers::{parse_graph_file, write_graph_file, write_svg};
+pub use metrics::{compute_metrics, LayoutMetrics};
+pub use stats::{compute_graph_stats, GraphStats};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator.
 #[cfg(feature = "wee_alloc")]
@@ -19,15 +45,19 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 #[wasm_bindgen]
 pub fn set_panic_hook() {
-    // When the `console_error_panic_hook` feature is enabled, we can call the
-    // `set_panic_hook` function to get better error messages if our code ever panics.
-    #[cfg(feature = "console_error_panic_hook")]
-    console_error_panic_hook::set_once();
+    utils::set_panic_hook();
 }
 
 #[wasm_bindgen]
 pub struct LayoutManager {
     graph: Graph,
+    session: Option<LayoutSession>,      // Step-by-step layout, see `start_layout`
+}
+
+impl Default for LayoutManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -37,11 +67,13 @@ impl LayoutManager {
         set_panic_hook();
         Self {
             graph: Graph::new(),
+            session: None,
         }
     }
 
     /// Add a node to the graph
     pub fn add_node(&mut self, id: String, x: Option<f64>, y: Option<f64>) {
+        self.cancel_layout();
         let mut node = Node::new(id);
         if let (Some(x_val), Some(y_val)) = (x, y) {
             node = node.with_position(x_val, y_val);
@@ -49,19 +81,31 @@ impl LayoutManager {
         self.graph.add_node(node);
     }
 
+    /// Lock a node at its current position, or release it, for fCoSE layouts
+    pub fn set_node_locked(&mut self, id: String, locked: bool) -> Result<(), JsValue> {
+        self.cancel_layout();
+        let node = self.graph.nodes.get_mut(&id)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown node: {}", id)))?;
+        node.locked = locked;
+        Ok(())
+    }
+
     /// Add an edge to the graph
     pub fn add_edge(&mut self, id: String, source: String, target: String) {
+        self.cancel_layout();
         let edge = Edge::new(id, source, target);
         self.graph.add_edge(edge);
     }
 
     /// Remove a node from the graph
     pub fn remove_node(&mut self, id: String) {
+        self.cancel_layout();
         self.graph.remove_node(&id);
     }
 
     /// Remove an edge from the graph
     pub fn remove_edge(&mut self, id: String) {
+        self.cancel_layout();
         self.graph.remove_edge(&id);
     }
 
@@ -69,6 +113,7 @@ impl LayoutManager {
     pub fn apply_fcose_layout(&mut self, options_json: String) -> Result<String, JsValue> {
         let options: FcoseOptions = serde_json::from_str(&options_json)
             .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?;
+        self.cancel_layout();
         
         FcoseLayoutEngine::new(options)
             .apply_layout(&mut self.graph)
@@ -79,6 +124,102 @@ impl LayoutManager {
             .map_err(|e| JsValue::from_str(&format!("Failed to serialize graph: {}", e)))
     }
 
+    /// Apply any layout algorithm, selected by the `name` tag in the JSON payload
+    /// (e.g. `{"name": "dagre", "rank_direction": "LR"}`)
+    pub fn apply_layout(&mut self, algorithm_json: String) -> Result<String, JsValue> {
+        let layout: LayoutAlgorithm = serde_json::from_str(&algorithm_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse layout: {}", e)))?;
+        self.cancel_layout();
+        
+        apply_layout(&mut self.graph, &layout)
+            .map_err(|e| JsValue::from_str(&format!("Layout error: {}", e)))?;
+        
+        // Return the updated graph as JSON
+        serde_json::to_string(&self.graph)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize graph: {}", e)))
+    }
+
+    /// Start a force-directed layout (fcose, cose-bilkent or multilevel) to be advanced
+    /// with `step_layout`, e.g. once per animation frame. Changing the graph cancels it.
+    pub fn start_layout(&mut self, algorithm_json: String) -> Result<(), JsValue> {
+        let layout: LayoutAlgorithm = serde_json::from_str(&algorithm_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse layout: {}", e)))?;
+        self.cancel_layout();
+        
+        self.session = Some(LayoutSession::start(&mut self.graph, &layout)
+            .map_err(|e| JsValue::from_str(&format!("Layout error: {}", e)))?);
+        Ok(())
+    }
+
+    /// Run up to `iterations` iterations of the started layout and return their progress
+    /// as a JSON array of `LayoutTick`s (empty once converged)
+    pub fn step_layout(&mut self, iterations: usize) -> Result<String, JsValue> {
+        let session = self.session.as_mut()
+            .ok_or_else(|| JsValue::from_str("No layout has been started"))?;
+        let ticks = session.step(&mut self.graph, iterations)
+            .map_err(|e| JsValue::from_str(&format!("Layout error: {}", e)))?;
+        
+        serde_json::to_string(&ticks)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize progress: {}", e)))
+    }
+
+    /// Whether the started layout has run to the end
+    pub fn is_layout_converged(&self) -> bool {
+        self.session.as_ref().is_some_and(LayoutSession::is_converged)
+    }
+
+    /// Stop the started layout, leaving nodes where they are
+    pub fn cancel_layout(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            session.cancel(&mut self.graph);
+        }
+    }
+
+    /// Current node positions as JSON, `{"id": [x, y], ...}`, for drawing between steps
+    pub fn layout_positions(&self) -> Result<String, JsValue> {
+        let positions: std::collections::BTreeMap<&String, (f64, f64)> = self.graph.nodes.iter()
+            .filter_map(|(id, node)| node.position.map(|position| (id, position)))
+            .collect();
+        serde_json::to_string(&positions)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize positions: {}", e)))
+    }
+
+    /// Apply a force-directed layout, calling `progress` with each iteration's `LayoutTick`
+    /// as JSON; returning `false` from it cancels the layout. Returns the graph as JSON.
+    pub fn apply_layout_with_progress(&mut self, algorithm_json: String, progress: &js_sys::Function) -> Result<String, JsValue> {
+        let layout: LayoutAlgorithm = serde_json::from_str(&algorithm_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse layout: {}", e)))?;
+        self.cancel_layout();
+        
+        // An exception in the callback cancels the layout and is passed on
+        let mut thrown = None;
+        LayoutSession::run(&mut self.graph, &layout, |tick| {
+            let result = serde_json::to_string(tick)
+                .map_err(|e| JsValue::from_str(&format!("Failed to serialize progress: {}", e)))
+                .and_then(|json| progress.call1(&JsValue::NULL, &JsValue::from_str(&json)));
+            match result {
+                Ok(keep_going) => keep_going.as_bool() != Some(false),
+                Err(e) => {
+                    thrown = Some(e);
+                    false
+                }
+            }
+        }).map_err(|e| JsValue::from_str(&format!("Layout error: {}", e)))?;
+        if let Some(e) = thrown {
+            return Err(e);
+        }
+        
+        // Return the updated graph as JSON
+        serde_json::to_string(&self.graph)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize graph: {}", e)))
+    }
+
+    /// List the available layouts with their names, descriptions and default options as JSON
+    pub fn list_layouts(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&layout_catalog())
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize layouts: {}", e)))
+    }
+
     /// Get the current graph state as JSON
     pub fn get_graph_json(&self) -> Result<String, JsValue> {
         serde_json::to_string(&self.graph)
@@ -87,6 +228,7 @@ impl LayoutManager {
 
     /// Load a graph from JSON
     pub fn load_graph_json(&mut self, json: String) -> Result<(), JsValue> {
+        self.cancel_layout();
         self.graph = serde_json::from_str(&json)
             .map_err(|e| JsValue::from_str(&format!("Failed to parse graph: {}", e)))?;
         Ok(())
@@ -94,39 +236,46 @@ impl LayoutManager {
 
     /// Parse and load a graph from various file formats
     pub fn parse_and_load_graph(&mut self, content: String, file_type: String) -> Result<(), JsValue> {
+        self.cancel_layout();
         self.graph = parse_graph_file(&content, &file_type)
             .map_err(|e| JsValue::from_str(&format!("Failed to parse file: {}", e)))?;
         Ok(())
     }
-}
 
-// CLI interface for running benchmarks
-#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
-pub fn main() {
-    use std::env;
+    /// Export the graph with its current layout in one of the file formats
+    pub fn export_graph(&self, file_type: String) -> Result<String, JsValue> {
+        write_graph_file(&self.graph, &file_type)
+            .map_err(|e| JsValue::from_str(&format!("Failed to write file: {}", e)))
+    }
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} benchmark <output_csv_path>", args[0]);
-        std::process::exit(1);
+    /// Measure the quality of the current layout, as JSON (see `LayoutMetrics`)
+    pub fn compute_metrics(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&compute_metrics(&self.graph))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize metrics: {}", e)))
     }
 
-    let command = &args[1];
-    let output_path = &args[2];
+    /// Describe the structure of the graph as JSON: counts, components, degrees and cycles
+    /// (see `GraphStats`)
+    pub fn graph_stats(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&compute_graph_stats(&self.graph))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize statistics: {}", e)))
+    }
 
-    match command.as_str() {
-        "benchmark" => {
-            match run_all_benchmarks(output_path) {
-                Ok(_) => println!("Benchmarks completed successfully"),
-                Err(e) => {
-                    eprintln!("Error running benchmarks: {}", e);
-                    std::process::exit(1);
-                }
-            }
-        }
-        _ => {
-            eprintln!("Unknown command: {}", command);
-            std::process::exit(1);
-        }
+    /// Draw the graph with its current layout as SVG
+    pub fn export_svg(&self, options_json: String) -> Result<String, JsValue> {
+        let options: SvgOptions = serde_json::from_str(&options_json)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?;
+        write_svg(&self.graph, &options)
+            .map_err(|e| JsValue::from_str(&format!("Failed to draw graph: {}", e)))
+    }
+}
+
+// Command-line interface, see `cli::run`
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = cli::run(&args, &mut std::io::stdout()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,369 @@
+//! Layout quality metrics.
+//!
+//! All metrics are measured on positioned leaf nodes (compound nodes are boxes around
+//! others, not drawn vertices). Edges are drawn as straight segments from node centre
+//! to node centre through their bend points, and nodes without a size count as
+//! `DEFAULT_NODE_SIZE` squares. Crossing and overlap counts compare every pair, so
+//! they take quadratic time.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use serde::{Deserialize, Serialize};
+use crate::types::{Edge, Graph, Id, Node};
+
+/// Size assumed for nodes without a width and height
+const DEFAULT_NODE_SIZE: f64 = 20.0;
+
+type Point = (f64, f64);
+
+/// Quality measures of a layout; lower is better unless noted
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayoutMetrics {
+    pub edge_crossings: usize,           // Pairs of edge segments that cross
+    pub node_overlaps: usize,            // Pairs of nodes whose boxes overlap
+    pub node_overlap_area: f64,          // Total area of those overlaps
+    pub edge_node_overlaps: usize,       // Edges passing through a node other than their ends
+    pub average_edge_length: f64,
+    pub edge_length_variance: f64,
+    pub angular_resolution: f64,         // Smallest angle between edges at a node, in degrees, averaged over nodes (higher is better)
+    pub stress: f64,                     // Normalized stress against shortest-path distances, after optimal scaling
+    pub neighborhood_preservation: f64,  // Share of graph neighbours that are also nearest in the layout, 0 to 1 (higher is better)
+    pub aspect_ratio: f64,               // Bounding box width divided by height
+    pub bounding_box_area: f64,
+}
+
+impl LayoutMetrics {
+    /// Every metric by name, in declaration order
+    pub fn values(&self) -> Vec<(&'static str, f64)> {
+        vec![
+            ("edge_crossings", self.edge_crossings as f64),
+            ("node_overlaps", self.node_overlaps as f64),
+            ("node_overlap_area", self.node_overlap_area),
+            ("edge_node_overlaps", self.edge_node_overlaps as f64),
+            ("average_edge_length", self.average_edge_length),
+            ("edge_length_variance", self.edge_length_variance),
+            ("angular_resolution", self.angular_resolution),
+            ("stress", self.stress),
+            ("neighborhood_preservation", self.neighborhood_preservation),
+            ("aspect_ratio", self.aspect_ratio),
+            ("bounding_box_area", self.bounding_box_area),
+        ]
+    }
+
+    /// Mean of several runs' metrics, with counts rounded to the nearest whole number
+    pub fn mean(runs: &[LayoutMetrics]) -> LayoutMetrics {
+        if runs.is_empty() {
+            return LayoutMetrics::default();
+        }
+        let n = runs.len() as f64;
+        let average = |metric: fn(&LayoutMetrics) -> f64| runs.iter().map(metric).sum::<f64>() / n;
+        LayoutMetrics {
+            edge_crossings: average(|m| m.edge_crossings as f64).round() as usize,
+            node_overlaps: average(|m| m.node_overlaps as f64).round() as usize,
+            node_overlap_area: average(|m| m.node_overlap_area),
+            edge_node_overlaps: average(|m| m.edge_node_overlaps as f64).round() as usize,
+            average_edge_length: average(|m| m.average_edge_length),
+            edge_length_variance: average(|m| m.edge_length_variance),
+            angular_resolution: average(|m| m.angular_resolution),
+            stress: average(|m| m.stress),
+            neighborhood_preservation: average(|m| m.neighborhood_preservation),
+            aspect_ratio: average(|m| m.aspect_ratio),
+            bounding_box_area: average(|m| m.bounding_box_area),
+        }
+    }
+}
+
+/// Positioned leaf nodes, in ID order
+fn leaves(graph: &Graph) -> Vec<&Node> {
+    let parents: HashSet<&Id> = graph.nodes.values().filter_map(|node| node.parent.as_ref()).collect();
+    graph.sorted_nodes().into_iter()
+        .map(|(_, node)| node)
+        .filter(|node| node.position.is_some() && !parents.contains(&node.id))
+        .collect()
+}
+
+/// Corners (min x, min y, max x, max y) of a node's box
+fn node_box(node: &Node) -> (f64, f64, f64, f64) {
+    let (x, y) = node.position.unwrap_or_default();
+    let (width, height) = node.size_or(DEFAULT_NODE_SIZE);
+    (x - width / 2.0, y - height / 2.0, x + width / 2.0, y + height / 2.0)
+}
+
+/// Points an edge is drawn through, or `None` for self-loops and unpositioned ends
+fn edge_path(graph: &Graph, edge: &Edge) -> Option<Vec<Point>> {
+    if edge.source == edge.target {
+        return None;
+    }
+    let mut path = vec![graph.nodes.get(&edge.source)?.position?];
+    path.extend(edge.bend_points.iter().copied());
+    path.push(graph.nodes.get(&edge.target)?.position?);
+    Some(path)
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Whether two segments cross at a point inside both (touching ends don't count)
+fn segments_cross(a: Point, b: Point, c: Point, d: Point) -> bool {
+    let orient = |p: Point, q: Point, r: Point| (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0);
+    orient(c, d, a) * orient(c, d, b) < 0.0 && orient(a, b, c) * orient(a, b, d) < 0.0
+}
+
+/// Whether a segment passes through the inside of a box (Liang-Barsky clipping)
+fn segment_enters_box(a: Point, b: Point, (min_x, min_y, max_x, max_y): (f64, f64, f64, f64)) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut enter, mut exit) = (0.0_f64, 1.0_f64);
+    for (p, q) in [(-dx, a.0 - min_x), (dx, max_x - a.0), (-dy, a.1 - min_y), (dy, max_y - a.1)] {
+        if p.abs() < 1e-12 {
+            if q <= 0.0 {
+                return false;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            exit = exit.min(q / p);
+        }
+    }
+    exit - enter > 1e-9
+}
+
+fn count_crossings(paths: &[Vec<Point>]) -> usize {
+    let segments: Vec<(usize, Point, Point)> = paths.iter().enumerate()
+        .flat_map(|(index, path)| path.windows(2).map(move |pair| (index, pair[0], pair[1])))
+        .collect();
+    let mut crossings = 0;
+    for (i, &(edge, a, b)) in segments.iter().enumerate() {
+        for &(other, c, d) in &segments[i + 1..] {
+            if edge != other && segments_cross(a, b, c, d) {
+                crossings += 1;
+            }
+        }
+    }
+    crossings
+}
+
+/// Overlapping pairs of node boxes and their total overlap area
+fn node_overlaps(nodes: &[&Node]) -> (usize, f64) {
+    let boxes: Vec<_> = nodes.iter().map(|node| node_box(node)).collect();
+    let (mut count, mut area) = (0, 0.0);
+    for (i, a) in boxes.iter().enumerate() {
+        for b in &boxes[i + 1..] {
+            let width = a.2.min(b.2) - a.0.max(b.0);
+            let height = a.3.min(b.3) - a.1.max(b.1);
+            if width > 1e-9 && height > 1e-9 {
+                count += 1;
+                area += width * height;
+            }
+        }
+    }
+    (count, area)
+}
+
+/// Average over nodes of the smallest angle between two of their edges, in degrees
+fn angular_resolution(edges: &[(&Edge, Vec<Point>)]) -> f64 {
+    let mut directions: BTreeMap<&Id, Vec<f64>> = BTreeMap::new();
+    for (edge, path) in edges {
+        let last = path.len() - 1;
+        for (node, from, toward) in [(&edge.source, path[0], path[1]), (&edge.target, path[last], path[last - 1])] {
+            directions.entry(node).or_default().push((toward.1 - from.1).atan2(toward.0 - from.0));
+        }
+    }
+    let mut smallest = Vec::new();
+    for angles in directions.values_mut().filter(|angles| angles.len() >= 2) {
+        angles.sort_by(|a, b| a.total_cmp(b));
+        let wrap = angles[0] + std::f64::consts::TAU - angles[angles.len() - 1];
+        let gap = angles.windows(2).map(|pair| pair[1] - pair[0]).fold(wrap, f64::min);
+        smallest.push(gap.to_degrees());
+    }
+    if smallest.is_empty() { 0.0 } else { smallest.iter().sum::<f64>() / smallest.len() as f64 }
+}
+
+/// Stress (with weights 1/d²) and neighbourhood preservation against graph distances
+fn distance_metrics(graph: &Graph, nodes: &[&Node]) -> (f64, f64) {
+    let index: HashMap<&Id, usize> = nodes.iter().enumerate().map(|(i, node)| (&node.id, i)).collect();
+    let mut neighbors: Vec<HashSet<usize>> = vec![HashSet::new(); nodes.len()];
+    for edge in graph.edges.values() {
+        if let (Some(&s), Some(&t)) = (index.get(&edge.source), index.get(&edge.target)) {
+            if s != t {
+                neighbors[s].insert(t);
+                neighbors[t].insert(s);
+            }
+        }
+    }
+    let position = |i: usize| nodes[i].position.unwrap_or_default();
+
+    // Shortest paths in hops from every node; pairs in different components are skipped.
+    // Stress only needs the count and the sums of ratio and squared ratio of layout
+    // distance to graph distance over the pairs
+    let (mut count, mut ratio_sum, mut ratio_squares) = (0usize, 0.0, 0.0);
+    for start in 0..nodes.len() {
+        let mut hops = vec![usize::MAX; nodes.len()];
+        hops[start] = 0;
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for &next in &neighbors[current] {
+                if hops[next] == usize::MAX {
+                    hops[next] = hops[current] + 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        for (other, &hop) in hops.iter().enumerate().skip(start + 1) {
+            if hop != usize::MAX {
+                let ratio = distance(position(start), position(other)) / hop as f64;
+                count += 1;
+                ratio_sum += ratio;
+                ratio_squares += ratio * ratio;
+            }
+        }
+    }
+    // Scale the layout to best fit the graph distances, then normalize by the pair count:
+    // the sum of (scale * ratio - 1)^2 expands to the sums accumulated above
+    let stress = if count == 0 {
+        0.0
+    } else {
+        let scale = if ratio_squares > 0.0 { ratio_sum / ratio_squares } else { 0.0 };
+        (scale * scale * ratio_squares - 2.0 * scale * ratio_sum + count as f64).max(0.0) / count as f64
+    };
+
+    // Jaccard similarity of each node's graph neighbours and as many nearest nodes
+    let mut similarities = Vec::new();
+    for (i, graph_neighbors) in neighbors.iter().enumerate().filter(|(_, set)| !set.is_empty()) {
+        let mut others: Vec<(f64, usize)> = (0..nodes.len()).filter(|&j| j != i).map(|j| (distance(position(i), position(j)), j)).collect();
+        others.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        let nearest: HashSet<usize> = others.iter().take(graph_neighbors.len()).map(|&(_, j)| j).collect();
+        let shared = nearest.intersection(graph_neighbors).count();
+        similarities.push(shared as f64 / (nearest.len() + graph_neighbors.len() - shared) as f64);
+    }
+    let preservation = if similarities.is_empty() { 0.0 } else { similarities.iter().sum::<f64>() / similarities.len() as f64 };
+
+    (stress, preservation)
+}
+
+/// Measure the quality of a graph's current layout
+pub fn compute_metrics(graph: &Graph) -> LayoutMetrics {
+    let nodes = leaves(graph);
+    if nodes.is_empty() {
+        return LayoutMetrics::default();
+    }
+    let edges: Vec<(&Edge, Vec<Point>)> = graph.sorted_edges().into_iter()
+        .filter_map(|edge| edge_path(graph, edge).map(|path| (edge, path)))
+        .collect();
+    let paths: Vec<Vec<Point>> = edges.iter().map(|(_, path)| path.clone()).collect();
+
+    let lengths: Vec<f64> = paths.iter().map(|path| path.windows(2).map(|pair| distance(pair[0], pair[1])).sum()).collect();
+    let (average_edge_length, edge_length_variance) = if lengths.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let mean = lengths.iter().sum::<f64>() / lengths.len() as f64;
+        (mean, lengths.iter().map(|length| (length - mean).powi(2)).sum::<f64>() / lengths.len() as f64)
+    };
+
+    let mut edge_node_overlaps = 0;
+    for (edge, path) in &edges {
+        for node in nodes.iter().filter(|node| node.id != edge.source && node.id != edge.target) {
+            let bounds = node_box(node);
+            if path.windows(2).any(|pair| segment_enters_box(pair[0], pair[1], bounds)) {
+                edge_node_overlaps += 1;
+            }
+        }
+    }
+
+    let (node_overlaps, node_overlap_area) = node_overlaps(&nodes);
+    let (stress, neighborhood_preservation) = distance_metrics(graph, &nodes);
+    let (min_x, min_y, max_x, max_y) = nodes.iter().map(|node| node_box(node)).fold(
+        (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |(a, b, c, d), (x0, y0, x1, y1)| (a.min(x0), b.min(y0), c.max(x1), d.max(y1)),
+    );
+    let (width, height) = (max_x - min_x, max_y - min_y);
+
+    LayoutMetrics {
+        edge_crossings: count_crossings(&paths),
+        node_overlaps,
+        node_overlap_area,
+        edge_node_overlaps,
+        average_edge_length,
+        edge_length_variance,
+        angular_resolution: angular_resolution(&edges),
+        stress,
+        neighborhood_preservation,
+        aspect_ratio: if height > 0.0 { width / height } else { 0.0 },
+        bounding_box_area: width * height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square a-b-c-d with both diagonals, so the diagonals cross once
+    fn square(side: f64) -> Graph {
+        let mut graph = Graph::new();
+        for (id, x, y) in [("a", 0.0, 0.0), ("b", side, 0.0), ("c", side, side), ("d", 0.0, side)] {
+            graph.add_node(Node::new(id).with_position(x, y).with_size(10.0, 10.0));
+        }
+        for (source, target) in [("a", "b"), ("b", "c"), ("c", "d"), ("d", "a"), ("a", "c"), ("b", "d")] {
+            graph.add_edge(Edge::new(format!("{}{}", source, target), source, target));
+        }
+        graph
+    }
+
+    #[test]
+    fn test_square_with_diagonals() {
+        let metrics = compute_metrics(&square(100.0));
+
+        assert_eq!(metrics.edge_crossings, 1);
+        assert_eq!((metrics.node_overlaps, metrics.edge_node_overlaps), (0, 0));
+        let diagonal = 100.0 * 2f64.sqrt();
+        assert!((metrics.average_edge_length - (400.0 + 2.0 * diagonal) / 6.0).abs() < 1e-9);
+        assert!(metrics.edge_length_variance > 0.0);
+        // Every corner sees its three edges 45 degrees apart
+        assert!((metrics.angular_resolution - 45.0).abs() < 1e-9);
+        // Complete graph: every pair is one hop apart
+        assert!(metrics.stress > 0.0 && metrics.stress < 0.1);
+        assert!((metrics.neighborhood_preservation - 1.0).abs() < 1e-9);
+        assert!((metrics.aspect_ratio - 1.0).abs() < 1e-9);
+        assert!((metrics.bounding_box_area - 110.0 * 110.0).abs() < 1e-9);
+
+        // Stress does not depend on the scale of the layout
+        assert!((compute_metrics(&square(7.0)).stress - metrics.stress).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_overlaps_and_path_stress() {
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("a").with_position(0.0, 0.0))
+             .add_node(Node::new("b").with_position(10.0, 10.0))
+             .add_node(Node::new("c").with_position(100.0, 0.0))
+             .add_node(Node::new("d").with_position(50.0, 0.0).with_size(4.0, 4.0));
+        graph.add_edge(Edge::new("ac", "a", "c"));
+        let metrics = compute_metrics(&graph);
+
+        // Default 20x20 boxes of a and b overlap by 10x10; a-c runs through d
+        assert_eq!(metrics.node_overlaps, 1);
+        assert!((metrics.node_overlap_area - 100.0).abs() < 1e-9);
+        assert_eq!(metrics.edge_node_overlaps, 1);
+
+        // A path drawn in a straight line at even spacing has no stress
+        let mut path = Graph::new();
+        for i in 0..4 {
+            path.add_node(Node::new(i.to_string()).with_position(i as f64 * 30.0, 0.0));
+        }
+        for i in 0..3 {
+            path.add_edge(Edge::new(format!("e{}", i), i.to_string(), (i + 1).to_string()));
+        }
+        assert!(compute_metrics(&path).stress < 1e-12);
+    }
+
+    #[test]
+    fn test_empty_and_compound_graphs() {
+        assert_eq!(compute_metrics(&Graph::new()), LayoutMetrics::default());
+
+        // The compound box around its children is not an overlap
+        let mut graph = Graph::new();
+        graph.add_node(Node::new("group").with_position(0.0, 0.0).with_size(100.0, 100.0))
+             .add_node(Node::new("a").with_parent("group").with_position(-20.0, 0.0))
+             .add_node(Node::new("b").with_parent("group").with_position(20.0, 0.0));
+        assert_eq!(compute_metrics(&graph).node_overlaps, 0);
+    }
+}
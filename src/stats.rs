@@ -0,0 +1,187 @@
+//! Structural statistics of a graph, independent of its layout.
+//!
+//! Degrees count edges whose ends both exist, with a self-loop adding two to its node.
+//! Components are weakly connected, and a node belongs to the same component as its
+//! compound parent, since layouts move them together. Cycles are found by a
+//! depth-first search in node ID order, so `back_edges` is the size of that search's
+//! feedback edge set rather than the smallest one.
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::types::{Graph, Id};
+
+/// Counts, connectivity, degrees and cycles of a graph
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphStats {
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub compound_count: usize,           // Nodes with children
+    pub positioned_count: usize,         // Nodes with a position
+    pub self_loops: usize,
+    pub parallel_edges: usize,           // Edges repeating an earlier edge's source and target
+    pub components: usize,
+    pub largest_component: usize,        // Nodes in the largest component
+    pub isolated_nodes: usize,           // Nodes without edges, parent or children
+    pub min_degree: usize,
+    pub max_degree: usize,
+    pub mean_degree: f64,
+    pub max_in_degree: usize,
+    pub max_out_degree: usize,
+    pub is_acyclic: bool,                // No directed cycles, self-loops included
+    pub back_edges: usize,               // Edges closing a cycle in the depth-first search, self-loops excluded
+    pub cycle: Vec<Id>,                  // Nodes of the first cycle found, in edge order; empty when acyclic
+}
+
+/// Union-find root of a node, halving paths on the way
+fn find(roots: &mut [usize], mut node: usize) -> usize {
+    while roots[node] != node {
+        roots[node] = roots[roots[node]];
+        node = roots[node];
+    }
+    node
+}
+
+/// Compute the structural statistics of a graph
+pub fn compute_graph_stats(graph: &Graph) -> GraphStats {
+    let ids: Vec<&Id> = graph.sorted_node_ids();
+    let index: HashMap<&Id, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let n = ids.len();
+    let mut stats = GraphStats { node_count: n, edge_count: graph.edges.len(), is_acyclic: true, ..GraphStats::default() };
+    if n == 0 {
+        return stats;
+    }
+
+    let mut out: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let (mut in_degree, mut out_degree) = (vec![0; n], vec![0; n]);
+    let mut seen = HashSet::new();
+    for edge in graph.sorted_edges() {
+        let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) else { continue };
+        out_degree[source] += 1;
+        in_degree[target] += 1;
+        out[source].push(target);
+        if source == target {
+            stats.self_loops += 1;
+        }
+        if !seen.insert((source, target)) {
+            stats.parallel_edges += 1;
+        }
+    }
+
+    // Weak components over edges and parent links
+    let mut roots: Vec<usize> = (0..n).collect();
+    let mut has_family = vec![false; n];
+    let links = graph.sorted_nodes().into_iter()
+        .filter_map(|(id, node)| Some((index[id], *index.get(node.parent.as_ref()?)?)))
+        .chain(out.iter().enumerate().flat_map(|(source, targets)| targets.iter().map(move |&target| (source, target))))
+        .collect::<Vec<_>>();
+    for &(a, b) in &links {
+        has_family[a] = true;
+        has_family[b] = true;
+        let (a, b) = (find(&mut roots, a), find(&mut roots, b));
+        roots[a] = b;
+    }
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for node in 0..n {
+        *sizes.entry(find(&mut roots, node)).or_default() += 1;
+    }
+    stats.components = sizes.len();
+    stats.largest_component = sizes.values().copied().max().unwrap_or(0);
+
+    let parents: HashSet<&Id> = graph.nodes.values().filter_map(|node| node.parent.as_ref()).collect();
+    stats.compound_count = ids.iter().filter(|id| parents.contains(*id)).count();
+    stats.positioned_count = graph.nodes.values().filter(|node| node.position.is_some()).count();
+    stats.isolated_nodes = (0..n).filter(|&node| !has_family[node]).count();
+
+    let degrees: Vec<usize> = (0..n).map(|node| in_degree[node] + out_degree[node]).collect();
+    stats.min_degree = degrees.iter().copied().min().unwrap_or(0);
+    stats.max_degree = degrees.iter().copied().max().unwrap_or(0);
+    stats.mean_degree = degrees.iter().sum::<usize>() as f64 / n as f64;
+    stats.max_in_degree = in_degree.iter().copied().max().unwrap_or(0);
+    stats.max_out_degree = out_degree.iter().copied().max().unwrap_or(0);
+
+    // Depth-first search; an edge to a node still on the stack closes a cycle
+    let mut state = vec![0u8; n]; // 0: unvisited, 1: on the stack, 2: done
+    for start in 0..n {
+        if state[start] != 0 {
+            continue;
+        }
+        let mut stack = vec![(start, 0)];
+        state[start] = 1;
+        while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+            let Some(&target) = out[node].get(*next) else {
+                state[node] = 2;
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+            match state[target] {
+                0 => {
+                    state[target] = 1;
+                    stack.push((target, 0));
+                }
+                1 => {
+                    stats.is_acyclic = false;
+                    if target != node {
+                        stats.back_edges += 1;
+                    }
+                    if stats.cycle.is_empty() {
+                        let from = stack.iter().position(|&(member, _)| member == target).unwrap_or(0);
+                        stats.cycle = stack[from..].iter().map(|&(member, _)| ids[member].clone()).collect();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Edge, Node};
+
+    #[test]
+    fn test_counts_components_and_degrees() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d", "e", "group", "lone"] {
+            graph.add_node(Node::new(id));
+        }
+        graph.nodes.get_mut("d").unwrap().parent = Some("group".to_string());
+        graph.nodes.get_mut("a").unwrap().position = Some((0.0, 0.0));
+        graph.add_edge(Edge::new("ab", "a", "b"))
+             .add_edge(Edge::new("ab2", "a", "b"))
+             .add_edge(Edge::new("bc", "b", "c"))
+             .add_edge(Edge::new("de", "d", "e"))
+             .add_edge(Edge::new("dangling", "a", "missing"));
+        let stats = compute_graph_stats(&graph);
+
+        assert_eq!((stats.node_count, stats.edge_count, stats.compound_count, stats.positioned_count), (7, 5, 1, 1));
+        assert_eq!((stats.self_loops, stats.parallel_edges), (0, 1));
+        // {a, b, c}, {d, e, group} and {lone}
+        assert_eq!((stats.components, stats.largest_component, stats.isolated_nodes), (3, 3, 1));
+        assert_eq!((stats.min_degree, stats.max_degree, stats.max_in_degree, stats.max_out_degree), (0, 3, 2, 2));
+        assert!((stats.mean_degree - 8.0 / 7.0).abs() < 1e-9);
+        assert!(stats.is_acyclic && stats.cycle.is_empty());
+
+        assert_eq!(compute_graph_stats(&Graph::new()), GraphStats { is_acyclic: true, ..GraphStats::default() });
+    }
+
+    #[test]
+    fn test_cycles() {
+        let mut graph = Graph::new();
+        for id in ["a", "b", "c", "d"] {
+            graph.add_node(Node::new(id));
+        }
+        graph.add_edge(Edge::new("ab", "a", "b"))
+             .add_edge(Edge::new("bc", "b", "c"))
+             .add_edge(Edge::new("ca", "c", "a"))
+             .add_edge(Edge::new("dd", "d", "d"));
+        let stats = compute_graph_stats(&graph);
+
+        assert!(!stats.is_acyclic);
+        assert_eq!((stats.back_edges, stats.self_loops), (1, 1));
+        assert_eq!(stats.cycle, vec!["a", "b", "c"]);
+        assert_eq!(stats.max_degree, 2);
+    }
+}
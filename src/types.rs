@@ -1,11 +1,16 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::layout::algorithms::fcose::FcoseOptions;
+use crate::layout::compound::Hierarchy;
 
 /// Unique identifier for nodes and edges
 pub type Id = String;
 
 /// Key-value pair for metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MetadataValue {
     String(String),
@@ -13,12 +18,66 @@ pub enum MetadataValue {
     Boolean(bool),
 }
 
+/// Outline of a node, used together with its width and height. Polygonal shapes
+/// are laid out by their bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeShape {
+    Rectangle,
+    #[default]
+    Ellipse,
+    Circle,
+    Diamond,
+    Triangle,
+    Hexagon,
+}
+
+impl NodeShape {
+    /// Whether the outline is an ellipse (or circle) rather than a box
+    pub fn is_round(self) -> bool {
+        matches!(self, NodeShape::Ellipse | NodeShape::Circle)
+    }
+
+    /// Distance from the centre of a node of this shape to its boundary along a unit
+    /// direction (polygons are approximated by their bounding box)
+    pub fn boundary_distance(self, size: (f64, f64), direction: (f64, f64)) -> f64 {
+        let (half_width, half_height) = (size.0 / 2.0, size.1 / 2.0);
+        if half_width <= 0.0 || half_height <= 0.0 {
+            return 0.0;
+        }
+        if self.is_round() {
+            1.0 / ((direction.0 / half_width).powi(2) + (direction.1 / half_height).powi(2)).sqrt()
+        } else {
+            let to_side = if direction.0.abs() > 1e-9 { half_width / direction.0.abs() } else { f64::INFINITY };
+            let to_top = if direction.1.abs() > 1e-9 { half_height / direction.1.abs() } else { f64::INFINITY };
+            to_side.min(to_top)
+        }
+    }
+}
+
+impl std::str::FromStr for NodeShape {
+    type Err = String;
+
+    /// Parses a shape name, accepting the common DOT aliases (`box`, `rect`, `oval`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "rectangle" | "rect" | "box" | "square" => Ok(NodeShape::Rectangle),
+            "ellipse" | "oval" => Ok(NodeShape::Ellipse),
+            "circle" => Ok(NodeShape::Circle),
+            "diamond" => Ok(NodeShape::Diamond),
+            "triangle" => Ok(NodeShape::Triangle),
+            "hexagon" => Ok(NodeShape::Hexagon),
+            other => Err(format!("Unsupported node shape: {}", other)),
+        }
+    }
+}
+
 /// Node in the graph
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Node {
     pub id: Id,
     pub position: Option<(f64, f64)>,
-    #[serde(default)]
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub metadata: HashMap<String, MetadataValue>,
     #[serde(default)]
     pub label: String,
@@ -28,6 +87,18 @@ pub struct Node {
     pub pos_x: f64,
     #[serde(rename = "y", default)]
     pub pos_y: f64,
+    #[serde(default)]
+    pub width: Option<f64>,
+    #[serde(default)]
+    pub height: Option<f64>,
+    #[serde(default)]
+    pub shape: NodeShape,
+    /// Compound node this node is nested in
+    #[serde(default)]
+    pub parent: Option<Id>,
+    /// Keep the node at its current position in fCoSE layouts (other layouts ignore it)
+    #[serde(default)]
+    pub locked: bool,
 }
 
 impl Node {
@@ -40,6 +111,11 @@ impl Node {
             r#type: String::new(),
             pos_x: 0.0,
             pos_y: 0.0,
+            width: None,
+            height: None,
+            shape: NodeShape::default(),
+            parent: None,
+            locked: false,
         }
     }
 
@@ -54,6 +130,38 @@ impl Node {
         self.metadata.insert(key.into(), value.into());
         self
     }
+
+    pub fn with_size(mut self, width: f64, height: f64) -> Self {
+        self.width = Some(width);
+        self.height = Some(height);
+        self
+    }
+
+    pub fn with_shape(mut self, shape: NodeShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_parent(mut self, parent: impl Into<Id>) -> Self {
+        self.parent = Some(parent.into());
+        self
+    }
+
+    pub fn with_locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    /// Width and height of the node. A missing dimension falls back to the other one,
+    /// then to `default`; circles use the larger dimension as their diameter.
+    pub fn size_or(&self, default: f64) -> (f64, f64) {
+        let width = self.width.or(self.height).unwrap_or(default);
+        let height = self.height.or(self.width).unwrap_or(default);
+        match self.shape {
+            NodeShape::Circle => (width.max(height), width.max(height)),
+            _ => (width, height),
+        }
+    }
 }
 
 /// Edge in the graph
@@ -63,18 +171,52 @@ pub struct Edge {
     pub id: Id,
     pub source: Id,
     pub target: Id,
-    #[serde(default)]
+    #[serde(default, serialize_with = "serialize_sorted")]
     pub metadata: HashMap<String, MetadataValue>,
     #[serde(default)]
     pub r#type: String,
     #[serde(default = "default_weight")]
     pub weight: f64,
+    /// Minimum number of ranks the edge spans in layered layouts
+    #[serde(default = "default_minlen")]
+    pub minlen: u32,
+    /// Intermediate points of the edge from source to target, set by layered layouts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bend_points: Vec<(f64, f64)>,
+    /// Path to draw the edge along, set by layouts according to `edge_routing`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<EdgeRoute>,
+}
+
+/// How the points of an `EdgeRoute` are joined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteStyle {
+    /// One straight line between the two points
+    Straight,
+    /// Straight segments through all points
+    Polyline,
+    /// Straight segments that are all horizontal or vertical
+    Orthogonal,
+    /// Cubic Bézier curves: a start point followed by (control, control, end) triples
+    Spline,
+}
+
+/// Drawable path of an edge, from the boundary of its source to the boundary of its target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EdgeRoute {
+    pub style: RouteStyle,
+    pub points: Vec<(f64, f64)>,
 }
 
 fn default_weight() -> f64 {
     1.0
 }
 
+fn default_minlen() -> u32 {
+    1
+}
+
 fn generate_edge_id() -> String {
     use std::sync::atomic::{AtomicUsize, Ordering};
     static COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -90,9 +232,22 @@ impl Edge {
             metadata: HashMap::new(),
             r#type: String::new(),
             weight: 1.0,
+            minlen: 1,
+            bend_points: Vec::new(),
+            route: None,
         }
     }
 
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_minlen(mut self, minlen: u32) -> Self {
+        self.minlen = minlen;
+        self
+    }
+
     pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
         self.metadata.insert(key.into(), value.into());
         self
@@ -102,10 +257,17 @@ impl Edge {
 /// Complete graph structure
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Graph {
+    #[serde(serialize_with = "serialize_sorted")]
     pub nodes: HashMap<Id, Node>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub edges: HashMap<Id, Edge>,
 }
 
+/// Serialize a map in key order so the same graph always produces the same JSON
+fn serialize_sorted<S: Serializer, V: Serialize>(map: &HashMap<String, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 impl Graph {
     pub fn new() -> Self {
         Self {
@@ -141,6 +303,48 @@ impl Graph {
     pub fn remove_edge(&mut self, id: &Id) -> Option<Edge> {
         self.edges.remove(id)
     }
+
+    /// Drop the bend points and routes of every edge, so that edges are drawn straight
+    pub fn clear_bend_points(&mut self) {
+        for edge in self.edges.values_mut() {
+            edge.bend_points.clear();
+            edge.route = None;
+        }
+    }
+
+    /// Check that every `parent` refers to an existing node and that the parent
+    /// links form a forest (no cycles)
+    pub fn validate_hierarchy(&self) -> Result<(), String> {
+        Hierarchy::new(self).map(|_| ())
+    }
+
+    /// Node IDs in ascending order, independent of `HashMap` iteration order
+    pub fn sorted_node_ids(&self) -> Vec<&Id> {
+        let mut ids: Vec<&Id> = self.nodes.keys().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Nodes in ascending ID order, for engines that index nodes by position
+    pub fn sorted_nodes(&self) -> Vec<(&Id, &Node)> {
+        let mut nodes: Vec<(&Id, &Node)> = self.nodes.iter().collect();
+        nodes.sort_by(|a, b| a.0.cmp(b.0));
+        nodes
+    }
+
+    /// Mutable counterpart of [`Graph::sorted_nodes`], in the same order
+    pub fn sorted_nodes_mut(&mut self) -> Vec<(&Id, &mut Node)> {
+        let mut nodes: Vec<(&Id, &mut Node)> = self.nodes.iter_mut().collect();
+        nodes.sort_by(|a, b| a.0.cmp(b.0));
+        nodes
+    }
+
+    /// Edges in ascending ID order
+    pub fn sorted_edges(&self) -> Vec<&Edge> {
+        let mut edges: Vec<&Edge> = self.edges.values().collect();
+        edges.sort_by(|a, b| a.id.cmp(&b.id));
+        edges
+    }
 }
 
 /// Helper struct for deserializing graph JSON files
@@ -174,14 +378,33 @@ impl From<GraphFile> for Graph {
 
 /// Base layout configuration options
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LayoutOptions {
     pub padding: u32,
+    pub seed: Option<u64>,       // Fixed seed for reproducible layouts; random when unset
+    pub compound_padding: f64,   // Space between a compound node's border and its children
+    pub edge_routing: Option<String>,  // "STRAIGHT", "POLYLINE", "ORTHOGONAL" or "SPLINES"; the engine's own when unset
+    pub merge_edges: bool,       // Lay out and draw edges with the same source and target as one
 }
 
 impl Default for LayoutOptions {
     fn default() -> Self {
         Self {
             padding: 30,
+            seed: None,
+            compound_padding: 10.0,
+            edge_routing: None,
+            merge_edges: false,
+        }
+    }
+}
+
+impl LayoutOptions {
+    /// Random number generator for a layout run, seeded from `seed` when set
+    pub fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
         }
     }
 }
@@ -216,3 +439,242 @@ impl From<bool> for MetadataValue {
         MetadataValue::Boolean(value)
     }
 }
+
+/// Dagre layout options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DagreLayoutOptions {
+    pub base: LayoutOptions,
+    pub ranker: String,          // "network-simplex", "tight-tree" or "longest-path"
+    pub rank_direction: String,  // "TB", "BT", "LR" or "RL"
+    pub rank_separation: f64,
+    pub node_separation: f64,
+    pub acyclic: bool,
+    pub cross_minimization: String,  // "LAYER_SWEEP" or "INTERACTIVE" (keep the current order)
+    pub node_placement: String,  // "BRANDES_KOEPF", "LINEAR_SEGMENTS" or "SIMPLE"
+}
+
+impl Default for DagreLayoutOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            ranker: "network-simplex".to_string(),
+            rank_direction: "TB".to_string(),
+            rank_separation: 50.0,
+            node_separation: 50.0,
+            acyclic: true,
+            cross_minimization: "LAYER_SWEEP".to_string(),
+            node_placement: "BRANDES_KOEPF".to_string(),
+        }
+    }
+}
+
+/// KLay Layered layout options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KlayLayeredLayoutOptions {
+    pub base: LayoutOptions,
+    pub layer_spacing: f64,  // Space between layers
+    pub node_spacing: f64,   // Space between nodes in same layer
+    pub cross_minimization: String,  // "LAYER_SWEEP" or "INTERACTIVE" (keep the current order)
+    pub node_placement: String,  // "BRANDES_KOEPF", "LINEAR_SEGMENTS" or "SIMPLE"
+}
+
+impl Default for KlayLayeredLayoutOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            layer_spacing: 50.0,
+            node_spacing: 20.0,
+            cross_minimization: "LAYER_SWEEP".to_string(),
+            node_placement: "BRANDES_KOEPF".to_string(),
+        }
+    }
+}
+
+/// CiSE layout options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CiseLayoutOptions {
+    pub base: LayoutOptions,
+    pub clusters: Vec<Vec<Id>>,  // Node IDs grouped by cluster
+    pub circle_spacing: f64,     // Space between cluster circles
+}
+
+impl Default for CiseLayoutOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            clusters: Vec::new(),
+            circle_spacing: 20.0,
+        }
+    }
+}
+
+/// Concentric layout options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcentricLayoutOptions {
+    pub base: LayoutOptions,
+    pub concentric_by: String,  // "degree" or "id"
+    pub level_width: f64,       // Radius increment between levels
+}
+
+impl Default for ConcentricLayoutOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            concentric_by: "degree".to_string(),
+            level_width: 100.0,
+        }
+    }
+}
+
+/// CoSE Bilkent layout options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoseBilkentLayoutOptions {
+    pub base: LayoutOptions,
+    pub node_repulsion: f64,
+    pub ideal_edge_length: f64,
+    pub theta: f64,              // Barnes-Hut accuracy; 0 computes exact all-pairs repulsion
+    pub compound_gravity: f64,   // Pull of compound nodes on their children
+}
+
+impl Default for CoseBilkentLayoutOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            node_repulsion: 4500.0,
+            ideal_edge_length: 50.0,
+            theta: 0.8,
+            compound_gravity: 0.1,
+        }
+    }
+}
+
+/// Multi-level force-directed layout options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultilevelLayoutOptions {
+    pub base: LayoutOptions,
+    pub ideal_edge_length: f64,          // Natural spring length at the finest level
+    pub coarsening_threshold: usize,     // Stop coarsening once a level has this many nodes
+    pub min_coarsening_ratio: f64,       // Stop coarsening when a level shrinks less than this
+    pub repulsive_constant: f64,         // Walshaw's C; larger values spread nodes out
+    pub theta: f64,                      // Barnes-Hut accuracy; 0 computes exact all-pairs repulsion
+    pub initial_temperature: f64,        // Initial maximum displacement, in ideal edge lengths
+    pub cooling_factor: f64,
+    pub tolerance: f64,                  // Converged when displacements fall below tolerance * k
+    pub max_iterations: usize,           // Refinement iterations per level
+    pub coarsest_layout_iterations: usize,
+}
+
+impl Default for MultilevelLayoutOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            ideal_edge_length: 50.0,
+            coarsening_threshold: 20,
+            min_coarsening_ratio: 0.95,
+            repulsive_constant: 0.2,
+            theta: 0.8,
+            initial_temperature: 2.0,
+            cooling_factor: 0.9,
+            tolerance: 0.01,
+            max_iterations: 50,
+            coarsest_layout_iterations: 200,
+        }
+    }
+}
+
+/// SVG export options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgOptions {
+    pub base: LayoutOptions,             // `padding` is the margin around the drawing, in pixels
+    pub scale: f64,                      // Pixels per layout unit
+    pub default_node_size: f64,          // Size of nodes without a width and height
+    pub font_size: f64,
+    pub show_labels: bool,               // Draw node labels (the ID when a node has no label)
+    pub node_style: SvgNodeStyle,        // Style of nodes whose type has no entry in `type_styles`
+    pub type_styles: HashMap<String, SvgNodeStyle>,  // Node styles by `Node.type`
+    pub edge_color: String,
+    pub edge_width: f64,
+    pub arrows: bool,                    // Arrowheads at edge targets
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            base: LayoutOptions::default(),
+            scale: 1.0,
+            default_node_size: 20.0,
+            font_size: 12.0,
+            show_labels: true,
+            node_style: SvgNodeStyle::default(),
+            type_styles: HashMap::new(),
+            edge_color: "#666666".to_string(),
+            edge_width: 1.0,
+            arrows: true,
+        }
+    }
+}
+
+/// Fill, outline and label colour of a node in SVG output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgNodeStyle {
+    pub fill: String,
+    pub stroke: String,
+    pub stroke_width: f64,
+    pub label_color: String,
+}
+
+impl Default for SvgNodeStyle {
+    fn default() -> Self {
+        Self {
+            fill: "#dbe8f5".to_string(),
+            stroke: "#35608c".to_string(),
+            stroke_width: 1.0,
+            label_color: "#1a1a1a".to_string(),
+        }
+    }
+}
+
+/// Layout algorithm selection, tagged by name in JSON (e.g. `{"name": "dagre", ...}`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "kebab-case")]
+pub enum LayoutAlgorithm {
+    Fcose(FcoseOptions),
+    CoseBilkent(CoseBilkentLayoutOptions),
+    Cise(CiseLayoutOptions),
+    Concentric(ConcentricLayoutOptions),
+    KlayLayered(KlayLayeredLayoutOptions),
+    Dagre(DagreLayoutOptions),
+    Multilevel(MultilevelLayoutOptions),
+}
+
+impl LayoutAlgorithm {
+    /// The `name` tag used for this algorithm in JSON
+    pub fn id(&self) -> &'static str {
+        match self {
+            LayoutAlgorithm::Fcose(_) => "fcose",
+            LayoutAlgorithm::CoseBilkent(_) => "cose-bilkent",
+            LayoutAlgorithm::Cise(_) => "cise",
+            LayoutAlgorithm::Concentric(_) => "concentric",
+            LayoutAlgorithm::KlayLayered(_) => "klay-layered",
+            LayoutAlgorithm::Dagre(_) => "dagre",
+            LayoutAlgorithm::Multilevel(_) => "multilevel",
+        }
+    }
+}
+
+/// Progress of one iteration of a step-by-step layout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LayoutTick {
+    pub iteration: usize,                // Iterations run so far, this one included
+    pub energy: f64,                     // Sum of squared net forces on the nodes; falls as the layout settles
+    pub temperature: f64,                // The engine's cooling schedule (CoSE Bilkent does not cool and reports 1)
+    pub max_displacement: f64,           // Largest step given to a node in this iteration
+}